@@ -126,6 +126,12 @@ pub enum Error {
     #[error("suibase: Could not read link file `{path:?}`")]
     WorkdirStateLinkReadError { path: String },
 
+    #[error("suibase: Could not connect to the suibase-daemon API at `{path:?}`. Is the daemon running?")]
+    DaemonApiNotAvailable { path: String },
+
+    #[error("suibase: Request to the suibase-daemon API failed: {info}")]
+    DaemonApiRequestFailed { info: String },
+
     /*****************************/
     // Suibase internal errors
     // Likely a bug in  code.
@@ -32,6 +32,7 @@
 mod error;
 pub use crate::error::Error;
 
+mod suibase_daemon_api;
 mod suibase_helper_impl;
 mod suibase_root;
 mod suibase_workdir;
@@ -86,8 +87,9 @@ impl Helper {
     /// Select an existing workdir by name.
     ///
     /// Possible values are:
-    ///   "active", "cargobin", "localnet", "devnet", "testnet", "mainnet" and
-    ///    other custom names might be supported in future.
+    ///   "active", "cargobin", "localnet", "devnet", "testnet", "mainnet" or
+    ///   the name of a custom workdir (any other directory in ~/suibase/workdirs
+    ///   with a .state/name file).
     ///
     /// Note: "active" is special. It will resolve the active workdir at the moment of the
     ///       call. Example: if "localnet" is the active, then this call is equivalent to
@@ -182,4 +184,27 @@ impl Helper {
     pub fn ws_url(&self) -> Result<String, Error> {
         self.0.lock().unwrap().ws_url()
     }
+
+    /// Get the pathname of the suibase-daemon API Unix domain socket.
+    ///
+    /// Access to the socket is restricted to the user by the filesystem permissions.
+    ///
+    /// Related path: ~/suibase/workdirs/common/.state/api.sock
+    pub fn daemon_api_socket_path(&self) -> Result<String, Error> {
+        self.0.lock().unwrap().daemon_api_socket_path()
+    }
+
+    /// Send a JSON-RPC request to the suibase-daemon (through its Unix domain socket).
+    ///
+    /// Returns the JSON-RPC response as a string (may be a JSON-RPC error).
+    ///
+    /// Example:
+    /// ```
+    /// let resp = sbh.daemon_api_request(
+    ///     r#"{"jsonrpc":"2.0","id":1,"method":"getLinks","params":{"workdir":"localnet"}}"#,
+    /// )?;
+    /// ```
+    pub fn daemon_api_request(&self, json_request: &str) -> Result<String, Error> {
+        self.0.lock().unwrap().daemon_api_request(json_request)
+    }
 }
@@ -0,0 +1,138 @@
+// Minimal client of the suibase-daemon API through its Unix domain socket.
+//
+// One HTTP/1.1 POST per connection ("Connection: close"), so the response
+// body is everything after the headers (decoded when sent with
+// "Transfer-Encoding: chunked").
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use crate::error::Error;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) fn json_rpc_request(socket_path: &str, json_request: &str) -> Result<String, Error> {
+    let mut stream =
+        UnixStream::connect(socket_path).map_err(|_| Error::DaemonApiNotAvailable {
+            path: socket_path.to_string(),
+        })?;
+
+    let request_failed = |e: std::io::Error| Error::DaemonApiRequestFailed {
+        info: e.to_string(),
+    };
+
+    stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(request_failed)?;
+    stream
+        .set_write_timeout(Some(REQUEST_TIMEOUT))
+        .map_err(request_failed)?;
+
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        json_request.len(),
+        json_request
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(request_failed)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).map_err(request_failed)?;
+
+    parse_http_response(&response)
+}
+
+fn invalid_response(info: &str) -> Error {
+    Error::DaemonApiRequestFailed {
+        info: format!("invalid HTTP response ({})", info),
+    }
+}
+
+fn parse_http_response(response: &[u8]) -> Result<String, Error> {
+    let head_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid_response("no end of headers"))?;
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let body = &response[head_end + 4..];
+
+    // Status line is "HTTP/1.1 200 OK".
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+    if status != "200" {
+        return Err(Error::DaemonApiRequestFailed {
+            info: format!("HTTP status {}", status),
+        });
+    }
+
+    let is_chunked = head.lines().skip(1).any(|line| {
+        line.split_once(':').map_or(false, |(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.to_ascii_lowercase().contains("chunked")
+        })
+    });
+    if is_chunked {
+        let body = decode_chunked_body(body)?;
+        return Ok(String::from_utf8_lossy(&body).to_string());
+    }
+
+    Ok(String::from_utf8_lossy(body).to_string())
+}
+
+// Each chunk is "<size in hex>[;extensions]\r\n<data>\r\n", until a zero size
+// chunk (the trailers that may follow are ignored).
+fn decode_chunked_body(mut body: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| invalid_response("missing chunk size"))?;
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16)
+            .map_err(|_| invalid_response(&format!("invalid chunk size '{}'", size_hex)))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size + 2 || &body[size..size + 2] != b"\r\n" {
+            return Err(invalid_response("truncated chunk"));
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = &body[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_http_response;
+
+    #[test]
+    fn test_parse_http_response() {
+        let response = b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 15\r\n\r\n{\"result\":true}";
+        assert_eq!(parse_http_response(response).unwrap(), "{\"result\":true}");
+
+        let response = b"HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n";
+        assert!(parse_http_response(response).is_err());
+
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_http_response_chunked() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            a\r\n{\"result\":\r\n5;ext=1\r\ntrue}\r\n0\r\n\r\n";
+        assert_eq!(parse_http_response(response).unwrap(), "{\"result\":true}");
+
+        // Truncated or invalid chunks.
+        let response = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\na\r\n{\"res";
+        assert!(parse_http_response(response).is_err());
+        let response = b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\nzz\r\n";
+        assert!(parse_http_response(response).is_err());
+    }
+}
@@ -5,6 +5,7 @@
 use sui_types::base_types::{ObjectID, SuiAddress};
 
 use crate::error::Error;
+use crate::suibase_daemon_api;
 use crate::suibase_root::SuibaseRoot;
 use crate::suibase_workdir::SuibaseWorkdir;
 
@@ -45,8 +46,9 @@ impl SuibaseHelperImpl {
     // Select an existing workdir by name.
     //
     // Possible values are:
-    //   "active", "cargobin", "localnet", "devnet", "testnet", "mainnet" and
-    //    other custom names might be supported in future.
+    //   "active", "cargobin", "localnet", "devnet", "testnet", "mainnet" or
+    //   the name of a custom workdir (any other directory in ~/suibase/workdirs
+    //   with a .state/name file).
     //
     // Note: "active" is special. It will resolve the active workdir at the moment of the
     //       call. Example: if "localnet" is the active, then this call is equivalent to
@@ -159,4 +161,18 @@ impl SuibaseHelperImpl {
             None => Err(Error::WorkdirNotSelected),
         }
     }
+
+    // Get the pathname of the suibase-daemon API Unix domain socket.
+    pub fn daemon_api_socket_path(&mut self) -> Result<String, Error> {
+        if !self.root.is_installed() {
+            return Err(Error::NotInstalled);
+        }
+        Ok(self.root.daemon_api_socket_path().to_string())
+    }
+
+    // Send a JSON-RPC request to the suibase-daemon and return the JSON response.
+    pub fn daemon_api_request(&mut self, json_request: &str) -> Result<String, Error> {
+        let path = self.daemon_api_socket_path()?;
+        suibase_daemon_api::json_rpc_request(&path, json_request)
+    }
 }
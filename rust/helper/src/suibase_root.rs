@@ -12,6 +12,10 @@ pub(crate) struct SuibaseRoot {
     // Absolute path to suibase/workdirs
     // (e.g. /home/johndoe/suibase/workdirs )
     workdirs_path: String,
+
+    // Absolute path to the suibase-daemon API Unix domain socket.
+    // (e.g. /home/johndoe/suibase/workdirs/common/.state/api.sock )
+    daemon_api_socket_path: String,
 }
 
 impl SuibaseRoot {
@@ -21,6 +25,7 @@ impl SuibaseRoot {
             is_installed: false,
             suibase_path: String::new(),
             workdirs_path: String::new(),
+            daemon_api_socket_path: String::new(),
         };
         new_obj.refresh_state();
         new_obj
@@ -40,6 +45,10 @@ impl SuibaseRoot {
         &self.workdirs_path
     }
 
+    pub fn daemon_api_socket_path(self: &SuibaseRoot) -> &str {
+        &self.daemon_api_socket_path
+    }
+
     pub fn refresh_state(self: &mut SuibaseRoot) {
         if let Some(mut path_buf) = home_dir() {
             path_buf.push("suibase");
@@ -47,6 +56,11 @@ impl SuibaseRoot {
 
             path_buf.push("workdirs");
             self.workdirs_path = path_buf.to_string_lossy().to_string();
+
+            path_buf.push("common");
+            path_buf.push(".state");
+            path_buf.push("api.sock");
+            self.daemon_api_socket_path = path_buf.to_string_lossy().to_string();
         }
 
         let base_path_ok = if self.suibase_path.is_empty() {
@@ -79,6 +93,11 @@ mod tests {
         let workdir_path = sb.workdirs_path();
         assert_eq!(workdir_path.is_empty(), false);
         assert_eq!(workdir_path.ends_with("suibase/workdirs"), true);
+        let socket_path = sb.daemon_api_socket_path();
+        assert_eq!(
+            socket_path.ends_with("workdirs/common/.state/api.sock"),
+            true
+        );
     }
 
     #[test]
@@ -4,10 +4,14 @@ use crate::basic_types::*;
 
 use crate::network_monitor::NetMonTx;
 use crate::proxy_server::ProxyServer;
-use crate::shared_types::{Globals, InputPort, WorkdirProxyConfig};
+use crate::shared_types::{
+    ConfigDiagnosticLevel, Globals, GlobalsEventsDataST, GlobalsPackagesConfigST,
+    GlobalsWorkdirConfigST, GlobalsWorkdirStatusST, InputPort, ProxyListenConfig,
+    WorkdirProxyConfig,
+};
 use crate::workdirs_watcher::WorkdirsWatcher;
-use crate::workers::ShellWorker;
 use crate::workers::{EventsWriterWorker, EventsWriterWorkerParams};
+use crate::workers::{ShellWorker, StatusWorker};
 
 use anyhow::Result;
 
@@ -56,6 +60,9 @@ struct WorkdirTracking {
 
     events_writer_worker_tx: Option<AdminControllerTx>,
     events_writer_worker_handle: Option<NestedSubsystem<Box<dyn Error + Send + Sync>>>, // Set when the events_writer_worker is started.
+
+    status_worker_tx: Option<AdminControllerTx>,
+    status_worker_handle: Option<NestedSubsystem<Box<dyn Error + Send + Sync>>>, // Set when the status_worker is started.
 }
 
 impl std::fmt::Debug for WorkdirTracking {
@@ -71,6 +78,7 @@ impl std::fmt::Debug for WorkdirTracking {
 struct InputPortTracking {
     proxy_server_handle: Option<NestedSubsystem<Box<dyn Error + Send + Sync>>>, // Set when the proxy_server is started.
     port_number: u16, // port number used when the proxy_server was started.
+    listen_config: ProxyListenConfig, // Same, for the bind address, TLS and auth.
 }
 
 impl std::fmt::Debug for InputPortTracking {
@@ -78,6 +86,7 @@ impl std::fmt::Debug for InputPortTracking {
         f.debug_struct("WorkdirTracking")
             // NestedSubsystem does not implement Debug
             .field("port_number", &self.port_number)
+            .field("listen_config", &self.listen_config)
             .finish()
     }
 }
@@ -118,7 +127,11 @@ impl std::fmt::Debug for AdminControllerMsg {
 pub type AdminControllerEventID = u8;
 pub const EVENT_NOTIF_CONFIG_FILE_CHANGE: u8 = 1;
 pub const EVENT_DEBUG_PRINT: u8 = 2;
-pub const EVENT_SHELL_EXEC: u8 = 3;
+pub const EVENT_NOTIF_SUI_EVENT: u8 = 4; // Between events workers only (never sent to the AdminController).
+pub const EVENT_NOTIF_SUI_WATCH: u8 = 5; // Between events workers only (never sent to the AdminController).
+pub const EVENT_STATUS_REFRESH: u8 = 6; // workdir_idx is None for all workdirs.
+pub const EVENT_WORKDIR_JOB: u8 = 7; // data_string is the job_id (see globals.jobs).
+pub const EVENT_WORKDIR_JOB_CANCEL: u8 = 8; // data_string is the job_id.
 
 impl AdminController {
     pub fn new(
@@ -140,14 +153,14 @@ impl AdminController {
 
     async fn process_shell_exec_msg(&mut self, msg: AdminControllerMsg, subsys: &SubsystemHandle) {
         // Simply forward to the proper ShellWorker (one worker per workdir).
-        if msg.event_id != EVENT_SHELL_EXEC {
+        if !matches!(msg.event_id, EVENT_WORKDIR_JOB | EVENT_WORKDIR_JOB_CANCEL) {
             log::error!("Unexpected event_id {:?}", msg.event_id);
             // Do nothing. Consume the message.
             return;
         }
 
         if msg.workdir_idx.is_none() {
-            log::error!("EVENT_SHELL_EXEC missing workdir_idx");
+            log::error!("event_id {} missing workdir_idx", msg.event_id);
             return;
         }
         let workdir_idx = msg.workdir_idx.unwrap();
@@ -168,7 +181,7 @@ impl AdminController {
         }
 
         if wd_tracking.shell_worker_tx.is_none() {
-            log::error!("EVENT_SHELL_EXEC missing shell_worker_tx");
+            log::error!("event_id {} missing shell_worker_tx", msg.event_id);
             return;
         }
         let shell_worker_tx = wd_tracking.shell_worker_tx.as_ref().unwrap();
@@ -178,6 +191,71 @@ impl AdminController {
         shell_worker_tx.send(msg).await.unwrap();
     }
 
+    async fn process_status_refresh_msg(
+        &mut self,
+        msg: AdminControllerMsg,
+        subsys: &SubsystemHandle,
+    ) {
+        // Forward to the proper StatusWorker (one worker per workdir).
+        if msg.event_id != EVENT_STATUS_REFRESH {
+            log::error!("Unexpected event_id {:?}", msg.event_id);
+            // Do nothing. Consume the message.
+            return;
+        }
+
+        if let Some(workdir_idx) = msg.workdir_idx {
+            self.forward_to_status_worker(workdir_idx, msg, subsys);
+            return;
+        }
+
+        // Periodic refresh of every workdir for which a config was loaded.
+        let workdir_indexes: Vec<WorkdirIdx> = self
+            .wd_tracking
+            .iter()
+            .filter(|(_, wd_tracking)| wd_tracking.last_read_config.is_some())
+            .map(|(workdir_idx, _)| workdir_idx)
+            .collect();
+        for workdir_idx in workdir_indexes {
+            let mut worker_msg = AdminControllerMsg::new();
+            worker_msg.event_id = EVENT_STATUS_REFRESH;
+            worker_msg.workdir_idx = Some(workdir_idx);
+            self.forward_to_status_worker(workdir_idx, worker_msg, subsys);
+        }
+    }
+
+    fn forward_to_status_worker(
+        &mut self,
+        workdir_idx: WorkdirIdx,
+        msg: AdminControllerMsg,
+        subsys: &SubsystemHandle,
+    ) {
+        let wd_tracking = self.wd_tracking.get_mut(workdir_idx);
+
+        // Instantiate and start the StatusWorker if not already done.
+        if wd_tracking.status_worker_handle.is_none() {
+            let (status_worker_tx, status_worker_rx) = tokio::sync::mpsc::channel(100);
+            wd_tracking.status_worker_tx = Some(status_worker_tx);
+            let status_worker =
+                StatusWorker::new(self.globals.clone(), status_worker_rx, workdir_idx);
+            let nested = subsys.start(SubsystemBuilder::new("status-worker", |a| {
+                status_worker.run(a)
+            }));
+            wd_tracking.status_worker_handle = Some(nested);
+        }
+
+        // Never wait on a busy worker (would delay all other workdirs). Dropping
+        // a periodic refresh is harmless, and an API request just gets no response.
+        if let Some(status_worker_tx) = wd_tracking.status_worker_tx.as_ref() {
+            if let Err(e) = status_worker_tx.try_send(msg) {
+                log::warn!(
+                    "status refresh for workdir_idx={} dropped: {}",
+                    workdir_idx,
+                    e
+                );
+            }
+        }
+    }
+
     async fn process_debug_print_msg(&mut self, msg: AdminControllerMsg) {
         // Send a response to the return channel with the debug print of a few
         // relevant internal states, particularly the configuration tracking.
@@ -246,6 +324,189 @@ impl AdminController {
         }
     }
 
+    // Apply a proxy_port_number change to the InputPort at port_idx.
+    //
+    // A port used by another workdir is not taken (the change remains requested),
+    // unless that other workdir is itself waiting for the port released here (a
+    // swap). Likewise, a workdir waiting for the released port gets it.
+    //
+    // Returns the port_idx of all the InputPorts that changed port.
+    fn apply_port_number(
+        ports: &mut ManagedVec<InputPort>,
+        port_idx: ManagedVecUSize,
+        new_port_number: u16,
+    ) -> Vec<ManagedVecUSize> {
+        let mut changed = Vec::new();
+        let old_port_number = match ports.get(port_idx) {
+            Some(input_port) => input_port.port_number(),
+            None => return changed,
+        };
+
+        // Another workdir already using the new port.
+        let conflict = ports
+            .iter()
+            .find(|(idx, p)| *idx != port_idx && p.port_number() == new_port_number)
+            .map(|(_, p)| (p.workdir_name().to_string(), p.requested_port_number()));
+        if let Some((other_workdir, other_requested_port)) = conflict {
+            if other_requested_port != old_port_number {
+                if let Some(input_port) = ports.get_mut(port_idx) {
+                    let error = format!(
+                        "proxy_port_number {} already used by {} (keeping port {})",
+                        new_port_number, other_workdir, old_port_number
+                    );
+                    log::error!("{} {}", input_port.workdir_name(), error);
+                    input_port.set_requested_port_number(new_port_number);
+                    input_port.set_proxy_server_error(Some(error));
+                }
+                return changed;
+            }
+        }
+
+        if let Some(input_port) = ports.get_mut(port_idx) {
+            input_port.set_port_number(new_port_number);
+            input_port.set_requested_port_number(0);
+            changed.push(port_idx);
+        }
+
+        // Give the released port to the workdir waiting for it (if any).
+        if let Some((waiting_idx, waiting_port)) = ports
+            .iter_mut()
+            .find(|(idx, p)| *idx != port_idx && p.requested_port_number() == old_port_number)
+        {
+            log::info!(
+                "{} proxy_port_number {} now available",
+                waiting_port.workdir_name(),
+                old_port_number
+            );
+            waiting_port.set_port_number(old_port_number);
+            waiting_port.set_requested_port_number(0);
+            waiting_port.set_proxy_server_error(None);
+            changed.push(waiting_idx);
+        }
+        changed
+    }
+
+    // Start the ProxyServer of an InputPort (as needed).
+    //
+    // On a port number change, gracefully shutdown (drain) the ProxyServer of the
+    // old port and start another one. The InputPort and its TargetServer stats are
+    // kept, and so are the NetworkMonitor health checks (they use the InputPort port).
+    //
+    // If the new port is not available, the new ProxyServer keeps trying to bind
+    // it and the failure is visible with getStatus.
+    //
+    // Same for a change of bind address, TLS or auth token.
+    async fn update_proxy_server(
+        &mut self,
+        port_idx: ManagedVecUSize,
+        workdir_idx: WorkdirIdx,
+        workdir_name: &str,
+        port_number: u16,
+        listen_config: ProxyListenConfig,
+        subsys: &SubsystemHandle,
+    ) {
+        let port_tracking = self.port_tracking.get_mut(port_idx);
+
+        if port_tracking.proxy_server_handle.is_some()
+            && (port_number != port_tracking.port_number
+                || listen_config != port_tracking.listen_config)
+        {
+            let message = if port_number != port_tracking.port_number {
+                format!(
+                    "{} proxy port number changed from {} to {}",
+                    workdir_name, port_tracking.port_number, port_number
+                )
+            } else {
+                format!(
+                    "{} proxy listening config changed (restarting proxy server)",
+                    workdir_name
+                )
+            };
+            GlobalsEventsDataST::add_console_event(&self.globals, workdir_idx, message).await;
+            if let Some(handle) = port_tracking.proxy_server_handle.take() {
+                handle.initiate_shutdown();
+            }
+        }
+
+        if port_tracking.proxy_server_handle.is_none() {
+            let proxy_server = ProxyServer::new();
+            let globals = self.globals.proxy.clone();
+            let netmon_tx = self.netmon_tx.clone();
+            let nested = subsys.start(SubsystemBuilder::new("proxy-server", move |a| {
+                proxy_server.run(a, port_idx, globals, netmon_tx)
+            }));
+
+            port_tracking.proxy_server_handle = Some(nested);
+            port_tracking.port_number = port_number;
+            port_tracking.listen_config = listen_config;
+        }
+    }
+
+    // Stop everything running for a custom workdir that was removed by the user.
+    //
+    // The Workdir itself remains in the globals (keeps its WorkdirIdx), so the
+    // workdir can be re-created later and be handled as on first load.
+    async fn remove_workdir_runtime(&mut self, workdir_idx: WorkdirIdx, workdir_name: &str) {
+        let wd_tracking = self.wd_tracking.get_mut(workdir_idx);
+        if wd_tracking.last_read_config.is_none() {
+            // Never loaded (or already removed).
+            return;
+        }
+        log::info!("workdir {} removed", workdir_name);
+
+        for handle in [
+            wd_tracking.shell_worker_handle.take(),
+            wd_tracking.events_writer_worker_handle.take(),
+            wd_tracking.status_worker_handle.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            handle.initiate_shutdown();
+        }
+        *wd_tracking = WorkdirTracking::default();
+
+        let removed_port_idx = {
+            let mut globals_guard = self.globals.proxy.write().await;
+            let globals = &mut *globals_guard;
+            let port_idx = globals
+                .input_ports
+                .iter()
+                .find(|(_, input_port)| input_port.workdir_idx() == workdir_idx)
+                .map(|(port_idx, _)| port_idx);
+            if let Some(port_idx) = port_idx {
+                globals.input_ports.remove(port_idx);
+            }
+            port_idx
+        }; // Release Globals write lock
+
+        if let Some(port_idx) = removed_port_idx {
+            let port_tracking = self.port_tracking.get_mut(port_idx);
+            if let Some(handle) = port_tracking.proxy_server_handle.take() {
+                handle.initiate_shutdown();
+            }
+            *port_tracking = InputPortTracking::default();
+        }
+
+        {
+            let mut globals_guard = self.globals.status.write().await;
+            let globals = &mut *globals_guard;
+            *globals.workdirs.get_mut(workdir_idx) = GlobalsWorkdirStatusST::new();
+        }
+        {
+            let mut globals_guard = self.globals.config.write().await;
+            let globals = &mut *globals_guard;
+            *globals.workdirs.get_mut(workdir_idx) = GlobalsWorkdirConfigST::default();
+        }
+
+        GlobalsEventsDataST::add_console_event(
+            &self.globals,
+            workdir_idx,
+            format!("{} removed", workdir_name),
+        )
+        .await;
+    }
+
     async fn process_config_msg(&mut self, msg: AdminControllerMsg, subsys: &SubsystemHandle) {
         // Detect any config change for one workdir, and apply it to all other runtime components.
 
@@ -265,6 +526,7 @@ impl AdminController {
         let mut workdir_config = WorkdirProxyConfig::new();
         let workdir_idx: u8;
         let workdir_name: String;
+        let is_removed: bool;
         {
             let workdirs_guard = self.globals.workdirs.read().await;
             let workdirs = &*workdirs_guard;
@@ -278,17 +540,29 @@ impl AdminController {
             let (found_workdir_idx, workdir) = workdir_search_result.unwrap();
             workdir_idx = found_workdir_idx;
             workdir_name = workdir.name().to_string();
+            is_removed = workdir.is_custom() && !workdir.path().exists();
+
+            // Values for the ${NAME} references in the links (optional files, needed
+            // before loading the links).
+            let _ =
+                workdir_config.load_secrets_file(&workdirs.secrets_yaml_common().to_string_lossy());
+            let _ =
+                workdir_config.load_secrets_file(&workdir.secrets_yaml_user().to_string_lossy());
 
             // Load the 3 suibase.yaml files. The default, common and user version in order.
-            let try_load = workdir_config
-                .load_and_merge_from_file(&workdir.suibase_yaml_default().to_string_lossy());
-            if try_load.is_err() {
-                log::error!(
-                    "Failed to load default config file {:?}",
-                    workdir.suibase_yaml_default()
-                );
-                // Do nothing. Consume the message.
-                return;
+            //
+            // Custom workdirs have no default (their user suibase.yaml has it all).
+            if !workdir.is_custom() {
+                let try_load = workdir_config
+                    .load_and_merge_from_file(&workdir.suibase_yaml_default().to_string_lossy());
+                if try_load.is_err() {
+                    log::error!(
+                        "Failed to load default config file {:?}",
+                        workdir.suibase_yaml_default()
+                    );
+                    // Do nothing. Consume the message.
+                    return;
+                }
             }
 
             // Optional, so no error if does not exists.
@@ -301,6 +575,12 @@ impl AdminController {
             let _ = workdir_config.load_state_file(&workdir.suibase_state_file().to_string_lossy());
         } // Release Workdirs read lock
 
+        if is_removed {
+            self.remove_workdir_runtime(workdir_idx, &workdir_name)
+                .await;
+            return;
+        }
+
         // Check if workdir_config has changed since last_read_config.
         let wd_tracking = self.wd_tracking.get_mut(workdir_idx);
 
@@ -319,9 +599,74 @@ impl AdminController {
         }
 
         log::info!("cfg notif {}", workdir_name);
+        log::debug!(
+            "{} effective config:\n{}",
+            workdir_name,
+            workdir_config.effective_config_dump()
+        );
+
+        // Log the config problems (only the new ones, to not repeat on every reload).
+        for diagnostic in workdir_config.diagnostics() {
+            let is_new = wd_tracking
+                .last_read_config
+                .as_ref()
+                .map_or(true, |config| !config.diagnostics().contains(&diagnostic));
+            if !is_new {
+                continue;
+            }
+            match diagnostic.level {
+                ConfigDiagnosticLevel::Error => log::error!("{}", diagnostic),
+                ConfigDiagnosticLevel::Warning => log::warn!("{}", diagnostic),
+            }
+        }
+
+        if wd_tracking.last_read_config.is_none() {
+            // First time this workdir is seen (e.g. daemon startup), restore
+            // the packages published and the watches added prior to this process.
+            if let Err(e) =
+                GlobalsPackagesConfigST::load_from_files(&self.globals, workdir_idx).await
+            {
+                log::error!("Failed to load packages config for {}: {}", workdir_name, e);
+            }
+            if let Err(e) = GlobalsEventsDataST::load_watches(&self.globals, workdir_idx).await {
+                log::error!("Failed to load watches for {}: {}", workdir_name, e);
+            }
+        }
+
+        // Report user requested state changes (e.g. "localnet start") on the console.
+        let last_user_request = wd_tracking
+            .last_read_config
+            .as_ref()
+            .and_then(|config| config.user_request().cloned());
+        if let Some(user_request) = workdir_config.user_request() {
+            if last_user_request.as_ref() != Some(user_request) {
+                GlobalsEventsDataST::add_console_event(
+                    &self.globals,
+                    workdir_idx,
+                    format!("{} {} requested", workdir_name, user_request.trim()),
+                )
+                .await;
+            }
+        }
+
+        {
+            // Config used to compute the status of the workdir.
+            let mut globals_guard = self.globals.config.write().await;
+            let globals = &mut *globals_guard;
+            let wd_config = globals.workdirs.get_mut(workdir_idx);
+            wd_config.is_local_network = workdir_config.is_local_network();
+            wd_config.sui_faucet_enabled = workdir_config.is_sui_faucet_enabled();
+            wd_config.sui_faucet_host_ip = workdir_config.sui_faucet_host_ip().to_string();
+            wd_config.sui_faucet_port = workdir_config.sui_faucet_port();
+            wd_config.config_diagnostics = workdir_config.diagnostics();
+            wd_config.effective_config = workdir_config.effective_config_dump();
+        } // Release Globals write lock
 
         // Apply the configuration to the globals.
-        let config_applied: Option<(ManagedVecUSize, u16)> = {
+        //
+        // Identifies the InputPorts (of this workdir and possibly another one on
+        // a port swap) with a ProxyServer to start or restart.
+        let proxy_updates: Vec<(ManagedVecUSize, WorkdirIdx, String, u16, ProxyListenConfig)> = {
             // Get a write lock on the globals.
             let mut globals_guard = self.globals.proxy.write().await;
             let globals = &mut *globals_guard;
@@ -340,58 +685,98 @@ impl AdminController {
             let input_port_search = ports.iter_mut().find(|p| p.1.workdir_idx() == workdir_idx);
 
             // Create the InputPort if does not exists.
-            if let Some((port_idx, input_port)) = input_port_search {
+            let applied_port_idx = if let Some((port_idx, input_port)) = input_port_search {
                 // Modifying an existing InputPort.
                 Self::apply_workdir_config(input_port, &workdir_config);
-                Some((port_idx, input_port.port_number()))
-            } else {
-                // TODO Verify there is no conflicting port assignment.
+                if input_port.listen_config() != workdir_config.proxy_listen_config() {
+                    input_port.set_listen_config(workdir_config.proxy_listen_config().clone());
+                }
 
+                // A port change is applied to the same InputPort (the TargetServer
+                // stats are kept). The ProxyServer is restarted further below.
+                let new_port_number = workdir_config.proxy_port_number();
+                if new_port_number == input_port.port_number() {
+                    input_port.set_requested_port_number(0);
+                    if input_port.is_proxy_server_running() {
+                        // Clear a conflict that was reported for another port.
+                        input_port.set_proxy_server_error(None);
+                    }
+                    vec![port_idx]
+                } else if new_port_number == 0 {
+                    log::warn!(
+                        "{} proxy_port_number removed (keeping port {})",
+                        workdir_name,
+                        input_port.port_number()
+                    );
+                    input_port.set_requested_port_number(0);
+                    vec![port_idx]
+                } else {
+                    let mut changed = Self::apply_port_number(ports, port_idx, new_port_number);
+                    if !changed.contains(&port_idx) {
+                        changed.insert(0, port_idx);
+                    }
+                    changed
+                }
+            } else if workdir_config.proxy_port_number() == 0 {
+                // Can happen for a custom workdir without proxy_port_number.
+                log::warn!(
+                    "{} has no proxy_port_number (no proxy server)",
+                    workdir_name
+                );
+                Vec::new()
+            } else if let Some((_, other_port)) = ports
+                .iter()
+                .find(|(_, p)| p.port_number() == workdir_config.proxy_port_number())
+            {
+                // Another workdir already using the configured port.
+                log::error!(
+                    "{} proxy_port_number {} already used by {} (no proxy server)",
+                    workdir_name,
+                    workdir_config.proxy_port_number(),
+                    other_port.workdir_name()
+                );
+                Vec::new()
+            } else {
                 // No InputPort yet for that workdir... so create it.
                 let mut input_port =
                     InputPort::new(workdir_idx, workdir_name.clone(), &workdir_config);
                 Self::apply_workdir_config(&mut input_port, &workdir_config);
-                let port_number = input_port.port_number();
-                ports
-                    .push(input_port)
-                    .map(|port_idx| (port_idx, port_number))
-            }
+                ports.push(input_port).into_iter().collect()
+            };
+
+            applied_port_idx
+                .into_iter()
+                .filter_map(|port_idx| {
+                    ports.get(port_idx).map(|p| {
+                        (
+                            port_idx,
+                            p.workdir_idx(),
+                            p.workdir_name().to_string(),
+                            p.port_number(),
+                            p.listen_config().clone(),
+                        )
+                    })
+                })
+                .collect()
         }; // Release Globals write lock
 
-        if let Some((port_idx, port_number)) = config_applied {
-            // As needed, start a proxy server for this port.
-            let port_tracking = self.port_tracking.get_mut(port_idx);
-
-            if port_tracking.proxy_server_handle.is_none() {
-                let proxy_server = ProxyServer::new();
-                let globals = self.globals.proxy.clone();
-                let netmon_tx = self.netmon_tx.clone();
-                let nested = subsys.start(SubsystemBuilder::new("proxy-server", move |a| {
-                    proxy_server.run(a, port_idx, globals, netmon_tx)
-                }));
-
-                port_tracking.proxy_server_handle = Some(nested);
-                port_tracking.port_number = port_number;
-            } else {
-                // Monitor a port number change. This is a rare "fundamental" configuration change that
-                // is simpler to handle by exiting the process (and let it be restarted automatically
-                // by its parent suibase script). The alternative would be to stop the TCP listening
-                // thread and coordinate with all other supporting threads only for ONE port and that is
-                // feasible but challenging to get right... particularly if the user does weird stuff like
-                // quickly toggling ports assignment between two workdirs (!!!).
-                if port_number != port_tracking.port_number {
-                    log::info!(
-                        "Port number changed from {} to {}",
-                        port_tracking.port_number,
-                        port_number
-                    );
-                    // Sleep a bit in case of a "restart loop" bug.
-                    tokio::time::sleep(std::time::Duration::from_secs(4)).await;
-                    subsys.request_shutdown();
-                }
-            }
+        // As needed, start (or restart) the proxy servers.
+        for (port_idx, port_workdir_idx, port_workdir_name, port_number, listen_config) in
+            proxy_updates
+        {
+            self.update_proxy_server(
+                port_idx,
+                port_workdir_idx,
+                &port_workdir_name,
+                port_number,
+                listen_config,
+                subsys,
+            )
+            .await;
         }
 
+        let wd_tracking = self.wd_tracking.get_mut(workdir_idx);
+
         // As needed, start an events_writer_worker for this workdir.
         if workdir_config.is_user_request_start()
             && wd_tracking.events_writer_worker_handle.is_none()
@@ -414,6 +799,12 @@ impl AdminController {
 
         // Remember the changes that were applied.
         wd_tracking.last_read_config = Some(workdir_config);
+
+        // Reflect the change in the status right away (e.g. "localnet stop").
+        let mut status_msg = AdminControllerMsg::new();
+        status_msg.event_id = EVENT_STATUS_REFRESH;
+        status_msg.workdir_idx = Some(workdir_idx);
+        self.forward_to_status_worker(workdir_idx, status_msg, subsys);
     }
 
     async fn event_loop(&mut self, subsys: &SubsystemHandle) {
@@ -427,9 +818,12 @@ impl AdminController {
                     EVENT_NOTIF_CONFIG_FILE_CHANGE => {
                         self.process_config_msg(msg, subsys).await;
                     }
-                    EVENT_SHELL_EXEC => {
+                    EVENT_WORKDIR_JOB | EVENT_WORKDIR_JOB_CANCEL => {
                         self.process_shell_exec_msg(msg, subsys).await;
                     }
+                    EVENT_STATUS_REFRESH => {
+                        self.process_status_refresh_msg(msg, subsys).await;
+                    }
                     _ => {
                         log::error!("Unknown event_id {}", msg.event_id);
                     }
@@ -519,3 +913,53 @@ fn test_load_config_from_suibase_default() {
     assert_eq!(link.rpc.as_ref().unwrap(), "http://0.0.0.0:9000");
     assert_eq!(link.ws.as_ref().unwrap(), "ws://0.0.0.0:9000");
 }
+
+#[test]
+fn test_apply_port_number_swap() {
+    let mut ports: ManagedVec<InputPort> = ManagedVec::new();
+    let mut add_port = |workdir_idx: WorkdirIdx, workdir_name: &str, port_number: u16| {
+        let mut input_port = InputPort::new(
+            workdir_idx,
+            workdir_name.to_string(),
+            &WorkdirProxyConfig::new(),
+        );
+        input_port.set_port_number(port_number);
+        ports.push(input_port).unwrap()
+    };
+    let localnet = add_port(0, "localnet", 44340);
+    let devnet = add_port(1, "devnet", 44341);
+    let testnet = add_port(2, "testnet", 44342);
+
+    // First side of the swap: the port is used, so the change remains requested.
+    let changed = AdminController::apply_port_number(&mut ports, localnet, 44341);
+    assert!(changed.is_empty());
+    let input_port = ports.get(localnet).unwrap();
+    assert_eq!(input_port.port_number(), 44340);
+    assert_eq!(input_port.requested_port_number(), 44341);
+    assert!(input_port.proxy_server_error().is_some());
+
+    // Other side of the swap: both ports change.
+    let changed = AdminController::apply_port_number(&mut ports, devnet, 44340);
+    assert_eq!(changed, vec![devnet, localnet]);
+    for (port_idx, port_number) in [(localnet, 44341), (devnet, 44340)] {
+        let input_port = ports.get(port_idx).unwrap();
+        assert_eq!(input_port.port_number(), port_number);
+        assert_eq!(input_port.requested_port_number(), 0);
+        assert!(input_port.proxy_server_error().is_none());
+    }
+
+    // A conflict with a workdir not waiting for the released port is still rejected.
+    let changed = AdminController::apply_port_number(&mut ports, testnet, 44340);
+    assert!(changed.is_empty());
+    assert_eq!(ports.get(testnet).unwrap().port_number(), 44342);
+    assert_eq!(ports.get(devnet).unwrap().port_number(), 44340);
+
+    // ... until that port is released by a move to a free port.
+    let changed = AdminController::apply_port_number(&mut ports, devnet, 44343);
+    assert_eq!(changed, vec![devnet, testnet]);
+    assert_eq!(ports.get(devnet).unwrap().port_number(), 44343);
+    let input_port = ports.get(testnet).unwrap();
+    assert_eq!(input_port.port_number(), 44340);
+    assert_eq!(input_port.requested_port_number(), 0);
+    assert!(input_port.proxy_server_error().is_none());
+}
@@ -0,0 +1,388 @@
+// Access control of the daemon JSON-RPC API (a tower layer in front of jsonrpsee).
+//
+//  - The "Host" must be a loopback name (mitigates DNS rebinding).
+//  - A request from a web page (with an "Origin" header) is accepted only from
+//    the 'api_allowed_origins' of the common suibase.yaml.
+//  - The mutating methods (e.g. postPublish) require "Authorization: Bearer <token>".
+//    The token is created by the daemon in a file readable only by the user
+//    (~/suibase/workdirs/common/.state/api_token). The read-only methods do not
+//    require it, unless 'api_auth_read_only: true'.
+//  - A WebSocket connection always requires the token (any method can be called
+//    once connected).
+//
+// Changes of the common suibase.yaml apply on the next start of the daemon.
+//
+// This layer is for TCP only. The Unix domain socket relies on the filesystem
+// permissions instead (see api_unix_socket.rs).
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use hyper::body::HttpBody;
+use hyper::{header, Body, HeaderMap, Method, Request, Response, StatusCode};
+use tower::{Layer, Service};
+
+use crate::basic_types::constant_time_eq;
+use crate::shared_types::SuibaseYaml;
+
+// Methods that do not change anything. Any other method (including the
+// ones added later) is considered mutating.
+const READ_ONLY_METHODS: [&str; 7] = [
+    "getLinks",
+    "getStatus",
+    "getConfigDiagnostics",
+    "getJob",
+    "getEvents",
+    "getPackagesConfig",
+    "rpc.discover",
+];
+
+// Names for this machine in the "Host" header (the port is ignored).
+const LOOPBACK_HOSTS: [&str; 4] = ["localhost", "127.0.0.1", "[::1]", "0.0.0.0"];
+
+// Same as the jsonrpsee default.
+const MAX_REQUEST_BODY_SIZE: usize = 10 * 1024 * 1024;
+
+// JSON-RPC error code of the rejected requests.
+const ACCESS_DENIED_ERROR_CODE: i32 = -32001;
+
+#[derive(Clone)]
+pub struct ApiAccessPolicy {
+    token: String,
+    allowed_origins: Vec<String>, // A trailing '*' matches any suffix.
+    auth_read_only: bool,         // Require the token for the read-only methods.
+}
+
+impl std::fmt::Debug for ApiAccessPolicy {
+    // Never display the token.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiAccessPolicy")
+            .field("allowed_origins", &self.allowed_origins)
+            .field("auth_read_only", &self.auth_read_only)
+            .finish()
+    }
+}
+
+impl ApiAccessPolicy {
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            // The webviews of the VSCode extension.
+            allowed_origins: vec!["vscode-webview://*".to_string()],
+            auth_read_only: false,
+        }
+    }
+
+    // Apply api_allowed_origins and api_auth_read_only from the common suibase.yaml.
+    //
+    // Problems in the file are reported by the AdminController (getConfigDiagnostics).
+    pub fn load_common_file(&mut self, path: &Path) {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return, // The file is optional.
+        };
+        let mut diagnostics = Vec::new();
+        let path = path.to_string_lossy();
+        if let Ok(yaml) = SuibaseYaml::parse(&contents, &path, true, &mut diagnostics) {
+            if let Some(allowed_origins) = yaml.api_allowed_origins {
+                self.allowed_origins = allowed_origins;
+            }
+            if let Some(auth_read_only) = yaml.api_auth_read_only {
+                self.auth_read_only = auth_read_only;
+            }
+        }
+    }
+
+    // Reuse the token of a previous run (clients may have read it already), unless
+    // the file became readable by others. Otherwise, create a new one.
+    pub fn load_or_create_token(path: &Path) -> Result<String> {
+        if let Ok(metadata) = std::fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 == 0 {
+                let token = std::fs::read_to_string(path)?.trim().to_string();
+                if !token.is_empty() {
+                    return Ok(token);
+                }
+            } else {
+                log::warn!("{} readable by other users (replaced)", path.display());
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let token = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        // Created with 0600 (never readable by others), then renamed.
+        let tmp_path = path.with_extension("tmp");
+        let _ = std::fs::remove_file(&tmp_path);
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        file.write_all(format!("{}\n", token).as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(token)
+    }
+
+    fn is_read_only_method(method: &str) -> bool {
+        READ_ONLY_METHODS.contains(&method)
+    }
+
+    fn is_loopback_host(host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        let name = match host.rsplit_once(':') {
+            Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+            _ => host.as_str(), // No port (e.g. "[::1]").
+        };
+        LOOPBACK_HOSTS.contains(&name)
+    }
+
+    fn is_allowed_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => origin.starts_with(prefix),
+                None => origin == allowed,
+            })
+    }
+
+    fn is_authorized(&self, headers: &HeaderMap) -> bool {
+        let provided = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or("");
+        constant_time_eq(provided.as_bytes(), self.token.as_bytes())
+    }
+
+    // Checks that do not depend on the method. Returns the rejection, if any.
+    fn check_headers(&self, headers: &HeaderMap) -> Option<(StatusCode, String)> {
+        if let Some(host) = headers.get(header::HOST) {
+            let host = host.to_str().unwrap_or_default();
+            if !Self::is_loopback_host(host) {
+                return Some((
+                    StatusCode::FORBIDDEN,
+                    format!("host '{}' not allowed", host),
+                ));
+            }
+        }
+        if let Some(origin) = headers.get(header::ORIGIN) {
+            let origin = origin.to_str().unwrap_or_default();
+            if !self.is_allowed_origin(origin) {
+                return Some((
+                    StatusCode::FORBIDDEN,
+                    format!("origin '{}' not allowed (see api_allowed_origins)", origin),
+                ));
+            }
+        }
+        None
+    }
+
+    // method is None when the body is not a single JSON-RPC request.
+    fn requires_token(&self, method: Option<&str>) -> bool {
+        match method {
+            Some(method) => self.auth_read_only || !Self::is_read_only_method(method),
+            None => true,
+        }
+    }
+}
+
+fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+fn error_response(status: StatusCode, id: serde_json::Value, message: &str) -> Response<Body> {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": { "code": ACCESS_DENIED_ERROR_CODE, "message": message },
+        "id": id,
+    });
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    if status == StatusCode::UNAUTHORIZED {
+        headers.insert(
+            header::WWW_AUTHENTICATE,
+            header::HeaderValue::from_static("Bearer"),
+        );
+    }
+    resp
+}
+
+fn unauthorized_response(id: serde_json::Value) -> Response<Body> {
+    let token_file = "~/suibase/workdirs/common/.state/api_token";
+    error_response(
+        StatusCode::UNAUTHORIZED,
+        id,
+        &format!("missing or invalid bearer token (see {})", token_file),
+    )
+}
+
+pub(super) async fn read_body(mut body: Body) -> Result<Vec<u8>, StatusCode> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+        if bytes.len() + chunk.len() > MAX_REQUEST_BODY_SIZE {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+#[derive(Clone)]
+pub struct ApiAuthLayer {
+    policy: Arc<ApiAccessPolicy>,
+}
+
+impl ApiAuthLayer {
+    pub fn new(policy: ApiAccessPolicy) -> Self {
+        Self {
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl<S> Layer<S> for ApiAuthLayer {
+    type Service = ApiAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiAuth {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiAuth<S> {
+    inner: S,
+    policy: Arc<ApiAccessPolicy>,
+}
+
+impl<S> Service<Request<Body>> for ApiAuth<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Response<Body>, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Use the instance that was polled ready (the clone is not).
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let policy = self.policy.clone();
+
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+
+            if let Some((status, message)) = policy.check_headers(&parts.headers) {
+                log::warn!("API request rejected: {}", message);
+                return Ok(error_response(status, serde_json::Value::Null, &message));
+            }
+
+            // CORS preflight (answered by the CorsLayer).
+            if parts.method == Method::OPTIONS {
+                return inner.call(Request::from_parts(parts, body)).await;
+            }
+
+            if is_websocket_upgrade(&parts.headers) {
+                if !policy.is_authorized(&parts.headers) {
+                    return Ok(unauthorized_response(serde_json::Value::Null));
+                }
+                return inner.call(Request::from_parts(parts, body)).await;
+            }
+
+            // The method is known only from the body (buffered, then given to jsonrpsee).
+            let bytes = match read_body(body).await {
+                Ok(bytes) => bytes,
+                Err(status) => {
+                    let message = status.canonical_reason().unwrap_or_default();
+                    return Ok(error_response(status, serde_json::Value::Null, message));
+                }
+            };
+            let json = serde_json::from_slice::<serde_json::Value>(&bytes).unwrap_or_default();
+            let method = json.get("method").and_then(|method| method.as_str());
+            if policy.requires_token(method) && !policy.is_authorized(&parts.headers) {
+                let id = json.get("id").cloned().unwrap_or_default();
+                return Ok(unauthorized_response(id));
+            }
+
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+
+    #[test]
+    fn test_access_policy() {
+        let policy = ApiAccessPolicy::new("abc123".to_string());
+
+        assert!(!policy.requires_token(Some("getStatus")));
+        assert!(policy.requires_token(Some("postPublish")));
+        assert!(policy.requires_token(Some("someFutureMethod")));
+        assert!(policy.requires_token(None));
+
+        assert!(ApiAccessPolicy::is_loopback_host("localhost:44399"));
+        assert!(ApiAccessPolicy::is_loopback_host("[::1]:44399"));
+        assert!(ApiAccessPolicy::is_loopback_host("[::1]"));
+        assert!(!ApiAccessPolicy::is_loopback_host("attacker.com:44399"));
+
+        assert!(policy.is_allowed_origin("vscode-webview://1234"));
+        assert!(!policy.is_allowed_origin("https://attacker.com"));
+
+        let mut headers = HeaderMap::new();
+        assert!(!policy.is_authorized(&headers));
+        headers.insert(header::AUTHORIZATION, "Bearer abc12".parse().unwrap());
+        assert!(!policy.is_authorized(&headers));
+        headers.insert(header::AUTHORIZATION, "Bearer abc123".parse().unwrap());
+        assert!(policy.is_authorized(&headers));
+        assert!(!format!("{:?}", policy).contains("abc123"));
+    }
+
+    #[test]
+    fn test_token_file() {
+        let temp_dir = TempDir::new("api-token");
+        let path = temp_dir.path().join(".state").join("api_token");
+
+        let token = ApiAccessPolicy::load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(ApiAccessPolicy::load_or_create_token(&path).unwrap(), token);
+
+        // Replaced when readable by others.
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let new_token = ApiAccessPolicy::load_or_create_token(&path).unwrap();
+        assert_ne!(new_token, token);
+    }
+}
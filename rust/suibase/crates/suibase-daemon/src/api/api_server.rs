@@ -7,6 +7,8 @@
 //
 // A JSONRPCServer owns a jsonrpsee Server to handle the JSON-RPC requests.
 // ( https://github.com/paritytech/jsonrpsee )
+//
+// The same methods are also served on a Unix domain socket (see api_unix_socket.rs).
 
 use axum::async_trait;
 
@@ -28,14 +30,22 @@ use crate::api::impl_proxy_api::ProxyApiImpl;
 use super::PackagesApiServer;
 use crate::api::impl_packages_api::PackagesApiImpl;
 
+use super::{
+    open_rpc_document, serve_unix_socket, ApiAccessPolicy, ApiAuthLayer, DISCOVER_METHOD_NAME,
+};
+
 use hyper::Method;
 use jsonrpsee::{
-    core::server::rpc_module::Methods,
+    core::server::rpc_module::{Methods, RpcModule},
     server::{AllowHosts, ServerBuilder},
 };
 use std::net::SocketAddr;
+use std::path::Path;
 use tower_http::cors::{Any, CorsLayer};
 
+// Delay before retrying to serve a transport that failed (e.g. port in use).
+const RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
 #[derive(Clone)]
 pub struct APIServerParams {
     globals: Globals,
@@ -96,26 +106,34 @@ impl Runnable<APIServerParams> for APIServerThread {
 
 impl APIServerThread {
     async fn event_loop(self, _subsys: &SubsystemHandle) -> Result<()> {
-        // Reference:
-        // https://github.com/paritytech/jsonrpsee/blob/master/examples/examples/cors_server.rs
-        let cors = CorsLayer::new()
-            // Allow `POST` when accessing the resource
-            .allow_methods([Method::POST])
-            // Allow requests from any origin
-            .allow_origin(Any)
-            .allow_headers([hyper::header::CONTENT_TYPE]);
-        let middleware = tower::ServiceBuilder::new().layer(cors);
-
-        let builder = ServerBuilder::default()
-            .batch_requests_supported(false)
-            .set_host_filtering(AllowHosts::Any)
-            .set_middleware(middleware);
-
-        // TODO Put here the suibase.yaml proxy_port_number.
-        let server = builder
-            .build(SocketAddr::from(([127, 0, 0, 1], 44399)))
-            .await?;
+        // Access control (see api_auth.rs).
+        let (api_token_file, api_socket_file, suibase_yaml_common) = {
+            let globals_read_guard = self.params.globals.workdirs.read().await;
+            let globals = &*globals_read_guard;
+            (
+                globals.api_token_file().to_path_buf(),
+                globals.api_socket_file().to_path_buf(),
+                globals.suibase_yaml_common().to_path_buf(),
+            )
+        };
+        let token = ApiAccessPolicy::load_or_create_token(&api_token_file)?;
+        let mut policy = ApiAccessPolicy::new(token);
+        policy.load_common_file(&suibase_yaml_common);
+        log::info!("{:?}", policy);
+
+        let all_methods = self.build_methods();
+
+        // Both transports are served until the thread is cancelled. A failure
+        // of one of them is retried without interrupting the other.
+        tokio::select! {
+            _ = Self::unix_socket_loop(&api_socket_file, all_methods.clone()) => {}
+            _ = Self::tcp_loop(policy, all_methods) => {}
+        }
 
+        Ok(())
+    }
+
+    fn build_methods(&self) -> Methods {
         let mut all_methods = Methods::new();
 
         {
@@ -147,19 +165,64 @@ impl APIServerThread {
             }
         }
 
-        let start_result = server.start(all_methods);
-
-        if let Ok(handle) = start_result {
-            //let addr = server.local_addr()?;
-            //log::info!(local_addr =? addr, "JSON-RPC server listening on {addr}");
-            //log::info!("Available JSON-RPC methods : {:?}", module.method_names());
-            // Wait for the server to finish. This will block until
-            // CancelledByShutdown.
-            handle.stopped().await;
-        } else {
-            log::error!("JSONRPSEE failed to start");
+        {
+            // The document is generated once (does not change while running).
+            let mut module = RpcModule::new(open_rpc_document());
+            if let Err(e) = module.register_method(DISCOVER_METHOD_NAME, |_, doc| Ok(doc.clone())) {
+                log::error!("Error registering {}: {}", DISCOVER_METHOD_NAME, e);
+            }
+            if let Err(e) = all_methods.merge(module) {
+                log::error!("Error merging {} method: {}", DISCOVER_METHOD_NAME, e);
+            }
+        }
+
+        all_methods
+    }
+
+    async fn unix_socket_loop(path: &Path, methods: Methods) {
+        loop {
+            if let Err(e) = serve_unix_socket(path, methods.clone()).await {
+                log::error!("unix socket {} failed: {}", path.display(), e);
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
         }
+    }
 
-        Ok(())
+    async fn tcp_loop(policy: ApiAccessPolicy, methods: Methods) {
+        loop {
+            // Reference:
+            // https://github.com/paritytech/jsonrpsee/blob/master/examples/examples/cors_server.rs
+            let cors = CorsLayer::new()
+                // Allow `POST` when accessing the resource
+                .allow_methods([Method::POST])
+                // The origins are filtered by the ApiAuthLayer.
+                .allow_origin(Any)
+                .allow_headers([hyper::header::CONTENT_TYPE, hyper::header::AUTHORIZATION]);
+            let middleware = tower::ServiceBuilder::new()
+                .layer(ApiAuthLayer::new(policy.clone()))
+                .layer(cors);
+
+            let builder = ServerBuilder::default()
+                .batch_requests_supported(false)
+                .set_host_filtering(AllowHosts::Any)
+                .set_middleware(middleware);
+
+            // TODO Put here the suibase.yaml proxy_port_number.
+            match builder
+                .build(SocketAddr::from(([127, 0, 0, 1], 44399)))
+                .await
+            {
+                Ok(server) => match server.start(methods.clone()) {
+                    Ok(handle) => {
+                        // Wait for the server to finish. This will block until
+                        // CancelledByShutdown.
+                        handle.stopped().await;
+                    }
+                    Err(e) => log::error!("JSONRPSEE failed to start: {}", e),
+                },
+                Err(e) => log::error!("JSONRPSEE failed to bind: {}", e),
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
     }
 }
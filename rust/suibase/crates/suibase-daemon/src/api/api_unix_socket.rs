@@ -0,0 +1,174 @@
+// Unix domain socket transport of the daemon API.
+//
+// Same JSON-RPC methods as the TCP server (HTTP POST only, no WebSocket/subscription).
+//
+// The socket is created at ~/suibase/workdirs/common/.state/api.sock with
+// permissions 0600. The filesystem permissions are the access control, so
+// no token is required for the mutating methods (see api_auth.rs).
+//
+// Example:
+//   curl --unix-socket ~/suibase/workdirs/common/.state/api.sock \
+//        -H "Content-Type: application/json" \
+//        -d '{"jsonrpc":"2.0","id":1,"method":"getLinks","params":{"workdir":"localnet"}}' \
+//        http://localhost/
+use std::convert::Infallible;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use jsonrpsee::core::server::rpc_module::Methods;
+use tokio::net::UnixListener;
+
+use super::api_auth::read_body;
+
+const PARSE_ERROR_CODE: i32 = -32700;
+
+// Remove the socket file when the server stops (including cancellation).
+struct SocketFileGuard(PathBuf);
+
+impl Drop for SocketFileGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn bind(path: &Path) -> Result<UnixListener> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // A socket file left by a previous daemon can't be re-used.
+    if let Err(e) = std::fs::remove_file(path) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            return Err(e.into());
+        }
+    }
+
+    // Bind on a temporary name and rename once the permissions are set, so
+    // that the socket is never accessible with the umask permissions.
+    let tmp_path = path.with_extension("sock.tmp");
+    let _ = std::fs::remove_file(&tmp_path);
+    let listener = UnixListener::bind(&tmp_path)?;
+    let renamed = std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))
+        .and_then(|_| std::fs::rename(&tmp_path, path));
+    if let Err(e) = renamed {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+    Ok(listener)
+}
+
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    resp
+}
+
+fn parse_error_response() -> Response<Body> {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "error": { "code": PARSE_ERROR_CODE, "message": "Parse error" },
+        "id": serde_json::Value::Null,
+    });
+    json_response(StatusCode::OK, body.to_string())
+}
+
+async fn handle_request(methods: Methods, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::POST {
+        let mut resp = Response::new(Body::empty());
+        *resp.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return resp;
+    }
+
+    let body = match read_body(req.into_body()).await {
+        Ok(body) => body,
+        Err(status) => {
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = status;
+            return resp;
+        }
+    };
+
+    let request = match String::from_utf8(body) {
+        Ok(request) => request,
+        Err(_) => return parse_error_response(),
+    };
+
+    match methods.raw_json_request(&request).await {
+        Ok((method_response, _subscription_rx)) => {
+            json_response(StatusCode::OK, method_response.result)
+        }
+        Err(_) => parse_error_response(),
+    }
+}
+
+// Serve the API on the socket. Returns only on error (e.g. bind failure).
+pub(crate) async fn serve_unix_socket(path: &Path, methods: Methods) -> Result<()> {
+    let listener = bind(path)?;
+    let _guard = SocketFileGuard(path.to_path_buf());
+    log::info!("listening on {}", path.display());
+
+    // Accept errors (e.g. too many open files) are transient, so they are
+    // retried here instead of stopping the hyper server.
+    let incoming = hyper::server::accept::from_stream(futures::stream::unfold(
+        listener,
+        |listener| async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _addr)) => {
+                        return Some((Ok::<_, std::io::Error>(stream), listener))
+                    }
+                    Err(e) => {
+                        log::warn!("unix socket accept error: {}", e);
+                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                    }
+                }
+            }
+        },
+    ));
+
+    let make_service = make_service_fn(move |_conn| {
+        let methods = methods.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let methods = methods.clone();
+                async move { Ok::<_, Infallible>(handle_request(methods, req).await) }
+            }))
+        }
+    });
+
+    hyper::Server::builder(incoming).serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+
+    #[test]
+    fn test_bind_permissions() {
+        let temp_dir = TempDir::new("api-sock");
+        let path = temp_dir.path().join(".state").join("api.sock");
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // Stale file from a previous daemon is replaced.
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "stale").unwrap();
+
+            let _listener = bind(&path).unwrap();
+            let metadata = std::fs::metadata(&path).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+            assert!(!path.with_extension("sock.tmp").exists());
+        });
+    }
+}
@@ -85,4 +85,107 @@ impl<T: Clone + PartialEq> Versioned<T> {
         header.method_uuid = Some(self.uuid.get_method_uuid());
         header.data_uuid = Some(self.uuid.get_data_uuid());
     }
+
+    // true if the caller already has this version (uuids from its request).
+    pub fn is_same_version(
+        &self,
+        method_uuid: Option<&String>,
+        data_uuid: Option<&String>,
+    ) -> bool {
+        match (method_uuid, data_uuid) {
+            (Some(method_uuid), Some(data_uuid)) => {
+                *data_uuid == self.uuid.get_data_uuid()
+                    && *method_uuid == self.uuid.get_method_uuid()
+            }
+            _ => false,
+        }
+    }
+}
+
+// Long-poll for the methods accepting method_uuid/data_uuid.
+//
+// Instead of responding "no change" right away, the method checks again
+// periodically for up to 'wait_secs' and responds as soon as the data changes.
+pub const LONG_POLL_MAX_WAIT_SECS: u64 = 60;
+const LONG_POLL_CHECK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(100);
+
+pub struct LongPoll {
+    deadline: Option<tokio::time::Instant>,
+}
+
+impl LongPoll {
+    pub fn new(wait_secs: Option<u64>) -> Self {
+        let deadline = wait_secs.filter(|secs| *secs > 0).map(|secs| {
+            tokio::time::Instant::now()
+                + tokio::time::Duration::from_secs(secs.min(LONG_POLL_MAX_WAIT_SECS))
+        });
+        Self { deadline }
+    }
+
+    // Call when there is no change to report. Returns true (after a short
+    // delay) when the caller should check again for a change.
+    pub async fn wait(&self, check_interval: Option<tokio::time::Duration>) -> bool {
+        let deadline = match self.deadline {
+            Some(deadline) => deadline,
+            None => return false,
+        };
+        let now = tokio::time::Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        let check_interval = check_interval.unwrap_or(LONG_POLL_CHECK_INTERVAL);
+        tokio::time::sleep(check_interval.min(deadline - now)).await;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{Duration, Instant};
+
+    #[test]
+    fn test_is_same_version() {
+        let mut versioned = Versioned::new(1);
+        let mut header = Header::default();
+        versioned.init_header_uuids(&mut header);
+        let (method_uuid, data_uuid) = (header.method_uuid.clone(), header.data_uuid.clone());
+        assert!(versioned.is_same_version(method_uuid.as_ref(), data_uuid.as_ref()));
+        assert!(!versioned.is_same_version(None, data_uuid.as_ref()));
+
+        // No change, same version.
+        versioned.set(&1);
+        assert!(versioned.is_same_version(method_uuid.as_ref(), data_uuid.as_ref()));
+
+        versioned.set(&2);
+        assert!(!versioned.is_same_version(method_uuid.as_ref(), data_uuid.as_ref()));
+    }
+
+    // The timing tests use the virtual time of tokio (no dependency on the load of the machine).
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_no_wait() {
+        for wait_secs in [None, Some(0)] {
+            let start = Instant::now();
+            assert!(!LongPoll::new(wait_secs).wait(None).await);
+            assert_eq!(start.elapsed(), Duration::ZERO);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_long_poll_wait_until_deadline() {
+        // Capped to LONG_POLL_MAX_WAIT_SECS.
+        let long_poll = LongPoll::new(Some(3600));
+        let max_deadline = Instant::now() + Duration::from_secs(LONG_POLL_MAX_WAIT_SECS);
+        assert!(long_poll.deadline.unwrap() <= max_deadline);
+
+        // Checks again every interval, and no longer once the deadline is reached.
+        let start = Instant::now();
+        let long_poll = LongPoll::new(Some(1));
+        let mut checks = 0;
+        while long_poll.wait(Some(Duration::from_millis(200))).await {
+            checks += 1;
+        }
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(checks, 5);
+    }
 }
@@ -17,6 +17,7 @@ use std::collections::HashMap;
 // All *successful" JSON responses have a required "Header" field for data versioning.
 //
 use super::def_header::Header;
+use super::open_rpc::{OpenRpcBuilder, Param};
 use jsonrpsee::core::RpcResult;
 use jsonrpsee_proc_macros::rpc;
 
@@ -48,12 +49,18 @@ pub struct LinkStats {
 
     #[serde(skip_serializing_if = "String::is_empty")]
     pub error_info: String, // Sometime more info when DOWN.
+
+    // As configured in suibase.yaml (both default to the link being enabled).
+    pub selectable: bool, // Can be selected for user traffic.
+    pub monitored: bool,  // Health checked by the daemon.
 }
 
 impl LinkStats {
     pub fn new(alias: String) -> Self {
         LinkStats {
             alias,
+            selectable: true,
+            monitored: true,
             ..Default::default()
         }
     }
@@ -225,6 +232,12 @@ pub struct SuiEvents {
     pub timestamp: String,
 }
 
+impl SuiEvents {
+    pub fn new(message: String, timestamp: String) -> Self {
+        Self { message, timestamp }
+    }
+}
+
 #[serde_as]
 #[derive(Clone, Debug, JsonSchema, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -281,15 +294,39 @@ impl Default for SuccessResponse {
 #[serde(rename_all = "camelCase")]
 pub struct SuiObjectInstance {
     object_id: String,
+
+    // Full Move type (e.g. "0x123::my_module::AdminCap").
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object_type: Option<String>,
+
+    // Address of the owner, "shared", "immutable" or "object:<id>".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    owner: Option<String>,
 }
 
 impl SuiObjectInstance {
     pub fn new(object_id: String) -> Self {
-        Self { object_id }
+        Self {
+            object_id,
+            object_type: None,
+            owner: None,
+        }
     }
     pub fn object_id(&self) -> &str {
         &self.object_id
     }
+    pub fn object_type(&self) -> Option<&String> {
+        self.object_type.as_ref()
+    }
+    pub fn set_object_type(&mut self, object_type: String) {
+        self.object_type = Some(object_type);
+    }
+    pub fn owner(&self) -> Option<&String> {
+        self.owner.as_ref()
+    }
+    pub fn set_owner(&mut self, owner: String) {
+        self.owner = Some(owner);
+    }
 }
 
 #[serde_as]
@@ -300,6 +337,18 @@ pub struct PackageInstance {
     pub package_name: String,
     pub package_timestamp: String,
     pub init_objects: Option<Vec<SuiObjectInstance>>,
+
+    // UpgradeCap created (or used for upgrading) by this publication.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrade_cap_id: Option<String>,
+
+    // Package version on the network (1 for the original publication).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+
+    // Package id of the original publication (same as package_id when not an upgrade).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_package_id: Option<String>,
 }
 
 impl PackageInstance {
@@ -309,6 +358,9 @@ impl PackageInstance {
             package_name,
             package_timestamp,
             init_objects: None,
+            upgrade_cap_id: None,
+            version: None,
+            original_package_id: None,
         }
     }
 }
@@ -329,8 +381,22 @@ pub struct MoveConfig {
 
     // Packages previously published (does not include the current).
     // Useful for tracking older package id for debug browsing.
-    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub older_packages: Vec<PackageInstance>,
+
+    // Upgrade lineage of the latest package.
+    //
+    // Tools should use original_package_id for the types (e.g. event filtering)
+    // and the latest package id for calling the functions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_package_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upgrade_cap_id: Option<String>,
+
+    // Package ids from the original publication to the latest upgrade.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub version_chain: Vec<String>,
 }
 
 impl MoveConfig {
@@ -339,6 +405,9 @@ impl MoveConfig {
             path: None,
             latest_package: None,
             older_packages: Vec::new(),
+            original_package_id: None,
+            upgrade_cap_id: None,
+            version_chain: Vec::new(),
         }
     }
 }
@@ -365,7 +434,8 @@ pub struct PackagesConfigResponse {
     // the latest instance.
     //
     // Among the move_configs, there is an additional constraint:
-    //   - The MoveConfig.path must all be distinct.
+    //   - The (MoveConfig.path, package name) must all be distinct (a
+    //     Move.toml may have more than one package).
     //
     #[serde(skip_serializing_if = "Option::is_none")]
     pub move_configs: Option<HashMap<String, MoveConfig>>,
@@ -398,79 +468,410 @@ impl Default for PackagesConfigResponse {
     }
 }
 
-#[rpc(server)]
-pub trait ProxyApi {
-    /// Returns data about all the RPC/Websocket links
-    /// for a given workdir.
-    ///
-    /// By default fetch everything, but can reduce load
-    /// with the options.
-    #[method(name = "getLinks")]
-    async fn get_links(
-        &self,
-        workdir: String,
-        summary: Option<bool>,
-        links: Option<bool>,
-        data: Option<bool>,
-        display: Option<bool>,
-        debug: Option<bool>,
-    ) -> RpcResult<LinksResponse>;
-
-    #[method(name = "fsChange")]
-    async fn fs_change(&self, path: String) -> RpcResult<InfoResponse>;
-}
-
-#[rpc(server)]
-pub trait GeneralApi {
-    #[method(name = "getStatus")]
-    async fn get_status(
-        &self,
-        workdir: String,
-        data: Option<bool>,
-        display: Option<bool>,
-        debug: Option<bool>,
-        method_uuid: Option<String>,
-        data_uuid: Option<String>,
-    ) -> RpcResult<StatusResponse>;
-}
-
-#[rpc(server)]
-pub trait PackagesApi {
-    #[method(name = "getEvents")]
-    async fn get_events(
-        &self,
-        workdir: String,
-        after_ts: Option<String>,
-        last_ts: Option<String>,
-    ) -> RpcResult<SuiEventsResponse>;
-
-    #[method(name = "getPackagesConfig")]
-    async fn get_packages_config(
-        &self,
-        workdir: String,
-        data: Option<bool>,
-        display: Option<bool>,
-        debug: Option<bool>,
-        method_uuid: Option<String>,
-        data_uuid: Option<String>,
-    ) -> RpcResult<PackagesConfigResponse>;
-
-    #[method(name = "prePublish")]
-    async fn pre_publish(
-        &self,
-        workdir: String,
-        move_toml_path: String,
-        package_name: String,
-    ) -> RpcResult<SuccessResponse>;
-
-    #[method(name = "postPublish")]
-    async fn post_publish(
-        &self,
-        workdir: String,
-        move_toml_path: String,
-        package_name: String,
-        package_uuid: String,
-        package_timestamp: String,
-        package_id: String,
-    ) -> RpcResult<SuccessResponse>;
+#[serde_as]
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JobResponse {
+    pub header: Header,
+
+    pub job_id: u64,
+    pub workdir: String,
+    pub command: String,
+
+    // "queued", "running", "succeeded", "failed", "cancelled" or "timed_out"
+    pub state: String,
+
+    // Output lines starting at output_offset, split per stream.
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+
+    // Offset to request for the next output lines (the total number of
+    // lines so far, both streams combined).
+    pub next_output_offset: u64,
+
+    // Absent while running, or when the process was killed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+impl JobResponse {
+    pub fn new() -> Self {
+        Self {
+            header: Header::default(),
+            job_id: 0,
+            workdir: String::new(),
+            command: String::new(),
+            state: String::new(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+            next_output_offset: 0,
+            exit_code: None,
+        }
+    }
+}
+
+impl Default for JobResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiagnosticInfo {
+    pub level: String, // "error" or "warning"
+    pub file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>, // 1-based
+    pub message: String,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, JsonSchema, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiagnosticsResponse {
+    pub header: Header,
+
+    // Problems found in the suibase.yaml files of the workdir (default, common and user).
+    pub diagnostics: Vec<ConfigDiagnosticInfo>,
+
+    // This is the output when the option 'display' is true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+
+    // The effective config (merged from all files) when the option 'debug' is
+    // true. Each value is annotated with the file that did set it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub debug: Option<String>,
+}
+
+impl ConfigDiagnosticsResponse {
+    pub fn new() -> Self {
+        Self {
+            header: Header::default(),
+            diagnostics: Vec::new(),
+            display: None,
+            debug: None,
+        }
+    }
+}
+
+impl Default for ConfigDiagnosticsResponse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Defines a jsonrpsee trait (#[rpc(server)]) and a function adding its methods
+// to the OpenRPC document (see open_rpc.rs), so both are always consistent.
+//
+// The summary of a method is the first paragraph of its doc comment, and the
+// rest is its description.
+//
+// Limitations: the param types are an ident (e.g. String) or an Option of an
+// ident (see ApiParam) and the result type is an ident.
+macro_rules! rpc_api {
+    (pub trait $trait_name:ident => fn $open_rpc_fn:ident { $($body:tt)* }) => {
+        rpc_api!(@munch [$trait_name $open_rpc_fn] [] [] $($body)*);
+    };
+
+    (@munch $names:tt [$($items:tt)*] [$($calls:tt)*]
+        $(#[doc = $doc:tt])*
+        #[method(name = $name:tt)]
+        async fn $fn_name:ident(
+            &self $(, $param:ident: $param_type:ident $(<$param_inner:ident>)?)* $(,)?
+        ) -> RpcResult<$result:ident>;
+        $($rest:tt)*
+    ) => {
+        rpc_api!(@munch $names
+            [
+                $($items)*
+                $(#[doc = $doc])*
+                #[method(name = $name)]
+                async fn $fn_name(
+                    &self $(, $param: $param_type $(<$param_inner>)?)*
+                ) -> RpcResult<$result>;
+            ]
+            [
+                $($calls)*
+                .method::<$result>(
+                    $name,
+                    &[$($doc),*],
+                    vec![$(Param::of::<$param_type $(<$param_inner>)?>(stringify!($param))),*],
+                )
+            ]
+            $($rest)*
+        );
+    };
+
+    (@munch $names:tt [$($items:tt)*] [$($calls:tt)*]
+        $(#[doc = $doc:tt])*
+        #[subscription(name = $name:tt, unsubscribe = $unsubscribe:tt, item = $item:ident $(,)?)]
+        fn $fn_name:ident(
+            &self $(, $param:ident: $param_type:ident $(<$param_inner:ident>)?)* $(,)?
+        );
+        $($rest:tt)*
+    ) => {
+        rpc_api!(@munch $names
+            [
+                $($items)*
+                $(#[doc = $doc])*
+                #[subscription(name = $name, unsubscribe = $unsubscribe, item = $item)]
+                fn $fn_name(
+                    &self $(, $param: $param_type $(<$param_inner>)?)*
+                );
+            ]
+            [
+                $($calls)*
+                .subscription::<$item>(
+                    $name,
+                    $unsubscribe,
+                    &[$($doc),*],
+                    vec![$(Param::of::<$param_type $(<$param_inner>)?>(stringify!($param))),*],
+                )
+            ]
+            $($rest)*
+        );
+    };
+
+    (@munch [$trait_name:ident $open_rpc_fn:ident] [$($items:tt)*] [$($calls:tt)*]) => {
+        #[rpc(server)]
+        pub trait $trait_name {
+            $($items)*
+        }
+
+        // Add the methods of the trait to the OpenRPC document.
+        pub fn $open_rpc_fn(builder: &mut OpenRpcBuilder) {
+            builder $($calls)*;
+        }
+    };
+}
+
+rpc_api! {
+    pub trait ProxyApi => fn proxy_api_open_rpc {
+        /// Returns data about all the RPC/Websocket links
+        /// for a given workdir.
+        ///
+        /// By default fetch everything, but can reduce load
+        /// with the options.
+        ///
+        /// See getStatus for method_uuid, data_uuid and wait_secs.
+        #[method(name = "getLinks")]
+        async fn get_links(
+            &self,
+            workdir: String,
+            summary: Option<bool>,
+            links: Option<bool>,
+            data: Option<bool>,
+            display: Option<bool>,
+            debug: Option<bool>,
+            method_uuid: Option<String>,
+            data_uuid: Option<String>,
+            wait_secs: Option<u64>,
+        ) -> RpcResult<LinksResponse>;
+
+        /// Notify the daemon of a change to the files of a workdir.
+        #[method(name = "fsChange")]
+        async fn fs_change(&self, path: String) -> RpcResult<InfoResponse>;
+    }
+}
+
+rpc_api! {
+    pub trait GeneralApi => fn general_api_open_rpc {
+        /// Returns the status of a workdir.
+        ///
+        /// When method_uuid/data_uuid are the ones of the latest data, only the
+        /// header is returned (meaning "no change").
+        ///
+        /// With wait_secs (max 60), the "no change" response is delayed until the
+        /// data changes or wait_secs expires (long-poll).
+        #[method(name = "getStatus")]
+        async fn get_status(
+            &self,
+            workdir: String,
+            data: Option<bool>,
+            display: Option<bool>,
+            debug: Option<bool>,
+            method_uuid: Option<String>,
+            data_uuid: Option<String>,
+            wait_secs: Option<u64>,
+        ) -> RpcResult<StatusResponse>;
+
+        /// Request a workdir command ("start", "stop", "regen" or "update").
+        ///
+        /// The command is executed in the background (a few at the time per workdir,
+        /// others are queued). Use the returned job_id with getJob to follow its progress.
+        ///
+        /// The job is killed after timeout_secs (default 3600, max 14400).
+        #[method(name = "workdirCommand")]
+        async fn workdir_command(
+            &self,
+            workdir: String,
+            command: String,
+            timeout_secs: Option<u64>,
+        ) -> RpcResult<JobResponse>;
+
+        /// Request to cancel a queued or running job (the process is killed).
+        ///
+        /// The response is the state at the time of the request. The job becomes
+        /// "cancelled" shortly after (use getJob to confirm).
+        #[method(name = "cancelJob")]
+        async fn cancel_job(&self, job_id: u64) -> RpcResult<JobResponse>;
+
+        /// Returns the problems found in the suibase.yaml files of a workdir
+        /// (e.g. invalid values, unknown keys), with their line number.
+        ///
+        /// The bad values are ignored (the rest of the config still applies).
+        ///
+        /// With debug, also returns the effective config and the source file of
+        /// each value.
+        #[method(name = "getConfigDiagnostics")]
+        async fn get_config_diagnostics(
+            &self,
+            workdir: String,
+            display: Option<bool>,
+            debug: Option<bool>,
+        ) -> RpcResult<ConfigDiagnosticsResponse>;
+
+        /// Get the state of a job and its output lines starting at output_offset.
+        ///
+        /// With wait_secs (max 60), the response is delayed until there are more
+        /// output lines, the job completes or wait_secs expires (long-poll).
+        #[method(name = "getJob")]
+        async fn get_job(
+            &self,
+            job_id: u64,
+            output_offset: Option<u64>,
+            wait_secs: Option<u64>,
+        ) -> RpcResult<JobResponse>;
+    }
+}
+
+rpc_api! {
+    pub trait PackagesApi => fn packages_api_open_rpc {
+        /// Get the events stored for a given workdir.
+        ///
+        /// channel is one of "user" (default), "console" or "watch".
+        #[method(name = "getEvents")]
+        async fn get_events(
+            &self,
+            workdir: String,
+            after_ts: Option<String>,
+            last_ts: Option<String>,
+            channel: Option<String>,
+        ) -> RpcResult<SuiEventsResponse>;
+
+        /// Stream the events stored after the subscription for a given workdir.
+        ///
+        /// Same filters as getEvents. Events already stored that match the
+        /// filters are sent first (in one batch). The subscription is closed
+        /// by the daemon once last_ts is exceeded.
+        #[subscription(
+            name = "subscribeEvents",
+            unsubscribe = "unsubscribeEvents",
+            item = SuiEventsResponse
+        )]
+        fn subscribe_events(
+            &self,
+            workdir: String,
+            after_ts: Option<String>,
+            last_ts: Option<String>,
+            channel: Option<String>,
+        );
+
+        /// Watch an address or object for changes. The matches are stored
+        /// in the "watch" events channel.
+        ///
+        /// kind is "address" (any transaction sent by it) or "object" (any
+        /// transaction changing it).
+        ///
+        /// The watches are kept across daemon restarts.
+        ///
+        /// The result is false when there is no change (already watched).
+        #[method(name = "addWatch")]
+        async fn add_watch(
+            &self,
+            workdir: String,
+            kind: String,
+            id: String,
+        ) -> RpcResult<SuccessResponse>;
+
+        /// Stop watching an address or object.
+        ///
+        /// The result is false when there is no change (was not watched).
+        #[method(name = "removeWatch")]
+        async fn remove_watch(
+            &self,
+            workdir: String,
+            kind: String,
+            id: String,
+        ) -> RpcResult<SuccessResponse>;
+
+        /// Returns the Move packages published in a workdir.
+        ///
+        /// See getStatus for method_uuid, data_uuid and wait_secs.
+        #[method(name = "getPackagesConfig")]
+        async fn get_packages_config(
+            &self,
+            workdir: String,
+            data: Option<bool>,
+            display: Option<bool>,
+            debug: Option<bool>,
+            method_uuid: Option<String>,
+            data_uuid: Option<String>,
+            wait_secs: Option<u64>,
+        ) -> RpcResult<PackagesConfigResponse>;
+
+        /// Step done before a package publication.
+        #[method(name = "prePublish")]
+        async fn pre_publish(
+            &self,
+            workdir: String,
+            move_toml_path: String,
+            package_name: String,
+        ) -> RpcResult<SuccessResponse>;
+
+        /// Change the Move.toml location of a MoveConfig (e.g. after moving its directory).
+        ///
+        /// Moves are also detected on the next publication, so this is needed only to
+        /// reflect the change sooner or when the Suibase.toml was not moved along.
+        #[method(name = "relinkMoveConfig")]
+        async fn relink_move_config(
+            &self,
+            workdir: String,
+            package_uuid: String,
+            move_toml_path: String,
+        ) -> RpcResult<SuccessResponse>;
+
+        /// Merge the publication history of a MoveConfig into another.
+        ///
+        /// Merge the publication history of the from_uuid MoveConfig into
+        /// into_uuid. The from_uuid MoveConfig is then deleted (its published-data
+        /// is kept, and is part of into_uuid on the next daemon startup).
+        #[method(name = "mergeMoveConfigs")]
+        async fn merge_move_configs(
+            &self,
+            workdir: String,
+            from_uuid: String,
+            into_uuid: String,
+        ) -> RpcResult<SuccessResponse>;
+
+        /// Step done after a package publication.
+        ///
+        /// The objects created at publication (e.g. AdminCap, shared objects) are
+        /// read from the published-data directory. When not available there, they
+        /// are retrieved from the network with the tx_digest (when provided).
+        #[method(name = "postPublish")]
+        async fn post_publish(
+            &self,
+            workdir: String,
+            move_toml_path: String,
+            package_name: String,
+            package_uuid: String,
+            package_timestamp: String,
+            package_id: String,
+            tx_digest: Option<String>,
+        ) -> RpcResult<SuccessResponse>;
+    }
 }
@@ -1,24 +1,28 @@
-use tokio::sync::Mutex;
-
 use axum::async_trait;
 
 use anyhow::Result;
 
 use jsonrpsee::core::RpcResult;
 
-use crate::admin_controller::{AdminControllerMsg, AdminControllerTx, EVENT_SHELL_EXEC};
+use crate::admin_controller::{
+    AdminControllerMsg, AdminControllerTx, EVENT_STATUS_REFRESH, EVENT_WORKDIR_JOB,
+    EVENT_WORKDIR_JOB_CANCEL,
+};
 use crate::basic_types::WorkdirIdx;
-use crate::shared_types::{Globals, GlobalsWorkdirsST};
-
-use super::{GeneralApiServer, RpcInputError, RpcSuibaseError, StatusResponse, StatusService};
+use crate::shared_types::{
+    Globals, GlobalsWorkdirsST, Job, JOB_DEFAULT_TIMEOUT_SECS, JOB_MAX_TIMEOUT_SECS,
+    WORKDIR_JOB_COMMANDS,
+};
+use crate::workers::StatusBuilder;
 
-use super::def_header::Versioned;
+use super::{
+    ConfigDiagnosticInfo, ConfigDiagnosticsResponse, GeneralApiServer, JobResponse, LongPoll,
+    RpcInputError, RpcSuibaseError, StatusResponse,
+};
 
 pub struct GeneralApiImpl {
     pub globals: Globals,
     pub admctrl_tx: AdminControllerTx,
-    // TODO Change this to be per workdir.
-    get_status_mutex: Mutex<tokio::time::Instant>,
 }
 
 impl GeneralApiImpl {
@@ -26,16 +30,18 @@ impl GeneralApiImpl {
         Self {
             globals,
             admctrl_tx,
-            get_status_mutex: Mutex::new(tokio::time::Instant::now()),
         }
     }
-    async fn shell_exec(&self, workdir_idx: WorkdirIdx, cmd: String) -> Result<String> {
+
+    // Request the StatusWorker of the workdir to refresh the status globals.
+    //
+    // Returns the debug information of the refresh.
+    async fn status_refresh(&self, workdir_idx: WorkdirIdx) -> Result<String> {
         let mut msg = AdminControllerMsg::new();
-        msg.event_id = EVENT_SHELL_EXEC;
+        msg.event_id = EVENT_STATUS_REFRESH;
         let (tx, rx) = tokio::sync::oneshot::channel();
         msg.resp_channel = Some(tx);
         msg.workdir_idx = Some(workdir_idx);
-        msg.data_string = Some(cmd);
         if (self.admctrl_tx.send(msg).await).is_ok() {
             match rx.await {
                 Ok(resp_str) => {
@@ -49,160 +55,27 @@ impl GeneralApiImpl {
         Err(RpcSuibaseError::InternalError("admctrl_tx.send failed".to_string()).into())
     }
 
-    fn remove_ascii_color_code(s: &str) -> String {
-        let mut result = String::new();
-        let mut is_color_code = false;
-        for c in s.chars() {
-            if is_color_code {
-                if c == 'm' {
-                    is_color_code = false;
-                }
-            } else if c == '\x1b' {
-                is_color_code = true;
-            } else {
-                result.push(c);
-            }
-        }
-        result
-    }
-
-    fn convert_status_cmd_resp_to_status_response(
-        &self,
-        cmd: String,
-        workdir_name: String,
-        resp: &mut StatusResponse,
-    ) -> bool {
-        // First line is two words, first should match the workdir name followed by the status word.
-        // If the workdir name does not match, then the resp.status is set to "DOWN" else the status word is stores in resp.status.
-
-        // Iterate every lines of cmd.
-        let mut line_number = 0;
-
-        let cmd = Self::remove_ascii_color_code(&cmd);
-        for line in cmd.lines() {
-            // Ignore lines starting with a "---" divider.
-            if line.trim_start().starts_with("---") {
-                continue;
-            }
-
-            line_number += 1;
-
-            // Split the line into words.
-            let mut words = line.split_whitespace();
-
-            if line_number == 1 {
-                // Get the very first word.
-                if let Some(word) = words.next() {
-                    if word == workdir_name {
-                        // The first word matches the workdir name, so the next word is the status.
-                        if let Some(status) = words.next() {
-                            resp.status = Some(status.to_string());
-                        }
-                    } else {
-                        resp.status = Some("DOWN".to_string());
-                        resp.status_info = Some(format!(
-                            "Missing status in [{}] first word is [{}]",
-                            cmd, word
-                        ));
-                        return false;
-                    }
-                }
-
-                if resp.status.is_none() {
-                    // Something is not right.
-                    resp.status = Some("DOWN".to_string());
-                    resp.status_info = Some(format!("Missing status in {}", cmd));
-                    return false;
-                }
-
-                continue; // Done with parsing first line
-            }
-            // Use first word in words to decide how to parse the remaining words.
-            let first_word = words.next();
-
-            match first_word {
-                Some("localnet") | Some("faucet") | Some("multi-link") | Some("proxy") => {
-                    // Get the 4th word in words.
-                    let mut service_status = words.nth(2).unwrap_or("").to_string();
-
-                    // Validate if service_status is one of substring "OK", "DOWN", "DEGRADED" or "NOT RUNNING"
-                    let status_is_valid = if service_status == "OK"
-                        || service_status == "DOWN"
-                        || service_status == "DEGRADED"
-                    {
-                        true
-                    } else if service_status == "NOT" {
-                        // Special case for two words "NOT RUNNING" status.
-                        let mut ret_value = false;
-                        if let Some(next_word) = words.next() {
-                            if next_word == "RUNNING" {
-                                ret_value = true;
-                                service_status = "NOT RUNNING".to_string();
-                            } else {
-                                service_status = format!("NOT {}", next_word);
-                            }
-                        }
-                        ret_value
-                    } else {
-                        false
-                    };
-                    let service_status = service_status; // Make service_status immutable.
-
-                    if !status_is_valid {
-                        // Something is not right.
-                        resp.status = Some("DOWN".to_string());
-                        resp.status_info = Some(format!(
-                            "Missing [{}] service status in [{}] service_status=[{}]",
-                            first_word.unwrap(),
-                            cmd,
-                            service_status,
-                        ));
-                        return false;
-                    }
-
-                    // Valid service status found, make sure the response has the services array initialized.
-                    if resp.services.is_none() {
-                        resp.services = Some(Vec::new());
-                    }
-
-                    // service label is everything before the ":" on the line.
-                    let service_label = line.split(':').next().unwrap_or("").trim().to_string();
-                    if service_label.is_empty() {
-                        continue;
-                    }
-
-                    // Lookup if the service is already in resp.services. If not then create it, else
-                    // just ignore this line.
-                    let services = resp.services.as_mut().unwrap();
-                    let mut found = false;
-                    for service in services.iter_mut() {
-                        if service.label == service_label {
-                            found = true;
-                            break;
-                        }
-                    }
-                    if !found {
-                        // Add a new service to resp.services.
-                        let mut new_service = StatusService::new(service_label);
-                        new_service.status = Some(service_status);
-                        services.push(new_service);
-                    }
-                }
-                Some("client") => {
-                    // Parse client line.
-                    // TODO
-                }
-                Some("asui") => {
-                    // Parse asui line.
-                    // TODO
-                }
-                _ => {
-                    // Unknown line, so ignore it.
+    // Build a response from the job state and its output lines starting at output_offset.
+    fn job_to_response(method: &str, job: &Job, output_offset: usize) -> JobResponse {
+        let mut resp = JobResponse::new();
+        resp.header.method = method.to_string();
+        resp.header.key = Some(job.job_id.to_string());
+        resp.job_id = job.job_id;
+        resp.workdir = job.workdir.clone();
+        resp.command = job.command.clone();
+        resp.state = job.state.to_string();
+        if output_offset < job.output.len() {
+            for output_line in &job.output[output_offset..] {
+                if output_line.is_stderr {
+                    resp.stderr.push(output_line.line.clone());
+                } else {
+                    resp.stdout.push(output_line.line.clone());
                 }
             }
         }
-
-        true
+        resp.next_output_offset = job.output.len() as u64;
+        resp.exit_code = job.exit_code;
+        resp
     }
 }
 
@@ -216,6 +89,7 @@ impl GeneralApiServer for GeneralApiImpl {
         debug: Option<bool>,
         method_uuid: Option<String>,
         data_uuid: Option<String>,
+        wait_secs: Option<u64>,
     ) -> RpcResult<StatusResponse> {
         // data/display/debug allow variations of how the output
         // is produced (and they may be combined).
@@ -241,134 +115,315 @@ impl GeneralApiServer for GeneralApiImpl {
         resp.header.method = "getStatus".to_string();
         resp.header.key = Some(workdir.clone());
 
-        // TODO: Consider refactoring as follow:
-        //         get_data_if_no_change(request_uuids,resp)
-        //         set_data_when_changed(new_data: T,resp)
+        // The status globals are kept fresh by a StatusWorker in the background.
+        //
+        // A refresh is requested here only when there is nothing yet in the
+        // globals or for debugging (to return how the status was computed).
+        let mut debug_out = String::new();
+        let need_refresh = {
+            let globals_read_guard = self.globals.status.read().await;
+            let globals = &*globals_read_guard;
+            globals
+                .workdirs
+                .get_if_some(workdir_idx)
+                .map_or(true, |globals| globals.ui.is_none())
+        };
+        if need_refresh || debug {
+            debug_out = match self.status_refresh(workdir_idx).await {
+                Ok(debug_out) => debug_out,
+                Err(e) => format!("Error: {e}"),
+            };
+        }
 
-        // Check if GlobalsStatus need to be refresh, if not, then
-        // just return what is already loaded in-memory.
-        //let now = tokio::time::Instant::now();
-        let mut resp_ready = false;
-        let mut force_resp_init = true;
-        {
+        let long_poll = LongPoll::new(wait_secs);
+        loop {
             // Get the globals for the target workdir_idx.
             let globals_read_guard = self.globals.status.read().await;
             let globals = &*globals_read_guard;
-            let globals = globals.workdirs.get_if_some(workdir_idx);
-
-            if let Some(globals) = globals {
-                if let Some(ui) = &globals.ui {
-                    force_resp_init = false;
-                    if globals.last_ui_update.elapsed() < tokio::time::Duration::from_millis(200) {
-                        // There is no need for a refresh, so initialize the response now.
-                        if data && !debug && !display {
-                            // Optimization for when requesting only the JSON output.
-                            // If no change since the specified user Uuids in the request, then
-                            // return an empty response (just echo the Uuids).
-                            if let (Some(method_uuid), Some(data_uuid)) = (method_uuid, data_uuid) {
-                                let globals_data_uuid = ui.get_uuid().get_data_uuid();
-                                if data_uuid == globals_data_uuid {
-                                    let globals_method_uuid = ui.get_uuid().get_method_uuid();
-                                    if method_uuid == globals_method_uuid {
-                                        ui.init_header_uuids(&mut resp.header);
-                                        resp_ready = true;
-                                    }
-                                }
-                            }
-                        }
-
-                        if !resp_ready {
-                            // Respond with the latest version in globals.
-                            resp = ui.get_data().clone();
-                            ui.init_header_uuids(&mut resp.header);
-                            // Remove fields that are not requested.
-                            if !display {
-                                resp.display = None;
-                            }
-                            if !debug {
-                                resp.debug = None;
-                            }
-                            resp_ready = true;
-                        }
-                    }
+            let ui = globals
+                .workdirs
+                .get_if_some(workdir_idx)
+                .and_then(|globals| globals.ui.as_ref());
+
+            let ui = match ui {
+                Some(ui) => ui,
+                None => {
+                    return Err(RpcSuibaseError::InternalError(format!(
+                        "status of {} not available ({})",
+                        workdir, debug_out
+                    ))
+                    .into())
+                }
+            };
+
+            // Optimization for when requesting only the JSON output.
+            // If no change since the specified user Uuids in the request, then
+            // return an empty response (just echo the Uuids).
+            if data
+                && !debug
+                && !display
+                && ui.is_same_version(method_uuid.as_ref(), data_uuid.as_ref())
+            {
+                ui.init_header_uuids(&mut resp.header);
+                drop(globals_read_guard);
+                if long_poll.wait(None).await {
+                    continue;
                 }
+                break;
             }
+
+            // Respond with the latest version in globals.
+            resp = ui.get_data().clone();
+            ui.init_header_uuids(&mut resp.header);
+            break;
         }
 
-        if resp_ready {
-            return Ok(resp);
+        if display {
+            resp.display = Some(StatusBuilder::display(&workdir, &resp));
+        }
+        if debug {
+            resp.debug = Some(debug_out);
         }
 
-        // If reaching here, then the globals may need to be refreshed.
+        Ok(resp)
+    }
+
+    async fn workdir_command(
+        &self,
+        workdir: String,
+        command: String,
+        timeout_secs: Option<u64>,
+    ) -> RpcResult<JobResponse> {
+        let workdir_idx = match GlobalsWorkdirsST::find_workdir_idx_by_name(&self.globals, &workdir)
+            .await
         {
-            // Allow only one API request at the time to modify the Status globals,
-            // Debounce excessive refresh request on short period of time.
-            let mut mutex_guard = self.get_status_mutex.lock().await;
-            let last_refresh = &mut *mutex_guard;
-            if force_resp_init || last_refresh.elapsed() >= tokio::time::Duration::from_millis(50) {
-                // Get an update with a "<workdir> status --json" shell call.
-                // Map it into the resp.
-                let cmd_resp = match self
-                    .shell_exec(workdir_idx, format!("{} status", workdir))
-                    .await
-                {
-                    Ok(cmd_resp) => cmd_resp,
-                    Err(e) => format!("Error: {e}"),
-                };
-
-                // Do not assumes that if shell_exec returns OK that the command was successful.
-                // The command execution may have failed, but the shell_exec itself may have succeeded.
-                // Suibase often includes "Error:" somewhere in the CLI output.
-
-                // Check if a line starts with "Error:" in cmd_resp.
-                let mut is_successful = cmd_resp
-                    .lines()
-                    .all(|line| !line.trim_start().starts_with("Error:"));
-
-                if is_successful {
-                    is_successful = self
-                        .convert_status_cmd_resp_to_status_response(cmd_resp, workdir, &mut resp);
+            Some(workdir_idx) => workdir_idx,
+            None => return Err(RpcInputError::InvalidParams("workdir".to_string(), workdir).into()),
+        };
+
+        if !WORKDIR_JOB_COMMANDS.contains(&command.as_str()) {
+            return Err(RpcInputError::InvalidParams("command".to_string(), command).into());
+        }
+
+        let timeout_secs = timeout_secs.unwrap_or(JOB_DEFAULT_TIMEOUT_SECS);
+        if timeout_secs == 0 || timeout_secs > JOB_MAX_TIMEOUT_SECS {
+            return Err(RpcInputError::InvalidParams(
+                "timeout_secs".to_string(),
+                timeout_secs.to_string(),
+            )
+            .into());
+        }
+
+        // Create the job and get it executed by the ShellWorker of the workdir.
+        let resp = {
+            let mut globals_write_guard = self.globals.jobs.write().await;
+            let globals = &mut *globals_write_guard;
+            let job_id =
+                globals.add_job(workdir_idx, workdir.clone(), command.clone(), timeout_secs);
+            match globals.get(job_id) {
+                Some(job) => Self::job_to_response("workdirCommand", job, 0),
+                None => {
+                    return Err(RpcSuibaseError::InternalError(format!(
+                        "job {} not found after creation",
+                        job_id
+                    ))
+                    .into())
                 }
-                let is_successful = is_successful; // Make is_successful immutable.
-
-                {
-                    // Get the globals for the target workdir_idx.
-                    let mut globals_read_guard = self.globals.status.write().await;
-                    let globals = &mut *globals_read_guard;
-                    let globals = globals.workdirs.get_mut(workdir_idx);
-
-                    if !is_successful {
-                        // Command was not successful, so return a DOWN status but initialize the rest to last known states
-                        // (if available uses the valid data from globals).
-                        if let Some(globals_ui) = &globals.ui {
-                            resp = globals_ui.get_data().clone();
-                        }
-                        // Force the status DOWN.
-                        // At this point, "resp.status_info" should be already set with something useful to debug.
-                        resp.status = Some("DOWN".to_string());
-                    }
+            }
+        };
 
-                    // Update globals with resp if different, and update the Uuid accordingly.
-                    // Also, initialize 'resp' with the same Uuids as stored in global.
-                    if let Some(globals_ui) = &mut globals.ui {
-                        if resp != *globals_ui.get_data() {
-                            globals_ui.set(&resp);
-                        }
-                        globals_ui.init_header_uuids(&mut resp.header);
-                    } else {
-                        let new_versioned_resp = Versioned::new(resp.clone());
-                        new_versioned_resp.init_header_uuids(&mut resp.header);
-                        globals.ui = Some(new_versioned_resp);
-                    }
+        let mut msg = AdminControllerMsg::new();
+        msg.event_id = EVENT_WORKDIR_JOB;
+        msg.workdir_idx = Some(workdir_idx);
+        msg.data_string = Some(resp.job_id.to_string());
+        if self.admctrl_tx.send(msg).await.is_err() {
+            return Err(
+                RpcSuibaseError::InternalError("admctrl_tx.send failed".to_string()).into(),
+            );
+        }
+
+        Ok(resp)
+    }
+
+    async fn get_job(
+        &self,
+        job_id: u64,
+        output_offset: Option<u64>,
+        wait_secs: Option<u64>,
+    ) -> RpcResult<JobResponse> {
+        let output_offset = output_offset.unwrap_or(0) as usize;
+
+        let long_poll = LongPoll::new(wait_secs);
+        let mut timed_out = false;
+        loop {
+            let globals_read_guard = self.globals.jobs.read().await;
+            let globals = &*globals_read_guard;
+            let job = match globals.get(job_id) {
+                Some(job) => job,
+                None => {
+                    return Err(RpcInputError::InvalidParams(
+                        "job_id".to_string(),
+                        job_id.to_string(),
+                    )
+                    .into())
+                }
+            };
+
+            // Wait for new output or for completion (when requested).
+            if !timed_out && output_offset >= job.output.len() && !job.state.is_completed() {
+                drop(globals_read_guard);
+                timed_out = !long_poll.wait(None).await;
+                continue;
+            }
+
+            return Ok(Self::job_to_response("getJob", job, output_offset));
+        }
+    }
 
-                    // Update the timestamps right before releasing the locks.
-                    let now = tokio::time::Instant::now();
-                    globals.last_ui_update = now;
-                    *last_refresh = now;
+    async fn cancel_job(&self, job_id: u64) -> RpcResult<JobResponse> {
+        let (resp, workdir_idx, is_completed) = {
+            let globals_read_guard = self.globals.jobs.read().await;
+            let globals = &*globals_read_guard;
+            match globals.get(job_id) {
+                Some(job) => (
+                    Self::job_to_response("cancelJob", job, job.output.len()),
+                    job.workdir_idx,
+                    job.state.is_completed(),
+                ),
+                None => {
+                    return Err(RpcInputError::InvalidParams(
+                        "job_id".to_string(),
+                        job_id.to_string(),
+                    )
+                    .into())
                 }
             }
+        };
+
+        // Nothing to cancel once completed.
+        if is_completed {
+            return Ok(resp);
+        }
+
+        // The job is cancelled by the ShellWorker of its workdir.
+        let mut msg = AdminControllerMsg::new();
+        msg.event_id = EVENT_WORKDIR_JOB_CANCEL;
+        msg.workdir_idx = Some(workdir_idx);
+        msg.data_string = Some(job_id.to_string());
+        if self.admctrl_tx.send(msg).await.is_err() {
+            return Err(
+                RpcSuibaseError::InternalError("admctrl_tx.send failed".to_string()).into(),
+            );
         }
 
         Ok(resp)
     }
+
+    async fn get_config_diagnostics(
+        &self,
+        workdir: String,
+        display: Option<bool>,
+        debug: Option<bool>,
+    ) -> RpcResult<ConfigDiagnosticsResponse> {
+        let display = display.unwrap_or(false);
+        let debug = debug.unwrap_or(false);
+
+        let workdir_idx = match GlobalsWorkdirsST::find_workdir_idx_by_name(&self.globals, &workdir)
+            .await
+        {
+            Some(workdir_idx) => workdir_idx,
+            None => return Err(RpcInputError::InvalidParams("workdir".to_string(), workdir).into()),
+        };
+
+        let mut resp = ConfigDiagnosticsResponse::new();
+        resp.header.method = "getConfigDiagnostics".to_string();
+        resp.header.key = Some(workdir.clone());
+
+        let mut display_out = String::new();
+        {
+            let globals_read_guard = self.globals.config.read().await;
+            let globals = &*globals_read_guard;
+            if let Some(wd_config) = globals.workdirs.get_if_some(workdir_idx) {
+                for diagnostic in &wd_config.config_diagnostics {
+                    if display {
+                        display_out.push_str(&format!("{}\n", diagnostic));
+                    }
+                    resp.diagnostics.push(ConfigDiagnosticInfo {
+                        level: diagnostic.level.as_str().to_string(),
+                        file: diagnostic.path.clone(),
+                        line: diagnostic.line.map(|line| line as u64),
+                        message: diagnostic.message.clone(),
+                    });
+                }
+                if debug {
+                    resp.debug = Some(wd_config.effective_config.clone());
+                }
+            }
+        }
+
+        if display {
+            if resp.diagnostics.is_empty() {
+                display_out = format!("No problem found in the {} config files\n", workdir);
+            }
+            resp.display = Some(display_out);
+        }
+
+        Ok(resp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_workdir_command_validation() {
+        let globals = Globals::new();
+        let (admctrl_tx, mut admctrl_rx) = tokio::sync::mpsc::channel(10);
+        let api = GeneralApiImpl::new(globals.clone(), admctrl_tx);
+        let workdir = "localnet".to_string();
+
+        let invalid_params = [
+            ("workdir", "notaworkdir", "start", None),
+            ("workdir", "localnet2", "start", None),
+            ("command", "localnet", "delete", None),
+            ("command", "localnet", "start; rm -rf ~", None),
+            ("timeout_secs", "localnet", "start", Some(0)),
+            (
+                "timeout_secs",
+                "localnet",
+                "start",
+                Some(JOB_MAX_TIMEOUT_SECS + 1),
+            ),
+        ];
+        for (param, workdir, command, timeout_secs) in invalid_params {
+            let err = api
+                .workdir_command(workdir.to_string(), command.to_string(), timeout_secs)
+                .await
+                .unwrap_err();
+            assert!(
+                err.to_string().contains(&format!("params {} ", param)),
+                "{}",
+                err
+            );
+        }
+        // Nothing queued nor sent to the AdminController.
+        assert!(admctrl_rx.try_recv().is_err());
+
+        for command in WORKDIR_JOB_COMMANDS {
+            let resp = api
+                .workdir_command(workdir.clone(), command.to_string(), None)
+                .await
+                .unwrap();
+            assert_eq!(resp.workdir, workdir);
+            assert_eq!(resp.command, command);
+            assert_eq!(resp.state, "queued");
+
+            let msg = admctrl_rx.try_recv().unwrap();
+            assert_eq!(msg.event_id, EVENT_WORKDIR_JOB);
+            assert_eq!(msg.data_string, Some(resp.job_id.to_string()));
+            let workdir_idx = GlobalsWorkdirsST::find_workdir_idx_by_name(&globals, &workdir).await;
+            assert_eq!(msg.workdir_idx, workdir_idx);
+        }
+    }
 }
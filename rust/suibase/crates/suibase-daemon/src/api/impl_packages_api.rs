@@ -1,22 +1,29 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::time::SystemTime;
 
 use axum::async_trait;
 
-use jsonrpsee::core::{Error as RpcError, RpcResult};
+use jsonrpsee::core::{error::SubscriptionClosed, Error as RpcError, RpcResult};
+use jsonrpsee::types::SubscriptionResult;
+use jsonrpsee::SubscriptionSink;
 
 use chrono::Utc;
 
 use crate::admin_controller::AdminControllerTx;
 use crate::api::RpcSuibaseError;
-use crate::shared_types::{Globals, GlobalsWorkdirsST};
+use crate::basic_types::WorkdirIdx;
+use crate::proxy_server::ProxyServer;
+use crate::shared_types::{
+    EventsChannel, Globals, GlobalsEventsDataST, GlobalsPackagesConfigST, GlobalsWorkdirsST,
+    SuiEventData, WatchKind, WatchTarget,
+};
 
 use super::{
-    MoveConfig, PackageInstance, PackagesApiServer, PackagesConfigResponse, RpcInputError,
-    SuccessResponse, SuiEventsResponse,
+    MoveConfig, PackagesApiServer, PackagesConfigResponse, RpcInputError, SuccessResponse,
+    SuiEvents, SuiEventsResponse,
 };
 
-use super::def_header::Versioned;
+use super::def_header::{LongPoll, Versioned};
 
 pub struct PackagesApiImpl {
     pub globals: Globals,
@@ -31,6 +38,165 @@ impl PackagesApiImpl {
         }
     }
 
+    // Validate the params shared by getEvents and subscribeEvents.
+    //
+    // Returns the workdir_idx, the parsed timestamps and the channel.
+    async fn validate_events_params(
+        globals: &Globals,
+        workdir: &String,
+        after_ts: Option<String>,
+        last_ts: Option<String>,
+        channel: Option<String>,
+    ) -> Result<(WorkdirIdx, Option<u64>, Option<u64>, EventsChannel), RpcError> {
+        let workdir_idx = match GlobalsWorkdirsST::find_workdir_idx_by_name(globals, workdir).await
+        {
+            Some(workdir_idx) => workdir_idx,
+            None => {
+                return Err(
+                    RpcInputError::InvalidParams("workdir".to_string(), workdir.clone()).into(),
+                )
+            }
+        };
+        let after_ts = Self::parse_ts_param("after_ts", after_ts)?;
+        let last_ts = Self::parse_ts_param("last_ts", last_ts)?;
+        let channel = match channel {
+            None => EventsChannel::User,
+            Some(channel) => match EventsChannel::from_name(&channel) {
+                Some(channel) => channel,
+                None => {
+                    return Err(RpcInputError::InvalidParams("channel".to_string(), channel).into())
+                }
+            },
+        };
+        Ok((workdir_idx, after_ts, last_ts, channel))
+    }
+
+    // Timestamps are milliseconds since epoch (as a string).
+    fn parse_ts_param(name: &str, value: Option<String>) -> Result<Option<u64>, RpcError> {
+        match value {
+            None => Ok(None),
+            Some(value) => match value.parse::<u64>() {
+                Ok(ts) => Ok(Some(ts)),
+                Err(_) => Err(RpcInputError::InvalidParams(name.to_string(), value).into()),
+            },
+        }
+    }
+
+    // Validate the params shared by addWatch and removeWatch.
+    async fn validate_watch_params(
+        &self,
+        workdir: &String,
+        kind: String,
+        id: String,
+    ) -> Result<(WorkdirIdx, WatchTarget), RpcError> {
+        let workdir_idx = match GlobalsWorkdirsST::find_workdir_idx_by_name(&self.globals, workdir)
+            .await
+        {
+            Some(workdir_idx) => workdir_idx,
+            None => {
+                return Err(
+                    RpcInputError::InvalidParams("workdir".to_string(), workdir.clone()).into(),
+                )
+            }
+        };
+        let kind = match WatchKind::from_name(&kind) {
+            Some(kind) => kind,
+            None => return Err(RpcInputError::InvalidParams("kind".to_string(), kind).into()),
+        };
+        match WatchTarget::new(kind, &id) {
+            Some(target) => Ok((workdir_idx, target)),
+            None => Err(RpcInputError::InvalidParams("id".to_string(), id).into()),
+        }
+    }
+
+    fn to_api_event(event: &SuiEventData) -> SuiEvents {
+        SuiEvents::new(event.msg.clone(), event.timestamp.clone())
+    }
+
+    // Returns false if the subscription is closed.
+    fn send_events(sink: &mut SubscriptionSink, workdir: &str, events: Vec<SuiEvents>) -> bool {
+        let mut resp = SuiEventsResponse::new();
+        resp.header.method = "subscribeEvents".to_string();
+        resp.header.key = Some(workdir.to_string());
+        resp.events = Some(events);
+        matches!(sink.send(&resp), Ok(true))
+    }
+
+    // Get the "objectChanges" of a publication, first from the publish-output.json
+    // written by the publish script, else by querying the network with the tx_digest.
+    async fn get_object_changes(
+        &self,
+        workdir_idx: WorkdirIdx,
+        package_name: &str,
+        package_uuid: &str,
+        package_timestamp: &str,
+        tx_digest: Option<&String>,
+    ) -> Option<serde_json::Value> {
+        let workdir_path = {
+            let workdirs_guard = self.globals.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+            workdirs.workdirs.get(workdir_idx)?.path().to_path_buf()
+        };
+
+        let publication_path = workdir_path
+            .join("published-data")
+            .join(package_name)
+            .join(package_uuid)
+            .join(package_timestamp);
+        if let Some(object_changes) =
+            GlobalsPackagesConfigST::read_object_changes(&publication_path)
+        {
+            return Some(object_changes);
+        }
+
+        let tx_digest = tx_digest?;
+        let mut json = self.query_transaction_block(workdir_idx, tx_digest).await?;
+        match json["result"]["objectChanges"].take() {
+            serde_json::Value::Null => None,
+            object_changes => Some(object_changes),
+        }
+    }
+
+    async fn query_transaction_block(
+        &self,
+        workdir_idx: WorkdirIdx,
+        tx_digest: &str,
+    ) -> Option<serde_json::Value> {
+        // Query through the proxy server of this workdir.
+        let (uri, auth_header) = {
+            let globals_read_guard = self.globals.proxy.read().await;
+            let globals = &*globals_read_guard;
+            let input_port = globals.find_input_port_by_workdir_idx(workdir_idx)?;
+            (input_port.local_url(), input_port.local_auth_header())
+        };
+        let body = format!(
+            r#"{{"jsonrpc":"2.0","method":"sui_getTransactionBlock","id":1,"params":["{}",{{"showObjectChanges":true}}]}}"#,
+            tx_digest
+        );
+        let mut req_builder = ProxyServer::local_client().post(uri);
+        if let Some(auth_header) = auth_header {
+            req_builder = req_builder.header(reqwest::header::AUTHORIZATION, auth_header);
+        }
+        let resp = req_builder
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await;
+        match resp {
+            Ok(resp) => match resp.json::<serde_json::Value>().await {
+                Ok(json) => Some(json),
+                Err(e) => {
+                    log::warn!("sui_getTransactionBlock {} invalid JSON: {}", tx_digest, e);
+                    None
+                }
+            },
+            Err(e) => {
+                log::warn!("sui_getTransactionBlock {} failed: {}", tx_digest, e);
+                None
+            }
+        }
+    }
+
     // Utility function to generate hash for the move_toml_path
     // and return it as a string.
     pub fn short_hash(move_toml_path: &str) -> String {
@@ -45,30 +211,177 @@ impl PackagesApiServer for PackagesApiImpl {
     async fn get_events(
         &self,
         workdir: String,
-        _after_ts: Option<String>,
-        _last_ts: Option<String>,
+        after_ts: Option<String>,
+        last_ts: Option<String>,
+        channel: Option<String>,
     ) -> RpcResult<SuiEventsResponse> {
-        // data/display/debug allow variations of how the output
-        // is produced (and they may be combined).
-        //
-        // They all default to false when not specified
-        // with the exception of data defaulting to true when
-        // the other (display and debug) are false.
-        //
-
-        // Verify workdir param is OK and get its corresponding workdir_idx.
-        let _workdir_idx =
-            match GlobalsWorkdirsST::find_workdir_idx_by_name(&self.globals, &workdir).await {
-                Some(workdir_idx) => workdir_idx,
-                None => {
-                    return Err(RpcInputError::InvalidParams("workdir".to_string(), workdir).into())
-                }
-            };
+        // Verify the params and get the corresponding workdir_idx.
+        let (workdir_idx, after_ts, last_ts, channel) =
+            Self::validate_events_params(&self.globals, &workdir, after_ts, last_ts, channel)
+                .await?;
 
         // Initialize some of the header fields of the response.
         let mut resp = SuiEventsResponse::new();
         resp.header.method = "getEvents".to_string();
         resp.header.key = Some(workdir.clone());
+
+        let mut events = Vec::new();
+        {
+            let globals_read_guard = self.globals.events_data.read().await;
+            let globals = &*globals_read_guard;
+            if let Some(globals) = globals.workdirs.get_if_some(workdir_idx) {
+                for event in globals.events(channel).iter() {
+                    if event.is_in_range(after_ts, last_ts) {
+                        events.push(Self::to_api_event(event));
+                    }
+                }
+            }
+        }
+        resp.events = Some(events);
+        Ok(resp)
+    }
+
+    fn subscribe_events(
+        &self,
+        mut sink: SubscriptionSink,
+        workdir: String,
+        after_ts: Option<String>,
+        last_ts: Option<String>,
+        channel: Option<String>,
+    ) -> SubscriptionResult {
+        // Validating the params requires the (async) globals locks, so the
+        // subscription is accepted or rejected from the spawned task.
+        let globals = self.globals.clone();
+        tokio::spawn(async move {
+            let params =
+                Self::validate_events_params(&globals, &workdir, after_ts, last_ts, channel).await;
+            let (workdir_idx, after_ts, last_ts, channel) = match params {
+                Ok(params) => params,
+                Err(e) => {
+                    let _ = sink.reject(e);
+                    return;
+                }
+            };
+
+            // Get the stored events and subscribe for the new ones under the same lock.
+            let (mut events_rx, stored_events) = {
+                let mut globals_write_guard = globals.events_data.write().await;
+                let globals = &mut *globals_write_guard;
+                let globals = globals.workdirs.get_mut(workdir_idx);
+                let stored_events: Vec<SuiEvents> = globals
+                    .events(channel)
+                    .iter()
+                    .filter(|event| event.is_in_range(after_ts, last_ts))
+                    .map(Self::to_api_event)
+                    .collect();
+                (globals.subscribe(), stored_events)
+            };
+
+            if sink.accept().is_err() {
+                return;
+            }
+
+            if !stored_events.is_empty() && !Self::send_events(&mut sink, &workdir, stored_events) {
+                return;
+            }
+
+            loop {
+                tokio::select! {
+                    event = events_rx.recv() => {
+                        match event {
+                            Ok((event_channel, event)) => {
+                                if event_channel != channel {
+                                    continue;
+                                }
+                                if event.is_in_range(after_ts, last_ts) {
+                                    let events = vec![Self::to_api_event(&event)];
+                                    if !Self::send_events(&mut sink, &workdir, events) {
+                                        return;
+                                    }
+                                } else if let (Some(last_ts), Some(ts)) = (last_ts, event.timestamp_ms()) {
+                                    if ts > last_ts {
+                                        // No more events can match.
+                                        sink.close(SubscriptionClosed::Success);
+                                        return;
+                                    }
+                                }
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                                log::warn!("subscribeEvents {} dropped {} events (slow subscriber)", workdir, n);
+                            }
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                                return;
+                            }
+                        }
+                    }
+                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(10)) => {
+                        // Do not keep an idle task for a subscriber that went away.
+                        if sink.is_closed() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    async fn add_watch(
+        &self,
+        workdir: String,
+        kind: String,
+        id: String,
+    ) -> RpcResult<SuccessResponse> {
+        let (workdir_idx, target) = self.validate_watch_params(&workdir, kind, id).await?;
+
+        let mut resp = SuccessResponse::new();
+        resp.header.method = "addWatch".to_string();
+        resp.header.key = Some(workdir);
+        resp.info = Some(target.key());
+
+        // The events workers periodically pick up the change.
+        {
+            let mut globals_write_guard = self.globals.events_data.write().await;
+            let globals = &mut *globals_write_guard;
+            resp.result = globals.workdirs.get_mut(workdir_idx).watches.insert(target);
+        }
+        if resp.result {
+            // Watches are restored on daemon restart.
+            if let Err(e) = GlobalsEventsDataST::save_watches(&self.globals, workdir_idx).await {
+                log::error!("failed to save watches: {}", e);
+            }
+        }
+        Ok(resp)
+    }
+
+    async fn remove_watch(
+        &self,
+        workdir: String,
+        kind: String,
+        id: String,
+    ) -> RpcResult<SuccessResponse> {
+        let (workdir_idx, target) = self.validate_watch_params(&workdir, kind, id).await?;
+
+        let mut resp = SuccessResponse::new();
+        resp.header.method = "removeWatch".to_string();
+        resp.header.key = Some(workdir);
+        resp.info = Some(target.key());
+
+        {
+            let mut globals_write_guard = self.globals.events_data.write().await;
+            let globals = &mut *globals_write_guard;
+            resp.result = globals
+                .workdirs
+                .get_mut(workdir_idx)
+                .watches
+                .remove(&target);
+        }
+        if resp.result {
+            // Watches are restored on daemon restart.
+            if let Err(e) = GlobalsEventsDataST::save_watches(&self.globals, workdir_idx).await {
+                log::error!("failed to save watches: {}", e);
+            }
+        }
         Ok(resp)
     }
 
@@ -114,6 +427,7 @@ impl PackagesApiServer for PackagesApiImpl {
         package_uuid: String,
         package_timestamp: String,
         package_id: String,
+        tx_digest: Option<String>,
     ) -> RpcResult<SuccessResponse> {
         // TODO More parameters validation.
 
@@ -144,6 +458,24 @@ impl PackagesApiServer for PackagesApiImpl {
             return Err(RpcSuibaseError::InternalError(err_msg).into());
         }
 
+        // Done prior to get the globals lock (file and network access).
+        let object_changes = self
+            .get_object_changes(
+                workdir_idx,
+                &package_name,
+                &package_uuid,
+                &package_timestamp,
+                tx_digest.as_ref(),
+            )
+            .await;
+        if object_changes.is_none() {
+            log::warn!(
+                "postPublish {} {}: init objects and upgrade info not available",
+                package_name,
+                package_id
+            );
+        }
+
         // Insert the data in the globals.
         {
             let mut globals_write_guard = self.globals.packages_config.write().await;
@@ -163,10 +495,15 @@ impl PackagesApiServer for PackagesApiImpl {
             let move_configs = config_resp.move_configs.as_mut().unwrap();
             let mut move_config = move_configs.get_mut(&package_uuid);
             if move_config.is_none() {
-                // Delete any other move_configs element where path equals move_toml_path.
+                // Delete any other move_configs element for the same package (e.g. the uuid
+                // was modified by the user). A Move.toml may have more than one package.
                 move_configs.retain(|_, config| {
                     if let Some(path) = &config.path {
-                        if path == &move_toml_path {
+                        let is_same_package = config
+                            .latest_package
+                            .as_ref()
+                            .map_or(true, |package| package.package_name == package_name);
+                        if path == &move_toml_path && is_same_package {
                             return false;
                         }
                     }
@@ -193,19 +530,29 @@ impl PackagesApiServer for PackagesApiImpl {
             }
 
             // Initialize this new current package.
-            move_config.latest_package = Some(PackageInstance::new(
+            move_config.latest_package = Some(GlobalsPackagesConfigST::new_package_instance(
                 package_id.clone(),
                 package_name.clone(),
                 package_timestamp.clone(),
+                object_changes.as_ref(),
             ));
 
+            // An upgrade shares the UpgradeCap of a previous publication.
+            GlobalsPackagesConfigST::update_lineage(move_config);
+
             // Make sure the latest known path is correctly reflected in globals.
             if move_config.path.is_none() || (move_config.path.as_ref().unwrap() != &move_toml_path)
             {
                 move_config.path = Some(move_toml_path.clone());
             }
 
-            // TODO Create init_objects by parsing the JSON output.
+            // Let the callers know that the data changed.
+            ui.get_mut_uuid().increment();
+        }
+
+        // Persist to survive a daemon restart.
+        if let Err(e) = GlobalsPackagesConfigST::save_to_file(&self.globals, workdir_idx).await {
+            log::error!("Failed to persist packages config for {}: {}", workdir, e);
         }
 
         // Return success.
@@ -214,6 +561,112 @@ impl PackagesApiServer for PackagesApiImpl {
         Ok(resp)
     }
 
+    async fn relink_move_config(
+        &self,
+        workdir: String,
+        package_uuid: String,
+        move_toml_path: String,
+    ) -> RpcResult<SuccessResponse> {
+        let workdir_idx = match GlobalsWorkdirsST::find_workdir_idx_by_name(&self.globals, &workdir)
+            .await
+        {
+            Some(workdir_idx) => workdir_idx,
+            None => return Err(RpcInputError::InvalidParams("workdir".to_string(), workdir).into()),
+        };
+        if !Self::move_toml_exists(&move_toml_path) {
+            return Err(
+                RpcInputError::InvalidParams("move_toml_path".to_string(), move_toml_path).into(),
+            );
+        }
+
+        // Use the same format as the path provided by the publish script.
+        let move_toml_path = move_toml_path.trim_end_matches("Move.toml").to_string();
+        self.set_move_config_path(workdir_idx, &package_uuid, &move_toml_path)
+            .await?;
+
+        let mut resp = SuccessResponse::new();
+        resp.header.method = "relinkMoveConfig".to_string();
+        resp.header.key = Some(workdir);
+        resp.result = true;
+        Ok(resp)
+    }
+
+    async fn merge_move_configs(
+        &self,
+        workdir: String,
+        from_uuid: String,
+        into_uuid: String,
+    ) -> RpcResult<SuccessResponse> {
+        let workdir_idx = match GlobalsWorkdirsST::find_workdir_idx_by_name(&self.globals, &workdir)
+            .await
+        {
+            Some(workdir_idx) => workdir_idx,
+            None => return Err(RpcInputError::InvalidParams("workdir".to_string(), workdir).into()),
+        };
+        if from_uuid == into_uuid {
+            return Err(RpcInputError::InvalidParams("from_uuid".to_string(), from_uuid).into());
+        }
+
+        {
+            let mut globals_write_guard = self.globals.packages_config.write().await;
+            let globals = &mut *globals_write_guard;
+            let ui = match globals.workdirs.get_mut(workdir_idx).ui.as_mut() {
+                Some(ui) => ui,
+                None => {
+                    return Err(
+                        RpcInputError::InvalidParams("from_uuid".to_string(), from_uuid).into(),
+                    )
+                }
+            };
+            let move_configs = match ui.get_mut_data().move_configs.as_mut() {
+                Some(move_configs) => move_configs,
+                None => {
+                    return Err(
+                        RpcInputError::InvalidParams("from_uuid".to_string(), from_uuid).into(),
+                    )
+                }
+            };
+            if !move_configs.contains_key(&into_uuid) {
+                return Err(
+                    RpcInputError::InvalidParams("into_uuid".to_string(), into_uuid).into(),
+                );
+            }
+            let from = match move_configs.remove(&from_uuid) {
+                Some(from) => from,
+                None => {
+                    return Err(
+                        RpcInputError::InvalidParams("from_uuid".to_string(), from_uuid).into(),
+                    )
+                }
+            };
+            let into = move_configs.get_mut(&into_uuid).unwrap();
+            GlobalsPackagesConfigST::merge_move_config(into, from);
+            ui.get_mut_uuid().increment();
+        }
+
+        // The published-data of from_uuid remains, so the merge must be
+        // recorded for it to not re-appear on the next daemon startup.
+        if let Err(e) = GlobalsPackagesConfigST::save_merge_record(
+            &self.globals,
+            workdir_idx,
+            &from_uuid,
+            &into_uuid,
+        )
+        .await
+        {
+            log::error!("Failed to persist merge record for {}: {}", workdir, e);
+        }
+        if let Err(e) = GlobalsPackagesConfigST::save_to_file(&self.globals, workdir_idx).await {
+            log::error!("Failed to persist packages config for {}: {}", workdir, e);
+        }
+
+        let mut resp = SuccessResponse::new();
+        resp.header.method = "mergeMoveConfigs".to_string();
+        resp.header.key = Some(workdir);
+        resp.result = true;
+        Ok(resp)
+    }
+
     async fn get_packages_config(
         &self,
         workdir: String,
@@ -222,6 +675,7 @@ impl PackagesApiServer for PackagesApiImpl {
         debug: Option<bool>,
         method_uuid: Option<String>,
         data_uuid: Option<String>,
+        wait_secs: Option<u64>,
     ) -> RpcResult<PackagesConfigResponse> {
         // data/display/debug allow variations of how the output
         // is produced (and they may be combined).
@@ -246,33 +700,33 @@ impl PackagesApiServer for PackagesApiImpl {
         let mut resp_ready: Option<PackagesConfigResponse> = None;
 
         // Just return what is already built in-memory, or empty.
-        {
+        let long_poll = LongPoll::new(wait_secs);
+        loop {
             // Get the globals for the target workdir_idx.
             let globals_read_guard = self.globals.packages_config.read().await;
             let globals = &*globals_read_guard;
             let globals = globals.workdirs.get_if_some(workdir_idx);
 
-            if let Some(globals) = globals {
-                if let Some(ui) = &globals.ui {
-                    if let (Some(method_uuid), Some(data_uuid)) = (method_uuid, data_uuid) {
-                        let globals_data_uuid = ui.get_uuid().get_data_uuid();
-                        if data_uuid == globals_data_uuid {
-                            let globals_method_uuid = ui.get_uuid().get_method_uuid();
-                            if method_uuid == globals_method_uuid {
-                                // The caller requested the same data that it already have a copy of.
-                                // Respond with the same UUID as a way to say "no change".
-                                let mut resp = PackagesConfigResponse::new();
-                                ui.init_header_uuids(&mut resp.header);
-                                resp_ready = Some(resp);
-                            }
-                        }
-                    } else {
-                        // The caller did not specify a method_uuid or data_uuid and
-                        // there is an in-memory response ready. Just respond with it.
-                        resp_ready = Some(ui.get_data().clone());
+            if let Some(ui) = globals.and_then(|globals| globals.ui.as_ref()) {
+                if ui.is_same_version(method_uuid.as_ref(), data_uuid.as_ref()) {
+                    // The caller requested the same data that it already have a copy of.
+                    // Respond with the same UUID as a way to say "no change".
+                    let mut resp = PackagesConfigResponse::new();
+                    ui.init_header_uuids(&mut resp.header);
+                    resp_ready = Some(resp);
+                    drop(globals_read_guard);
+                    if long_poll.wait(None).await {
+                        continue;
                     }
+                } else {
+                    // There is an in-memory response ready (different from
+                    // what the caller has, if anything). Just respond with it.
+                    let mut resp = ui.get_data().clone();
+                    ui.init_header_uuids(&mut resp.header);
+                    resp_ready = Some(resp);
                 }
             }
+            break;
         }
 
         if resp_ready.is_none() {
@@ -316,22 +770,18 @@ impl PackagesApiImpl {
         // creation_timestamp = "<epoch timestamp in microseconds> ISO 8061 datetime in local timezone"
         //
         // [packages]
-        // <package_name> = { uuid="<hash_of_move_toml_filepath>", custom_uuid=false }
+        // <package_name> = { uuid="<hash_of_move_toml_filepath>", uuid_custom=false }
         //
-        // For now, it seems Mysten Labs will have only *one* package per Move.toml, so same
-        // to be expected for the co-located Suibase.toml.
+        // There is one entry under [packages] per package published from the co-located
+        // Move.toml. An entry is added on the first publication of a package.
         //
         // Hash is defined as the RFC4648 Base32 (no pad) of the md5 bytes of the move_toml_path.
-        let suibase_toml_string = if !suibase_toml_path.exists() {
-            // Create the Suibase.toml file.
-            let mut packages_section = toml_edit::Table::new();
-            // Build a Table for each package.
-            let mut package_table = toml_edit::InlineTable::new();
-            package_table.insert("uuid", Self::short_hash(move_toml_path).into());
-            let uuid_custom = false;
-            package_table.insert("uuid_custom", uuid_custom.into());
-            packages_section.insert(package_name, toml_edit::Item::Value(package_table.into()));
-
+        // When more than one package, the package_name is included in the hash of the
+        // additional packages.
+        //
+        // The user can instead choose its own uuid with "uuid_custom=true". Such uuid is
+        // never modified by suibase and remains the same if the directory is moved.
+        let mut suibase_toml_doc = if !suibase_toml_path.exists() {
             let mut meta_section = toml_edit::Table::new();
             let now = std::time::SystemTime::now();
             let datetime_utc: chrono::DateTime<Utc> = now.into();
@@ -347,71 +797,527 @@ impl PackagesApiImpl {
 
             let mut suibase_toml_doc = toml_edit::Document::new();
             suibase_toml_doc["meta"] = toml_edit::Item::Table(meta_section);
-            suibase_toml_doc["packages"] = toml_edit::Item::Table(packages_section);
-
-            let new_file_string = suibase_toml_doc.to_string();
-            match tokio::fs::write(suibase_toml_path.clone(), new_file_string.clone()).await {
-                Ok(_) => {}
-                Err(e) => {
-                    let err_msg = format!("Failed to write Suibase.toml: {}", e);
-                    log::error!("{}", err_msg);
-                    return Err(RpcSuibaseError::FileAccessError(err_msg).into());
-                }
-            }
-            new_file_string
+            suibase_toml_doc["packages"] = toml_edit::Item::Table(toml_edit::Table::new());
+            suibase_toml_doc
         } else {
             // Read the existing Suibase.toml file.
-            match tokio::fs::read_to_string(suibase_toml_path.clone()).await {
-                Ok(read_string) => read_string,
+            let suibase_toml_string =
+                match tokio::fs::read_to_string(suibase_toml_path.clone()).await {
+                    Ok(read_string) => read_string,
+                    Err(e) => {
+                        let err_msg = format!("Failed to read Suibase.toml: {}", e);
+                        log::error!("{}", err_msg);
+                        return Err(RpcSuibaseError::FileAccessError(err_msg).into());
+                    }
+                };
+            match suibase_toml_string.parse::<toml_edit::Document>() {
+                Ok(suibase_toml_doc) => suibase_toml_doc,
                 Err(e) => {
-                    let err_msg = format!("Failed to read Suibase.toml: {}", e);
+                    let err_msg = format!("Failed to parse {:?}: {}", suibase_toml_path, e);
                     log::error!("{}", err_msg);
-                    return Err(RpcSuibaseError::FileAccessError(err_msg).into());
+                    return Err(RpcSuibaseError::InvalidConfig(err_msg).into());
                 }
             }
         };
 
-        // TODO Add robustness to toml_edit if the document exists but the user deleted the UUID field.
-        // TODO Implement to regenerate the UUID if moving of Suibase.toml is detected. Need to put path in toml for this.
+        let before = suibase_toml_doc.to_string();
+        let to_rpc_error = |err_msg: String| {
+            let err_msg = format!("{:?}: {}", suibase_toml_path, err_msg);
+            log::error!("{}", err_msg);
+            RpcError::from(RpcSuibaseError::InvalidConfig(err_msg))
+        };
+        let (mut package_uuid, uuid_custom) =
+            Self::get_or_add_package_uuid(&mut suibase_toml_doc, move_toml_path, package_name)
+                .map_err(to_rpc_error)?;
 
-        let suibase_toml_doc = match suibase_toml_string.parse::<toml_edit::Document>() {
-            Ok(suibase_toml_doc) => Some(suibase_toml_doc),
-            Err(e) => {
-                log::error!("Failed to parse Suibase.toml: {}", e);
-                None
+        // Detect if the Suibase.toml was moved (or copied) from another location.
+        let regenerate = self
+            .reconcile_moved_package(
+                workdir_idx,
+                move_toml_path,
+                package_name,
+                &package_uuid,
+                uuid_custom,
+            )
+            .await?;
+        if regenerate {
+            // A copy of the Suibase.toml of a package still at its original location is
+            // a distinct package, so give it its own identity.
+            if let Some(packages) = suibase_toml_doc["packages"].as_table_like_mut() {
+                packages.remove(package_name);
             }
+            (package_uuid, _) =
+                Self::get_or_add_package_uuid(&mut suibase_toml_doc, move_toml_path, package_name)
+                    .map_err(to_rpc_error)?;
+        }
+
+        // Write the file only when created or modified.
+        let new_file_string = suibase_toml_doc.to_string();
+        if !suibase_toml_path.exists() || new_file_string != before {
+            if let Err(e) = tokio::fs::write(suibase_toml_path.clone(), new_file_string).await {
+                let err_msg = format!("Failed to write Suibase.toml: {}", e);
+                log::error!("{}", err_msg);
+                return Err(RpcSuibaseError::FileAccessError(err_msg).into());
+            }
+        }
+
+        Ok((workdir_idx, package_uuid))
+    }
+
+    // Get the uuid of package_name from the [packages] section of a Suibase.toml document.
+    //
+    // The entry is added (or fixed when its uuid was deleted by the user) as needed.
+    //
+    // Returns the uuid and its uuid_custom flag, or a user friendly message on error.
+    fn get_or_add_package_uuid(
+        doc: &mut toml_edit::Document,
+        move_toml_path: &str,
+        package_name: &str,
+    ) -> Result<(String, bool), String> {
+        if doc.get("packages").is_none() {
+            doc["packages"] = toml_edit::Item::Table(toml_edit::Table::new());
+        }
+        let packages = match doc["packages"].as_table_like_mut() {
+            Some(packages) => packages,
+            None => return Err("[packages] must be a table".to_string()),
         };
 
-        // TODO: Handling of multiple package.
-        let mut package_uuid = String::new();
-        if let Some(doc) = suibase_toml_doc {
-            if let Some(packages) = doc.get("packages") {
-                if let Some(packages) = packages.as_table() {
-                    // Iterate the packages until finding the one that match package_name.
-                    for (key, value) in packages.iter() {
-                        if *key == *package_name {
-                            if let Some(value) = value.as_inline_table() {
-                                if let Some(uuid) = value.get("uuid") {
-                                    if let Some(uuid) = uuid.as_str() {
-                                        package_uuid = uuid.to_string();
-                                    }
-                                }
-                            }
-                            break;
-                        }
-                    }
+        // uuid used by the other packages (must be unique within the file).
+        let mut other_uuids = HashSet::new();
+        for (key, value) in packages.iter() {
+            if key != package_name {
+                if let Some(uuid) = value.get("uuid").and_then(|uuid| uuid.as_str()) {
+                    other_uuids.insert(uuid.to_string());
                 }
             }
         }
 
-        // If somehow could not load the package_uuid from the file, then default
-        // to a calculated value.
+        if let Some(entry) = packages.get(package_name) {
+            let uuid = entry.get("uuid").and_then(|uuid| uuid.as_str());
+            let uuid_custom = entry
+                .get("uuid_custom")
+                .and_then(|uuid_custom| uuid_custom.as_bool())
+                .unwrap_or(false);
+            match uuid {
+                Some(uuid) if !Self::is_valid_uuid(uuid) => {
+                    return Err(format!(
+                        "invalid uuid '{}' for package {}",
+                        uuid, package_name
+                    ));
+                }
+                Some(uuid) if other_uuids.contains(uuid) => {
+                    return Err(format!(
+                        "uuid '{}' of package {} is used by another package",
+                        uuid, package_name
+                    ));
+                }
+                Some(uuid) => return Ok((uuid.to_string(), uuid_custom)),
+                None if uuid_custom => {
+                    return Err(format!(
+                        "missing uuid for package {} (required with uuid_custom=true)",
+                        package_name
+                    ));
+                }
+                None => {} // Will be regenerated.
+            }
+        }
 
-        // TODO: Try to fix the Suibase.toml and try once again.
-        if package_uuid.is_empty() {
-            package_uuid = Self::short_hash(move_toml_path);
+        let mut package_uuid = Self::short_hash(move_toml_path);
+        if other_uuids.contains(&package_uuid) {
+            package_uuid = Self::short_hash(&format!("{}#{}", move_toml_path, package_name));
         }
+        let mut package_table = toml_edit::InlineTable::new();
+        package_table.insert("uuid", package_uuid.clone().into());
+        package_table.insert("uuid_custom", false.into());
+        packages.insert(package_name, toml_edit::Item::Value(package_table.into()));
+        Ok((package_uuid, false))
+    }
 
-        Ok((workdir_idx, package_uuid))
+    // A uuid is used as a key and in directory names (published-data).
+    fn is_valid_uuid(uuid: &str) -> bool {
+        !uuid.is_empty()
+            && uuid.len() <= 64
+            && uuid
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    }
+
+    // Path to a Move.toml as provided by the publish script (its directory) or the file itself.
+    fn move_toml_exists(move_toml_path: &str) -> bool {
+        let path = std::path::Path::new(move_toml_path);
+        if path.ends_with("Move.toml") {
+            path.exists()
+        } else {
+            path.join("Move.toml").exists()
+        }
+    }
+
+    // A package_uuid can be used by only one MoveConfig of a workdir.
+    //
+    // When the package_uuid is already known at another path:
+    //   - If the Move.toml no longer exists there, then the directory was
+    //     moved. The MoveConfig is updated with the new path (history is kept).
+    //   - Else the Suibase.toml was copied. Returns true to request a new uuid,
+    //     except for a custom uuid which is reported as a conflict.
+    async fn reconcile_moved_package(
+        &self,
+        workdir_idx: WorkdirIdx,
+        move_toml_path: &str,
+        package_name: &str,
+        package_uuid: &str,
+        uuid_custom: bool,
+    ) -> Result<bool, RpcError> {
+        let (other_path, other_package_name) = {
+            let globals_read_guard = self.globals.packages_config.read().await;
+            let globals = &*globals_read_guard;
+            let move_config = globals
+                .workdirs
+                .get_if_some(workdir_idx)
+                .and_then(|config| config.ui.as_ref())
+                .and_then(|ui| ui.get_data().move_configs.as_ref())
+                .and_then(|move_configs| move_configs.get(package_uuid));
+            match move_config {
+                Some(move_config) => (
+                    move_config.path.clone(),
+                    move_config
+                        .latest_package
+                        .as_ref()
+                        .map(|package| package.package_name.clone()),
+                ),
+                None => return Ok(false),
+            }
+        };
+
+        let other_path = match other_path {
+            Some(path) => path,
+            None => return Ok(false),
+        };
+        if other_path == move_toml_path
+            && other_package_name
+                .as_ref()
+                .map_or(true, |name| name == package_name)
+        {
+            return Ok(false);
+        }
+
+        if !Self::move_toml_exists(&other_path) {
+            self.set_move_config_path(workdir_idx, package_uuid, move_toml_path)
+                .await?;
+            GlobalsEventsDataST::add_console_event(
+                &self.globals,
+                workdir_idx,
+                format!(
+                    "package {} moved from {} to {}",
+                    package_name, other_path, move_toml_path
+                ),
+            )
+            .await;
+            return Ok(false);
+        }
+
+        if !uuid_custom {
+            return Ok(true);
+        }
+
+        let err_msg = format!(
+            "uuid '{}' of package {} in {} is already used by package {} in {}",
+            package_uuid,
+            package_name,
+            move_toml_path,
+            other_package_name.as_deref().unwrap_or("?"),
+            other_path
+        );
+        log::error!("{}", err_msg);
+        Err(RpcSuibaseError::InvalidConfig(err_msg).into())
+    }
+
+    // Change the path of a MoveConfig (and persist the change).
+    async fn set_move_config_path(
+        &self,
+        workdir_idx: WorkdirIdx,
+        package_uuid: &str,
+        move_toml_path: &str,
+    ) -> Result<(), RpcError> {
+        {
+            let mut globals_write_guard = self.globals.packages_config.write().await;
+            let globals = &mut *globals_write_guard;
+            let invalid_uuid = || -> RpcError {
+                RpcInputError::InvalidParams("package_uuid".to_string(), package_uuid.to_string())
+                    .into()
+            };
+            let ui = match globals.workdirs.get_mut(workdir_idx).ui.as_mut() {
+                Some(ui) => ui,
+                None => return Err(invalid_uuid()),
+            };
+            let move_config = ui
+                .get_mut_data()
+                .move_configs
+                .as_mut()
+                .and_then(|move_configs| move_configs.get_mut(package_uuid));
+            match move_config {
+                Some(move_config) => move_config.path = Some(move_toml_path.to_string()),
+                None => return Err(invalid_uuid()),
+            }
+            ui.get_mut_uuid().increment();
+        }
+        if let Err(e) = GlobalsPackagesConfigST::save_to_file(&self.globals, workdir_idx).await {
+            log::error!("Failed to persist packages config: {}", e);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+
+    fn get_uuid(
+        doc: &mut toml_edit::Document,
+        package_name: &str,
+    ) -> Result<(String, bool), String> {
+        PackagesApiImpl::get_or_add_package_uuid(doc, "/home/demo/move", package_name)
+    }
+
+    #[test]
+    fn test_is_valid_uuid() {
+        assert!(PackagesApiImpl::is_valid_uuid("ABCDEF234567"));
+        assert!(PackagesApiImpl::is_valid_uuid("my-uuid_1"));
+        assert!(PackagesApiImpl::is_valid_uuid(&"a".repeat(64)));
+        assert!(!PackagesApiImpl::is_valid_uuid(""));
+        assert!(!PackagesApiImpl::is_valid_uuid(&"a".repeat(65)));
+        assert!(!PackagesApiImpl::is_valid_uuid("../x"));
+        assert!(!PackagesApiImpl::is_valid_uuid("a b"));
+        assert!(!PackagesApiImpl::is_valid_uuid("a/b"));
+    }
+
+    #[test]
+    fn test_get_or_add_package_uuid() {
+        // Added to an empty document, then stable.
+        let mut doc = toml_edit::Document::new();
+        let (uuid, uuid_custom) = get_uuid(&mut doc, "demo").unwrap();
+        assert_eq!(uuid, PackagesApiImpl::short_hash("/home/demo/move"));
+        assert!(!uuid_custom);
+        assert_eq!(
+            doc["packages"]["demo"]["uuid"].as_str(),
+            Some(uuid.as_str())
+        );
+        assert_eq!(get_uuid(&mut doc, "demo").unwrap(), (uuid.clone(), false));
+
+        // Second package of the same Move.toml gets a different uuid.
+        let (other_uuid, _) = get_uuid(&mut doc, "other").unwrap();
+        assert_ne!(other_uuid, uuid);
+
+        // Custom uuid is kept.
+        let mut doc = "[packages]\ndemo = { uuid = \"my-uuid\", uuid_custom = true }\n"
+            .parse::<toml_edit::Document>()
+            .unwrap();
+        assert_eq!(
+            get_uuid(&mut doc, "demo").unwrap(),
+            ("my-uuid".to_string(), true)
+        );
+
+        // uuid deleted by the user is regenerated (unless custom).
+        let mut doc = "[packages]\ndemo = { uuid_custom = false }\n"
+            .parse::<toml_edit::Document>()
+            .unwrap();
+        assert_eq!(get_uuid(&mut doc, "demo").unwrap(), (uuid.clone(), false));
+        let mut doc = "[packages]\ndemo = { uuid_custom = true }\n"
+            .parse::<toml_edit::Document>()
+            .unwrap();
+        assert!(get_uuid(&mut doc, "demo").is_err());
+
+        // Invalid or duplicate uuid.
+        let mut doc = "[packages]\ndemo = { uuid = \"../x\" }\n"
+            .parse::<toml_edit::Document>()
+            .unwrap();
+        assert!(get_uuid(&mut doc, "demo").is_err());
+        let mut doc = "[packages]\ndemo = { uuid = \"u1\" }\nother = { uuid = \"u1\" }\n"
+            .parse::<toml_edit::Document>()
+            .unwrap();
+        assert!(get_uuid(&mut doc, "demo").is_err());
+
+        // [packages] must be a table.
+        let mut doc = "packages = 1\n".parse::<toml_edit::Document>().unwrap();
+        assert!(get_uuid(&mut doc, "demo").is_err());
+    }
+
+    // Globals with the workdirs in a temporary suibase home, loaded as on daemon startup.
+    async fn load_globals(suibase_home: &std::path::Path) -> (Globals, WorkdirIdx) {
+        let globals = Globals::new();
+        *globals.workdirs.write().await =
+            GlobalsWorkdirsST::with_suibase_home(suibase_home.to_path_buf());
+        let workdir_idx =
+            GlobalsWorkdirsST::find_workdir_idx_by_name(&globals, &"localnet".to_string())
+                .await
+                .unwrap();
+        GlobalsPackagesConfigST::load_from_files(&globals, workdir_idx)
+            .await
+            .unwrap();
+        (globals, workdir_idx)
+    }
+
+    async fn move_config_uuids(globals: &Globals, workdir_idx: WorkdirIdx) -> Vec<String> {
+        let globals_read_guard = globals.packages_config.read().await;
+        let globals = &*globals_read_guard;
+        let mut uuids: Vec<String> = globals
+            .workdirs
+            .get_if_some(workdir_idx)
+            .and_then(|config| config.ui.as_ref())
+            .and_then(|ui| ui.get_data().move_configs.as_ref())
+            .map(|move_configs| move_configs.keys().cloned().collect())
+            .unwrap_or_default();
+        uuids.sort();
+        uuids
+    }
+
+    async fn data_uuid(globals: &Globals, workdir_idx: WorkdirIdx) -> String {
+        let globals_read_guard = globals.packages_config.read().await;
+        let globals = &*globals_read_guard;
+        let ui = globals
+            .workdirs
+            .get_if_some(workdir_idx)
+            .unwrap()
+            .ui
+            .as_ref();
+        ui.unwrap().get_uuid().get_data_uuid()
+    }
+
+    #[tokio::test]
+    async fn test_merge_move_configs_is_durable() {
+        let temp_dir = TempDir::new("merge");
+        let suibase_home = temp_dir.path();
+        let published_data = suibase_home.join("workdirs/localnet/published-data/demo");
+        for (package_uuid, timestamp, package_id) in
+            [("uuid1", "1000", "0xp1"), ("uuid2", "2000", "0xp2")]
+        {
+            let path = published_data.join(package_uuid).join(timestamp);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(
+                path.join("package-id.json"),
+                format!("[\"{}\"]", package_id),
+            )
+            .unwrap();
+        }
+
+        let (globals, workdir_idx) = load_globals(suibase_home).await;
+        assert_eq!(
+            move_config_uuids(&globals, workdir_idx).await,
+            vec!["uuid1", "uuid2"]
+        );
+
+        let (admctrl_tx, _admctrl_rx) = tokio::sync::mpsc::channel(1);
+        let api = PackagesApiImpl::new(globals.clone(), admctrl_tx);
+        let before = data_uuid(&globals, workdir_idx).await;
+
+        // Invalid uuids do not change anything.
+        let workdir = "localnet".to_string();
+        for (from_uuid, into_uuid) in [("uuid1", "uuid1"), ("uuid1", "bad"), ("bad", "uuid2")] {
+            let result = api
+                .merge_move_configs(
+                    workdir.clone(),
+                    from_uuid.to_string(),
+                    into_uuid.to_string(),
+                )
+                .await;
+            assert!(result.is_err());
+        }
+        assert_eq!(data_uuid(&globals, workdir_idx).await, before);
+
+        let resp = api
+            .merge_move_configs(workdir.clone(), "uuid1".to_string(), "uuid2".to_string())
+            .await
+            .unwrap();
+        assert!(resp.result);
+        assert_ne!(data_uuid(&globals, workdir_idx).await, before);
+        assert_eq!(
+            move_config_uuids(&globals, workdir_idx).await,
+            vec!["uuid2"]
+        );
+
+        // from_uuid does not re-appear on daemon restart (its published-data still exists).
+        let (globals, workdir_idx) = load_globals(suibase_home).await;
+        assert_eq!(
+            move_config_uuids(&globals, workdir_idx).await,
+            vec!["uuid2"]
+        );
+        let globals_read_guard = globals.packages_config.read().await;
+        let ui = globals_read_guard
+            .workdirs
+            .get_if_some(workdir_idx)
+            .unwrap()
+            .ui
+            .as_ref()
+            .unwrap();
+        let into = &ui.get_data().move_configs.as_ref().unwrap()["uuid2"];
+        assert_eq!(into.latest_package.as_ref().unwrap().package_id, "0xp2");
+        assert_eq!(into.older_packages[0].package_id, "0xp1");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_get_packages_config_long_poll() {
+        let globals = Globals::new();
+        let workdir = "localnet".to_string();
+        let workdir_idx = GlobalsWorkdirsST::find_workdir_idx_by_name(&globals, &workdir)
+            .await
+            .unwrap();
+        {
+            let mut globals_write_guard = globals.packages_config.write().await;
+            let globals = &mut *globals_write_guard;
+            let mut config_resp = PackagesConfigResponse::new();
+            config_resp.move_configs = Some(HashMap::new());
+            globals.workdirs.get_mut(workdir_idx).ui = Some(Versioned::new(config_resp));
+        }
+        let (admctrl_tx, _admctrl_rx) = tokio::sync::mpsc::channel(1);
+        let api = PackagesApiImpl::new(globals.clone(), admctrl_tx);
+        let get = |method_uuid: Option<String>, data_uuid: Option<String>, wait_secs| {
+            api.get_packages_config(
+                workdir.clone(),
+                None,
+                None,
+                None,
+                method_uuid,
+                data_uuid,
+                wait_secs,
+            )
+        };
+
+        let resp = get(None, None, None).await.unwrap();
+        assert!(resp.move_configs.is_some());
+        let (method_uuid, data_uuid) = (resp.header.method_uuid, resp.header.data_uuid);
+
+        // Caller already has this version: "no change" right away without wait_secs.
+        let resp = get(method_uuid.clone(), data_uuid.clone(), None)
+            .await
+            .unwrap();
+        assert!(resp.move_configs.is_none());
+        assert_eq!(resp.header.data_uuid, data_uuid);
+
+        // With wait_secs, returns as soon as the data changes.
+        let changer = {
+            let globals = globals.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+                let mut globals_write_guard = globals.packages_config.write().await;
+                let globals = &mut *globals_write_guard;
+                let ui = globals.workdirs.get_mut(workdir_idx).ui.as_mut().unwrap();
+                let mut config_resp = ui.get_data().clone();
+                config_resp
+                    .move_configs
+                    .as_mut()
+                    .unwrap()
+                    .insert("uuid1".to_string(), MoveConfig::default());
+                ui.set(&config_resp);
+            })
+        };
+        let start = tokio::time::Instant::now();
+        let resp = get(method_uuid, data_uuid.clone(), Some(10)).await.unwrap();
+        // Virtual time: the change is noticed on the next check (every 100ms).
+        let elapsed = start.elapsed();
+        assert!(elapsed >= tokio::time::Duration::from_millis(300));
+        assert!(elapsed <= tokio::time::Duration::from_millis(400));
+        assert_ne!(resp.header.data_uuid, data_uuid);
+        assert!(resp.move_configs.unwrap().contains_key("uuid1"));
+        changer.await.unwrap();
     }
 }
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use tokio::sync::Mutex;
 
 use axum::async_trait;
@@ -8,16 +10,21 @@ use crate::admin_controller::{
     AdminControllerMsg, AdminControllerTx, EVENT_NOTIF_CONFIG_FILE_CHANGE,
 };
 use crate::basic_types::TargetServerIdx;
-use crate::shared_types::{GlobalsProxyMT, ServerStats, UuidST};
+use crate::shared_types::{GlobalsProxyMT, InputPort, ServerStats, UuidST};
 
 use super::{InfoResponse, ProxyApiServer};
 use super::{LinkStats, LinksResponse, LinksSummary, RpcInputError};
 
-use super::def_header::Versioned;
+use super::def_header::{LongPoll, Versioned};
+
+// The links stats are not versioned in the globals, so each check
+// re-evaluates them (less often than the default long-poll interval).
+const LINKS_LONG_POLL_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_millis(500);
 
 #[derive(Clone, PartialEq)]
 struct GetLinksInput {
-    pub target_servers_stats: Option<Vec<(TargetServerIdx, ServerStats)>>,
+    // (idx, stats, selectable, monitored)
+    pub target_servers_stats: Option<Vec<(TargetServerIdx, ServerStats, bool, bool)>>,
     pub all_servers_stats: Option<ServerStats>,
     pub selection_vectors: Option<Vec<Vec<u8>>>,
     pub input_port_found: bool,
@@ -41,12 +48,13 @@ impl GetLinksInput {
 pub struct ProxyApiImpl {
     pub globals: GlobalsProxyMT,
     pub admctrl_tx: AdminControllerTx,
-    prev_get_links_input: Mutex<Versioned<GetLinksInput>>,
+    // Last getLinks inputs per workdir (to detect changes).
+    prev_get_links_input: Mutex<HashMap<String, Versioned<GetLinksInput>>>,
 }
 
 impl ProxyApiImpl {
     pub fn new(globals: GlobalsProxyMT, admctrl_tx: AdminControllerTx) -> Self {
-        let prev_get_links_input = Mutex::new(Versioned::new(GetLinksInput::new()));
+        let prev_get_links_input = Mutex::new(HashMap::new());
         Self {
             globals,
             admctrl_tx,
@@ -211,6 +219,9 @@ impl ProxyApiServer for ProxyApiImpl {
         data: Option<bool>,
         display: Option<bool>,
         debug: Option<bool>,
+        method_uuid: Option<String>,
+        data_uuid: Option<String>,
+        wait_secs: Option<u64>,
     ) -> RpcResult<LinksResponse> {
         let mut resp = LinksResponse::new();
 
@@ -244,48 +255,84 @@ impl ProxyApiServer for ProxyApiImpl {
         let mut inputs = GetLinksInput::new();
         let mut inputs_version: Option<UuidST> = None;
 
-        {
-            // Get read lock access to the globals and just quickly copy what is needed.
-            // Most parsing and processing is done outside the lock.
-            let globals_read_guard = self.globals.read().await;
-            let globals = &*globals_read_guard;
-
-            if let Some(input_port) = globals.find_input_port_by_name(&workdir) {
-                inputs.input_port_found = true;
-                inputs.proxy_enabled = input_port.is_proxy_enabled();
-                inputs.user_request_start = input_port.is_user_request_start();
-
-                inputs.all_servers_stats = Some(input_port.all_servers_stats.clone());
-
-                let target_servers = &input_port.target_servers;
-
-                inputs.target_servers_stats = Some(
-                    target_servers
-                        .iter()
-                        .map(|(idx, target_server)| (idx, target_server.stats.clone()))
-                        .collect(),
-                );
-                inputs.selection_vectors = Some(input_port.selection_vectors.clone());
-            }
+        let long_poll = LongPoll::new(wait_secs);
+        loop {
+            {
+                // Get read lock access to the globals and just quickly copy what is needed.
+                // Most parsing and processing is done outside the lock.
+                let globals_read_guard = self.globals.read().await;
+                let globals = &*globals_read_guard;
+
+                if let Some(input_port) = globals.find_input_port_by_name(&workdir) {
+                    inputs.input_port_found = true;
+                    inputs.proxy_enabled = input_port.is_proxy_enabled();
+                    inputs.user_request_start = input_port.is_user_request_start();
+
+                    inputs.all_servers_stats = Some(input_port.all_servers_stats.clone());
+
+                    let target_servers = &input_port.target_servers;
+
+                    inputs.target_servers_stats = Some(
+                        target_servers
+                            .iter()
+                            .map(|(idx, target_server)| {
+                                (
+                                    idx,
+                                    target_server.stats.clone(),
+                                    target_server.is_selectable(),
+                                    target_server.is_monitored(),
+                                )
+                            })
+                            .collect(),
+                    );
+                    inputs.selection_vectors = Some(input_port.selection_vectors.clone());
+                }
 
-            // If debug, then extensively add more info to the output.
-            // (take a potential performance hit here).
-            if debug {
-                debug_out.push_str(&format!("{:?}", globals));
-            }
+                // If debug, then extensively add more info to the output.
+                // (take a potential performance hit here).
+                if debug {
+                    debug_out.push_str(&format!("{:?}", globals));
+                }
 
-            // If data, then handle potential UUID increment.
-            if data {
-                // To avoid race condition, prev_get_links_input is lock and modified only here.
-                // Outside the lock, use 'inputs' and 'inputs_version' within this thread.
-                let prev_input = &mut *self.prev_get_links_input.lock().await;
-                inputs_version = Some(prev_input.set(&inputs));
+                // If data, then handle potential UUID increment.
+                if data {
+                    // To avoid race condition, prev_get_links_input is lock and modified only here.
+                    // Outside the lock, use 'inputs' and 'inputs_version' within this thread.
+                    let prev_inputs = &mut *self.prev_get_links_input.lock().await;
+                    let prev_input = prev_inputs
+                        .entry(workdir.clone())
+                        .or_insert_with(|| Versioned::new(GetLinksInput::new()));
+                    inputs_version = Some(prev_input.set(&inputs));
+                }
+            } // Release the read lock.
+
+            // If no change since the specified user Uuids in the request, then
+            // return an empty response (just echo the Uuids).
+            if let (true, Some(version), Some(method_uuid), Some(data_uuid)) = (
+                data && !display && !debug,
+                &inputs_version,
+                &method_uuid,
+                &data_uuid,
+            ) {
+                if *method_uuid == version.get_method_uuid()
+                    && *data_uuid == version.get_data_uuid()
+                {
+                    if long_poll.wait(Some(LINKS_LONG_POLL_INTERVAL)).await {
+                        inputs = GetLinksInput::new();
+                        continue;
+                    }
+                    resp.header.method_uuid = Some(version.get_method_uuid());
+                    resp.header.data_uuid = Some(version.get_data_uuid());
+                    return Ok(resp);
+                }
             }
-        } // Release the read lock.
+            break;
+        }
 
         // Map the target_servers_stats into the API LinkStats.
         let mut healthy_server_count: usize = 0;
         let mut neutral_health_count: usize = 0;
+        let mut selectable_server_count: usize = 0;
         let mut link_stats: Vec<LinkStats> = Vec::new();
         let mut load_distribution_depth = 0;
         if let Some(target_servers_stats) = inputs.target_servers_stats {
@@ -315,7 +362,7 @@ impl ProxyApiServer for ProxyApiImpl {
                     // remember the position of that element in target_servers_stats.
                     let idx = target_servers_stats
                         .iter()
-                        .position(|(i, _)| *i == unmap_idx);
+                        .position(|(i, ..)| *i == unmap_idx);
                     if let Some(idx) = idx {
                         indices.push(idx);
                     } else {
@@ -338,8 +385,10 @@ impl ProxyApiServer for ProxyApiImpl {
             }
 
             for i in indices {
-                let server_stats = &target_servers_stats[i].1;
+                let (_, server_stats, selectable, monitored) = &target_servers_stats[i];
                 let mut link_stat = LinkStats::new(server_stats.alias());
+                link_stat.selectable = *selectable;
+                link_stat.monitored = *monitored;
 
                 let mut n_request = 0u64;
                 let mut n_success = 0u64;
@@ -350,9 +399,16 @@ impl ProxyApiServer for ProxyApiImpl {
                     link_stat.success_pct = Self::fmt_f64_api(success_pct);
                 };
 
+                // Only the selectable links contribute to the multi-link status.
                 let health_score = server_stats.health_score();
-                if health_score.is_normal() && health_score.is_sign_positive() {
-                    healthy_server_count += 1;
+                if *selectable {
+                    selectable_server_count += 1;
+                    if health_score.is_normal() && health_score.is_sign_positive() {
+                        healthy_server_count += 1;
+                    }
+                    if health_score == 0.0 {
+                        neutral_health_count += 1;
+                    }
                 }
                 link_stat.health_pct = Self::fmt_f64_api(health_score);
 
@@ -361,7 +417,6 @@ impl ProxyApiServer for ProxyApiImpl {
 
                 link_stat.status = if health_score == 0.0 {
                     // The server has not yet "determine" its initial health state.
-                    neutral_health_count += 1;
                     String::new()
                 } else if server_stats.is_healthy() {
                     "OK".to_string()
@@ -402,36 +457,15 @@ impl ProxyApiServer for ProxyApiImpl {
         }
 
         // Identify the multi-link RPC status.
-        let load_balance_str = if load_distribution_depth > 1 {
-            ", load-balanced".to_string()
-        } else {
-            String::new()
-        };
-
-        let server_count = link_stats.len();
-        (resp.status, resp.info) = if !inputs.proxy_enabled {
-            ("DOWN".to_string(), "proxy not enabled".to_string())
-        } else if !inputs.user_request_start {
-            ("DOWN".to_string(), format!("{} not started", workdir))
-        } else if server_count == 0 {
-            ("DOWN".to_string(), "no links in suibase.yaml".to_string())
-        } else if neutral_health_count == link_stats.len() {
-            ("DOWN".to_string(), "initializing".to_string())
-        } else if healthy_server_count == 0 {
-            ("DOWN".to_string(), "no servers available".to_string())
-        } else if healthy_server_count * 100 / server_count > 50 {
-            let resp_info = if workdir == "localnet" {
-                load_balance_str
-            } else {
-                format!("protected{}", load_balance_str)
-            };
-            ("OK".to_string(), resp_info)
-        } else {
-            (
-                "OK".to_string(),
-                format!(">50% degraded{}", load_balance_str),
-            )
-        };
+        (resp.status, resp.info) = InputPort::multi_link_status(
+            &workdir,
+            inputs.proxy_enabled,
+            inputs.user_request_start,
+            selectable_server_count,
+            healthy_server_count,
+            neutral_health_count,
+            load_distribution_depth,
+        );
 
         let mut display_out = String::new();
 
@@ -466,6 +500,18 @@ impl ProxyApiServer for ProxyApiImpl {
                 );
                 let mut load_distributed = load_distribution_depth;
                 for link_stat in link_stats.iter() {
+                    let mut info = link_stat.error_info.clone();
+                    for (flag, note) in [
+                        (link_stat.selectable, "(not selectable)"),
+                        (link_stat.monitored, "(not monitored)"),
+                    ] {
+                        if !flag {
+                            if !info.is_empty() {
+                                info.push(' ');
+                            }
+                            info.push_str(note);
+                        }
+                    }
                     let load_dist_marker = if load_distributed > 0 {
                         load_distributed -= 1;
                         "*"
@@ -481,7 +527,7 @@ impl ProxyApiServer for ProxyApiImpl {
                         Self::fmt_str_pct(&link_stat.load_pct),
                         Self::fmt_str_ms(&link_stat.resp_time),
                         Self::fmt_str_pct(&link_stat.success_pct),
-                        link_stat.error_info,
+                        info,
                     ));
                 }
             }
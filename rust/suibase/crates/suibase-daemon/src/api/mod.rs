@@ -1,15 +1,21 @@
 // This is a submodule specific to suibase-daemon.
 //
 // flatten under "api" module.
+pub(crate) use self::api_auth::*;
 pub(crate) use self::api_server::*;
+pub(crate) use self::api_unix_socket::*;
 pub(crate) use self::def_header::*;
 pub(crate) use self::def_methods::*;
+pub(crate) use self::open_rpc::*;
 pub(crate) use self::rpc_error::*;
 
+mod api_auth;
 mod api_server;
+mod api_unix_socket;
 mod def_header;
 mod def_methods;
 mod impl_general_api;
 mod impl_packages_api;
 mod impl_proxy_api;
+mod open_rpc;
 mod rpc_error;
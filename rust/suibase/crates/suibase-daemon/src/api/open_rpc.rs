@@ -0,0 +1,394 @@
+// OpenRPC document of the daemon API (https://spec.open-rpc.org).
+//
+// Returned by the "rpc.discover" method and dumped by "suibase-daemon openrpc".
+// Intended for generating typed clients (e.g. TypeScript, Python).
+//
+// The methods and their params are generated from the ProxyApi, GeneralApi and
+// PackagesApi traits (see rpc_api! in def_methods.rs), and the schemas from the
+// JsonSchema of the param and response types. A test verifies that every method
+// served by the daemon is documented.
+//
+// The parameters can be passed either by name (the Rust names in snake_case) or
+// by position (only by position for the unsubscribe methods). Another test
+// verifies the documented parameters against the served methods.
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::{Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+use super::{general_api_open_rpc, packages_api_open_rpc, proxy_api_open_rpc};
+
+pub const DISCOVER_METHOD_NAME: &str = "rpc.discover";
+
+const OPEN_RPC_VERSION: &str = "1.2.6";
+
+// Types of the params of the API methods. Option<T> is an optional param
+// documented with the schema of T.
+pub trait ApiParam {
+    const REQUIRED: bool;
+    fn schema(gen: &mut SchemaGenerator) -> Schema;
+}
+
+macro_rules! impl_api_param {
+    ($($t:ty),*) => {
+        $(
+            impl ApiParam for $t {
+                const REQUIRED: bool = true;
+                fn schema(gen: &mut SchemaGenerator) -> Schema {
+                    gen.subschema_for::<$t>()
+                }
+            }
+        )*
+    };
+}
+
+impl_api_param!(String, bool, u64);
+
+impl<T: ApiParam> ApiParam for Option<T> {
+    const REQUIRED: bool = false;
+    fn schema(gen: &mut SchemaGenerator) -> Schema {
+        T::schema(gen)
+    }
+}
+
+pub struct Param {
+    name: &'static str,
+    required: bool,
+    schema: fn(&mut SchemaGenerator) -> Schema,
+}
+
+impl Param {
+    pub fn of<T: ApiParam>(name: &'static str) -> Self {
+        Self {
+            name,
+            required: T::REQUIRED,
+            schema: T::schema,
+        }
+    }
+}
+
+// Subscription ids are generated by jsonrpsee (either a string or an integer).
+fn subscription_id_schema(gen: &mut SchemaGenerator) -> Schema {
+    Schema::Object(SchemaObject {
+        subschemas: Some(Box::new(SubschemaValidation {
+            one_of: Some(vec![
+                gen.subschema_for::<String>(),
+                gen.subschema_for::<u64>(),
+            ]),
+            ..Default::default()
+        })),
+        ..Default::default()
+    })
+}
+
+// The summary is the first paragraph of the doc comment, the description is the rest.
+fn summary_and_description(docs: &[&str]) -> (String, String) {
+    let lines: Vec<&str> = docs.iter().map(|line| line.trim()).collect();
+    let split = lines
+        .iter()
+        .position(|line| line.is_empty())
+        .unwrap_or(lines.len());
+    let summary = lines[..split].join(" ");
+    let description = lines[split..]
+        .iter()
+        .skip_while(|line| line.is_empty())
+        .copied()
+        .collect::<Vec<&str>>()
+        .join("\n");
+    (summary, description)
+}
+
+pub struct OpenRpcBuilder {
+    gen: SchemaGenerator,
+    methods: Vec<Value>,
+}
+
+impl OpenRpcBuilder {
+    fn new() -> Self {
+        let settings = SchemaSettings::draft07().with(|s| {
+            s.definitions_path = "#/components/schemas/".to_string();
+        });
+        Self {
+            gen: settings.into_generator(),
+            methods: Vec::new(),
+        }
+    }
+
+    fn push_method(
+        &mut self,
+        name: &str,
+        docs: &[&str],
+        param_structure: &str,
+        params: Vec<Param>,
+        result: Value,
+    ) {
+        let (summary, description) = summary_and_description(docs);
+        let params: Vec<Value> = params
+            .iter()
+            .map(|param| {
+                json!({
+                    "name": param.name,
+                    "required": param.required,
+                    "schema": (param.schema)(&mut self.gen),
+                })
+            })
+            .collect();
+        let mut method = json!({
+            "name": name,
+            "summary": summary,
+            "paramStructure": param_structure,
+            "params": params,
+            "result": result,
+        });
+        if !description.is_empty() {
+            method["description"] = json!(description);
+        }
+        self.methods.push(method);
+    }
+
+    pub fn method<R: JsonSchema>(
+        &mut self,
+        name: &str,
+        docs: &[&str],
+        params: Vec<Param>,
+    ) -> &mut Self {
+        let result = json!({
+            "name": R::schema_name(),
+            "required": true,
+            "schema": self.gen.subschema_for::<R>(),
+        });
+        self.push_method(name, docs, "either", params, result);
+        self
+    }
+
+    // Documented as two methods: the subscription and its unsubscribe.
+    pub fn subscription<I: JsonSchema>(
+        &mut self,
+        name: &str,
+        unsubscribe: &str,
+        docs: &[&str],
+        params: Vec<Param>,
+    ) -> &mut Self {
+        let mut docs = docs.to_vec();
+        let note = format!(
+            "Subscription (WebSocket only). The result is the subscription id, followed by \
+             \"{}\" notifications with a {}.",
+            name,
+            I::schema_name()
+        );
+        docs.extend(["", note.as_str()]);
+        let result = json!({
+            "name": "subscription_id",
+            "required": true,
+            "schema": subscription_id_schema(&mut self.gen),
+        });
+        self.push_method(name, &docs, "either", params, result);
+        // Make the item schema part of the components.
+        let _ = self.gen.subschema_for::<I>();
+
+        // jsonrpsee parses the subscription id only by position.
+        let summary = format!("Close a {} subscription.", name);
+        let params = vec![Param {
+            name: "subscription_id",
+            required: true,
+            schema: subscription_id_schema,
+        }];
+        let result = json!({
+            "name": "bool",
+            "required": true,
+            "schema": self.gen.subschema_for::<bool>(),
+        });
+        self.push_method(unsubscribe, &[&summary], "by-position", params, result);
+        self
+    }
+
+    fn build(self) -> Value {
+        json!({
+            "openrpc": OPEN_RPC_VERSION,
+            "info": {
+                "title": "Suibase Daemon API",
+                "description": "JSON-RPC API of the suibase-daemon. See https://suibase.io",
+                "license": { "name": "Apache-2.0" },
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "methods": self.methods,
+            "components": { "schemas": self.gen.definitions() },
+        })
+    }
+}
+
+pub fn open_rpc_document() -> Value {
+    let mut b = OpenRpcBuilder::new();
+    proxy_api_open_rpc(&mut b);
+    general_api_open_rpc(&mut b);
+    packages_api_open_rpc(&mut b);
+
+    // The result is this document (described by the OpenRPC meta-schema).
+    b.methods.push(json!({
+        "name": DISCOVER_METHOD_NAME,
+        "summary": "Returns the OpenRPC document of this API.",
+        "params": [],
+        "result": {
+            "name": "OpenRPC Schema",
+            "schema": { "$ref": "https://raw.githubusercontent.com/open-rpc/meta-schema/master/schema.json" },
+        },
+    }));
+
+    b.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::admin_controller::AdminControllerTx;
+    use crate::api::impl_general_api::GeneralApiImpl;
+    use crate::api::impl_packages_api::PackagesApiImpl;
+    use crate::api::impl_proxy_api::ProxyApiImpl;
+    use crate::api::{GeneralApiServer, PackagesApiServer, ProxyApiServer};
+    use crate::shared_types::Globals;
+    use jsonrpsee::core::server::rpc_module::Methods;
+
+    // All the methods served by the daemon (except rpc.discover).
+    fn served_methods(globals: Globals, admctrl_tx: AdminControllerTx) -> Methods {
+        let mut methods = Methods::new();
+        methods
+            .merge(ProxyApiImpl::new(globals.proxy.clone(), admctrl_tx.clone()).into_rpc())
+            .unwrap();
+        methods
+            .merge(GeneralApiImpl::new(globals.clone(), admctrl_tx.clone()).into_rpc())
+            .unwrap();
+        methods
+            .merge(PackagesApiImpl::new(globals, admctrl_tx).into_rpc())
+            .unwrap();
+        methods
+    }
+
+    #[test]
+    fn test_open_rpc_document() {
+        let doc = open_rpc_document();
+        assert_eq!(doc["openrpc"], OPEN_RPC_VERSION);
+
+        let documented: BTreeSet<String> = doc["methods"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["name"].as_str().unwrap().to_string())
+            .collect();
+
+        // Every served method must be documented (and nothing else).
+        let (admctrl_tx, _admctrl_rx) = tokio::sync::mpsc::channel(1);
+        let methods = served_methods(Globals::new(), admctrl_tx);
+        let mut served: BTreeSet<String> = methods
+            .method_names()
+            .map(|name| name.to_string())
+            .collect();
+        served.insert(DISCOVER_METHOD_NAME.to_string());
+        assert_eq!(documented, served);
+
+        // Every method has a summary (the doc comment of the trait method).
+        for method in doc["methods"].as_array().unwrap() {
+            let summary = method["summary"].as_str().unwrap_or_default();
+            assert!(!summary.is_empty(), "{} has no summary", method["name"]);
+        }
+
+        // All references resolve to a component schema.
+        let schemas = doc["components"]["schemas"].as_object().unwrap();
+        assert!(schemas.contains_key("LinksResponse"));
+        assert!(schemas.contains_key("Header"));
+        let text = doc.to_string();
+        for part in text.split("\"$ref\":\"#/components/schemas/").skip(1) {
+            let name = part.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "missing schema {}", name);
+        }
+    }
+
+    async fn call(methods: &Methods, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let (resp, _rx) = methods
+            .raw_json_request(&request.to_string())
+            .await
+            .unwrap();
+        serde_json::from_str(&resp.result).unwrap()
+    }
+
+    // Failure to deserialize the params (as opposed to a value rejected by the method).
+    fn is_parse_error(resp: &Value) -> bool {
+        resp["error"]["code"] == -32602 && resp["error"]["message"] == "Invalid params"
+    }
+
+    // A value of the documented type. The strings are the param name (the daemon
+    // includes the invalid value in its errors, so a wrong order is noticed).
+    fn param_value(param: &Value) -> Value {
+        match param["schema"]["type"].as_str() {
+            Some("string") => param["name"].clone(),
+            Some("boolean") => json!(true),
+            _ => json!(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_open_rpc_params() {
+        let doc = open_rpc_document();
+        let (admctrl_tx, _admctrl_rx) = tokio::sync::mpsc::channel(100);
+        let methods = served_methods(Globals::new(), admctrl_tx);
+
+        for method in doc["methods"].as_array().unwrap() {
+            let name = method["name"].as_str().unwrap();
+            if name == DISCOVER_METHOD_NAME || method["paramStructure"] == "by-position" {
+                continue;
+            }
+            let params = method["params"].as_array().unwrap();
+            let named: serde_json::Map<String, Value> = params
+                .iter()
+                .map(|p| (p["name"].as_str().unwrap().to_string(), param_value(p)))
+                .collect();
+            let positional: Vec<Value> = params.iter().map(param_value).collect();
+
+            // Same outcome by name and by position (the workdir and job do not
+            // exist, so the methods with side effects do nothing).
+            let by_name = call(&methods, name, Value::Object(named.clone())).await;
+            assert!(!is_parse_error(&by_name), "{} {}", name, by_name);
+            let by_position = call(&methods, name, Value::Array(positional.clone())).await;
+            assert_eq!(by_name, by_position, "{} params order", name);
+
+            // Every param is deserialized (the name and position are the ones of the trait).
+            for (i, param) in params.iter().enumerate() {
+                let param_name = param["name"].as_str().unwrap();
+                let mut invalid_named = named.clone();
+                invalid_named.insert(param_name.to_string(), json!([]));
+                let resp = call(&methods, name, Value::Object(invalid_named)).await;
+                assert!(is_parse_error(&resp), "{} param {}", name, param_name);
+
+                let mut invalid_positional = positional.clone();
+                invalid_positional[i] = json!([]);
+                let resp = call(&methods, name, Value::Array(invalid_positional)).await;
+                assert!(
+                    is_parse_error(&resp),
+                    "{} param {} position",
+                    name,
+                    param_name
+                );
+            }
+        }
+
+        // unsubscribeEvents with the id of an actual subscription.
+        let resp = call(
+            &methods,
+            "subscribeEvents",
+            json!({ "workdir": "localnet" }),
+        )
+        .await;
+        let subscription_id = resp["result"].clone();
+        assert!(
+            subscription_id.is_string() || subscription_id.is_u64(),
+            "{}",
+            resp
+        );
+        let resp = call(&methods, "unsubscribeEvents", json!([subscription_id])).await;
+        assert_eq!(resp["result"], true);
+        let resp = call(&methods, "unsubscribeEvents", json!([subscription_id])).await;
+        assert_eq!(resp["result"], false);
+    }
+}
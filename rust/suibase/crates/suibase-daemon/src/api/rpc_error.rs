@@ -32,6 +32,8 @@ pub enum RpcSuibaseError {
     InternalError(String),
     #[error("file access error: {0}")]
     FileAccessError(String),
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
 }
 
 impl RpcInputError {
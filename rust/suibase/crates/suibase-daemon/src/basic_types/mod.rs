@@ -6,9 +6,13 @@ pub(crate) use self::autosize_vec::*;
 //pub(crate) use self::error::*;
 pub(crate) use self::managed_vec::*;
 pub(crate) use self::suibase_basic_types::*;
+#[cfg(test)]
+pub(crate) use self::temp_dir::*;
 
 mod auto_thread;
 mod autosize_vec;
 mod error;
 mod managed_vec;
 mod suibase_basic_types;
+#[cfg(test)]
+mod temp_dir;
@@ -21,6 +21,11 @@ pub fn duration_to_micros(value: std::time::Duration) -> u32 {
     }
 }
 
+// Compare secrets (e.g. auth tokens) without leaking how much of them matches.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
 pub type InputPortIdx = crate::basic_types::ManagedVecUSize;
 pub type TargetServerIdx = crate::basic_types::ManagedVecUSize;
 pub type WorkdirIdx = crate::basic_types::ManagedVecUSize;
@@ -0,0 +1,28 @@
+// Temporary directory for the unit tests.
+//
+// The directory (and everything in it) is removed when the TempDir is
+// dropped, including when the test panics.
+use std::path::{Path, PathBuf};
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    // Creates "<system temp dir>/suibase-<name>-<uuid>".
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("suibase-{}-{}", name, uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
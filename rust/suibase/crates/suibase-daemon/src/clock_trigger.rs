@@ -4,6 +4,7 @@ use axum::async_trait;
 use tokio_graceful_shutdown::{FutureExt, SubsystemHandle};
 
 use crate::{
+    admin_controller::{AdminControllerMsg, AdminControllerTx, EVENT_STATUS_REFRESH},
     basic_types::{AutoThread, Runnable},
     network_monitor::{NetMonTx, NetworkMonitor},
 };
@@ -12,11 +13,15 @@ use tokio::time::{interval, Duration};
 #[derive(Clone)]
 pub struct ClockTriggerParams {
     netmon_tx: NetMonTx,
+    admctrl_tx: AdminControllerTx,
 }
 
 impl ClockTriggerParams {
-    pub fn new(netmon_tx: NetMonTx) -> Self {
-        Self { netmon_tx }
+    pub fn new(netmon_tx: NetMonTx, admctrl_tx: AdminControllerTx) -> Self {
+        Self {
+            netmon_tx,
+            admctrl_tx,
+        }
     }
 }
 
@@ -83,6 +88,15 @@ impl ClockTriggerThread {
                     // TODO This is bad if sustain for many seconds. Add watchdog here.
                 }
             }
+
+            if (tick % 2) == 0 {
+                // Every 2 seconds, refresh the status of all workdirs.
+                let mut msg = AdminControllerMsg::new();
+                msg.event_id = EVENT_STATUS_REFRESH;
+                if let Err(e) = self.params.admctrl_tx.send(msg).await {
+                    log::error!("send EVENT_STATUS_REFRESH {}", e);
+                }
+            }
         }
     }
 }
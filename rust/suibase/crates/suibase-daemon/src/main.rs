@@ -22,11 +22,14 @@
 //                        Started/stopped by the AdminController.
 //
 //  - ShellWorker:        Perform external call to Suibase command line. One instance per workdir (by design, will
-//                        serialize all command execution). Started/stopped by the AdminController.
+//                        bound concurrent command execution). Started/stopped by the AdminController.
 //
 //  - EventsWriterWorker: Manage connection(s) to subscribe/receive/dedup Sui events. Data written to FS (SQLite).
 //                        One instance per workdir. Uses tokio-tungstenite. Started/stopped by the AdminController.
 //
+//  - StatusWorker:       Keep the status of a workdir up to date (periodically requested by the ClockTrigger).
+//                        One instance per workdir. Started/stopped by the AdminController.
+//
 use anyhow::Result;
 
 use api::APIServerParams;
@@ -70,6 +73,14 @@ use tokio_graceful_shutdown::{
 pub enum Command {
     #[clap(name = "run")]
     Run {},
+
+    /// Print the OpenRPC document of the daemon API (JSON).
+    #[clap(name = "openrpc")]
+    OpenRpc {
+        /// Write to this file instead of stdout.
+        #[clap(long)]
+        output: Option<std::path::PathBuf>,
+    },
 }
 
 impl Command {
@@ -99,7 +110,7 @@ impl Command {
                 let apiserver_params = APIServerParams::new(globals.clone(), admctrl_tx.clone());
                 let apiserver = APIServer::new(apiserver_params);
 
-                let clock_params = ClockTriggerParams::new(netmon_tx.clone());
+                let clock_params = ClockTriggerParams::new(netmon_tx.clone(), admctrl_tx.clone());
                 let clock: ClockTrigger = ClockTrigger::new(clock_params);
 
                 // Start all top levels subsystems.
@@ -143,6 +154,14 @@ impl Command {
                 }
                 Ok(errors?)
             } // end Command::Run
+            Command::OpenRpc { output } => {
+                let doc = serde_json::to_string_pretty(&api::open_rpc_document())?;
+                match output {
+                    Some(path) => std::fs::write(path, doc + "\n")?,
+                    None => println!("{}", doc),
+                }
+                Ok(())
+            }
         }
     }
 } // end of Command
@@ -46,6 +46,11 @@ impl NetmonMsg {
             para16: [0; 1],
         }
     }
+
+    pub fn port_idx(&self) -> InputPortIdx {
+        self.port_idx
+    }
+
     pub fn server_idx(&self) -> u8 {
         self.server_idx
     }
@@ -55,9 +60,6 @@ impl NetmonMsg {
         &self.para32
     }*/
 
-    pub fn para16(&self) -> &[u16; 1] {
-        &self.para16
-    }
     /*
     pub fn para8(&self) -> &[u8; 2] {
         &self.para8
@@ -403,8 +405,11 @@ impl NetworkMonitor {
                     EVENT_GLOBALS_AUDIT => {
                         for (_, input_port) in input_ports.iter() {
                             if let Some(port_idx) = input_port.idx() {
-                                // Iterate every target_servers.
+                                // Iterate every monitored target_servers.
                                 for (_, target_server) in input_port.target_servers.iter() {
+                                    if !target_server.is_monitored() {
+                                        continue;
+                                    }
                                     if let Some(server_idx) = target_server.idx() {
                                         Self::process_latency_report_attempt_request(
                                             &mut self.mon_map,
@@ -792,7 +797,7 @@ impl NetworkMonitor {
 
         // Start another thread to initiate requests toward target servers (e.g. health check)
         let (request_worker_tx, request_worker_rx) = tokio::sync::mpsc::channel(1000);
-        let request_worker = RequestWorker::new(request_worker_rx);
+        let request_worker = RequestWorker::new(request_worker_rx, self.globals.clone());
         subsys.start(SubsystemBuilder::new("request-worker", |a| {
             request_worker.run(a)
         }));
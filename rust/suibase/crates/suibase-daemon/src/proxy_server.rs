@@ -1,4 +1,4 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::net::SocketAddr;
 
 use std::sync::Arc;
 
@@ -9,8 +9,8 @@ use crate::network_monitor::{
     NetMonTx, NetmonFlags, ProxyHandlerReport, HEADER_SBSD_SERVER_HC, HEADER_SBSD_SERVER_IDX,
 };
 use crate::shared_types::{
-    GlobalsProxyMT, REQUEST_FAILED_BODY_READ, REQUEST_FAILED_CONFIG_DISABLED,
-    REQUEST_FAILED_NOT_STARTED, REQUEST_FAILED_NO_SERVER_AVAILABLE,
+    GlobalsProxyMT, InputPort, ProxyListenConfig, REQUEST_FAILED_BODY_READ,
+    REQUEST_FAILED_CONFIG_DISABLED, REQUEST_FAILED_NOT_STARTED, REQUEST_FAILED_NO_SERVER_AVAILABLE,
     REQUEST_FAILED_NO_SERVER_RESPONDING, REQUEST_FAILED_RESP_BUILDER, REQUEST_FAILED_RESP_BYTES_RX,
     SEND_FAILED_UNSPECIFIED_ERROR,
 };
@@ -19,7 +19,7 @@ use anyhow::{anyhow, Result};
 use axum::{
     body::Body,
     extract::State,
-    http::{header, HeaderMap, Request, Response},
+    http::{header, HeaderMap, Request, Response, StatusCode},
     routing::get,
     Router,
 };
@@ -29,6 +29,7 @@ use hyper::http;
 use memchr::memmem;
 use serde::{Deserialize, Serialize};
 use tokio_graceful_shutdown::SubsystemHandle;
+use tokio_rustls::rustls;
 
 // An application target the localhost:port
 //
@@ -42,8 +43,18 @@ pub struct SharedStates {
     client: reqwest::Client,
     netmon_tx: NetMonTx,
     globals: GlobalsProxyMT,
+    auth_token: Option<String>, // Expected bearer token (proxy_auth_token).
 }
 
+// Delay between attempts to bind the port (e.g. still used by a ProxyServer being drained).
+const PROXY_SERVER_BIND_RETRY: std::time::Duration = std::time::Duration::from_secs(5);
+
+// On shutdown (e.g. port change), max time given to the in-flight requests to complete.
+const PROXY_SERVER_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+// Max time for a client to complete the TLS handshake.
+const PROXY_SERVER_TLS_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
 pub struct ProxyServer {
     enabled: bool,
 }
@@ -53,6 +64,17 @@ impl ProxyServer {
         Self { enabled: false }
     }
 
+    // Client for the requests of the daemon itself to its proxy ports (see
+    // InputPort::local_url). The user provided certificate is likely not
+    // issued for the loopback address, so it is not verified.
+    pub fn local_client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .no_proxy()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap_or_default()
+    }
+
     /*
     // From https://docs.rs/axum/0.6.18/src/axum/json.rs.html#147
     fn is_json_content_type(headers: &HeaderMap) -> bool {
@@ -108,6 +130,28 @@ impl ProxyServer {
         false
     }
 
+    // True when the request has the expected "Authorization: Bearer <token>".
+    //
+    // An empty expected token never matches (unresolved proxy_auth_token).
+    fn is_authorized(headers: &HeaderMap, auth_token: &str) -> bool {
+        let provided = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or("");
+        !auth_token.is_empty() && constant_time_eq(provided.as_bytes(), auth_token.as_bytes())
+    }
+
+    fn unauthorized_response() -> Result<Response<Body>, AppError> {
+        Ok(Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header(header::WWW_AUTHENTICATE, "Bearer")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                "{\"error\":\"missing or invalid bearer token (see proxy_auth_token)\"}",
+            ))?)
+    }
+
     async fn proxy_handler(
         State(states): State<Arc<SharedStates>>,
         req: Request<Body>,
@@ -133,6 +177,13 @@ impl ProxyServer {
         // these the following function can be called multiple times:
         //    - report.send_failed
 
+        // Rejected before any statistics (unauthorized requests are not "user traffic").
+        if let Some(auth_token) = &states.auth_token {
+            if !ProxyServer::is_authorized(req.headers(), auth_token) {
+                return ProxyServer::unauthorized_response();
+            }
+        }
+
         let handler_start = EpochTimestamp::now();
         let mut report = ProxyHandlerReport::new(&states.netmon_tx, states.port_idx, handler_start);
 
@@ -151,11 +202,17 @@ impl ProxyServer {
 
         let _ = ProxyServer::process_header_server_health_check(&mut headers, &mut report);
         headers.remove(header::HOST); // Remove the host header (will be replace with the target server).
+        if states.auth_token.is_some() {
+            // The proxy token is not for the target servers.
+            headers.remove(header::AUTHORIZATION);
+        }
 
         let mut retry_count = 0;
 
         // Find which target servers to send to...
-        let mut targets: Vec<(u8, String)> = Vec::new();
+        //
+        // (server_idx, uri, uri with secrets redacted, custom headers of the link)
+        let mut targets: Vec<(u8, String, String, HeaderMap)> = Vec::new();
         {
             let globals_read_guard = states.globals.read().await;
             let globals = &*globals_read_guard;
@@ -183,12 +240,23 @@ impl ProxyServer {
                     .into());
                 }
 
+                let mut selected: Vec<(u8, String)> = Vec::new();
                 if let Some(target_server_idx) = do_force_target_server_idx {
                     if let Some(target_server) = input_port.target_servers.get(target_server_idx) {
-                        targets.push((target_server_idx, target_server.rpc()));
+                        selected.push((target_server_idx, target_server.rpc()));
                     }
                 } else {
-                    input_port.get_best_target_servers(&mut targets, &handler_start)
+                    input_port.get_best_target_servers(&mut selected, &handler_start)
+                }
+                for (server_idx, target_uri) in selected {
+                    if let Some(target_server) = input_port.target_servers.get(server_idx) {
+                        targets.push((
+                            server_idx,
+                            target_uri,
+                            target_server.rpc_redacted(),
+                            target_server.headers().clone(),
+                        ));
+                    }
                 }
             }
         }
@@ -219,12 +287,19 @@ impl ProxyServer {
             }
         };
 
-        for (server_idx, target_uri) in targets.iter() {
+        for (server_idx, target_uri, target_origin, link_headers) in targets.iter() {
             // Build the request toward the current target server.
+            //
+            // The custom headers of the link (e.g. Authorization) replace the ones
+            // of the user request.
+            let mut req_headers = headers.clone();
+            for (name, value) in link_headers.iter() {
+                req_headers.insert(name.clone(), value.clone());
+            }
             let req_builder = states
                 .client
                 .request(method.clone(), target_uri)
-                .headers(headers.clone())
+                .headers(req_headers)
                 .body(bytes.clone());
 
             // Following works also (if one day bytes and cloning won't be needed):
@@ -319,7 +394,8 @@ impl ProxyServer {
                     if let Some(err_obj) = json_resp["error"].as_object() {
                         if !err_obj.contains_key("data") {
                             // Insert our own "data" field.
-                            let data = JsonRpcErrorDataObject::new(target_uri.clone(), retry_count);
+                            let data =
+                                JsonRpcErrorDataObject::new(target_origin.clone(), retry_count);
                             let mut json_resp = json_resp.clone();
                             if let Ok(data_obj) = serde_json::to_value(data) {
                                 json_resp["data"] = data_obj;
@@ -376,62 +452,250 @@ impl ProxyServer {
         globals: GlobalsProxyMT,
         netmon_tx: NetMonTx,
     ) -> Result<()> {
-        let shared_states: Arc<SharedStates> = Arc::new(SharedStates {
-            port_idx,
-            client: reqwest::Client::builder()
-                .no_proxy()
-                .connection_verbose(true)
-                .build()?,
-            globals,
-            netmon_tx,
-        });
-
         // Validate access to the PortStates in the Globals with an async confirmation that
         // there is a ProxyServer running for it (which will get clear on any failure to
         // start or later on any reason for thread exit).
-        let port_number = {
+        //
+        // The port number and listen config are read only once. On a change, the
+        // AdminController shutdowns this ProxyServer and starts another one.
+        let (instance, port_number, listen_config) = {
             // Yes... it is amazingly complicated just to get access... but this is happening rarely
             // and is the price to pay to make "flexible and safe" multi-threaded globals in Rust.
-            let mut globals_write_guard = shared_states.globals.write().await;
+            let mut globals_write_guard = globals.write().await;
             let globals = &mut *globals_write_guard;
             let input_ports = &mut globals.input_ports;
             if let Some(input_port) = input_ports.get_mut(port_idx) {
-                input_port.report_proxy_server_starting();
-                input_port.port_number()
+                (
+                    input_port.report_proxy_server_starting(),
+                    input_port.port_number(),
+                    input_port.listen_config().clone(),
+                )
             } else {
                 log::error!("port {} not found", port_idx);
                 return Err(anyhow!("port {} not found", port_idx));
             }
         };
 
+        let shared_states: Arc<SharedStates> = Arc::new(SharedStates {
+            port_idx,
+            client: reqwest::Client::builder()
+                .no_proxy()
+                .connection_verbose(true)
+                .build()?,
+            globals,
+            netmon_tx,
+            auth_token: listen_config.auth_token.clone(),
+        });
+
+        let tls_acceptor = if listen_config.is_tls() {
+            match Self::load_tls_config(&listen_config) {
+                Ok(tls_config) => Some(tokio_rustls::TlsAcceptor::from(tls_config)),
+                Err(err) => {
+                    // Does not fallback to plain HTTP. Retried on the next config change.
+                    let error = format!("TLS config error ({})", err);
+                    log::error!("port {} {}", port_number, error);
+                    Self::update_input_port(&shared_states, |input_port| {
+                        input_port.report_proxy_server_error(instance, error)
+                    })
+                    .await;
+                    subsys.on_shutdown_requested().await;
+                    Self::update_input_port(&shared_states, |input_port| {
+                        input_port.report_proxy_server_not_running(instance)
+                    })
+                    .await;
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+
         let app = Router::new()
             .fallback(get(Self::proxy_handler).post(Self::proxy_handler))
             .with_state(shared_states.clone());
 
-        let bind_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port_number);
-        log::info!("listening on {}", bind_address);
-
-        let return_value = axum::Server::bind(&bind_address)
-            .serve(app.into_make_service())
-            .with_graceful_shutdown(subsys.on_shutdown_requested())
-            .await
-            .map_err(|err| anyhow! {err});
+        let bind_address = SocketAddr::new(listen_config.bind_address, port_number);
 
-        log::info!("stopped for {}", bind_address);
+        // Retry until bound. The failure is visible with getStatus meanwhile.
+        let listener = loop {
+            match std::net::TcpListener::bind(bind_address) {
+                Ok(listener) => break listener,
+                Err(err) => {
+                    log::error!("bind failed for {}: {}", bind_address, err);
+                    let error = format!("port {} unavailable ({})", port_number, err);
+                    Self::update_input_port(&shared_states, |input_port| {
+                        input_port.report_proxy_server_error(instance, error)
+                    })
+                    .await;
+                    tokio::select! {
+                        _ = subsys.on_shutdown_requested() => {
+                            Self::update_input_port(&shared_states, |input_port| {
+                                input_port.report_proxy_server_not_running(instance)
+                            })
+                            .await;
+                            return Ok(());
+                        }
+                        _ = tokio::time::sleep(PROXY_SERVER_BIND_RETRY) => {}
+                    }
+                }
+            }
+        };
+        listener.set_nonblocking(true)?;
+
+        // On shutdown, stop accepting connections and let the in-flight requests
+        // complete (drain), but not for too long.
+        let mut accept_task: Option<tokio::task::JoinHandle<()>> = None;
+        let server: futures::future::BoxFuture<'_, hyper::Result<()>> = match tls_acceptor {
+            None => Box::pin(
+                axum::Server::from_tcp(listener)?
+                    .serve(app.into_make_service())
+                    .with_graceful_shutdown(subsys.on_shutdown_requested()),
+            ),
+            Some(tls_acceptor) => {
+                let (conn_tx, conn_rx) = tokio::sync::mpsc::channel(32);
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                accept_task = Some(tokio::spawn(Self::tls_accept_loop(
+                    listener,
+                    tls_acceptor,
+                    conn_tx,
+                )));
+                let incoming = hyper::server::accept::from_stream(futures::stream::unfold(
+                    conn_rx,
+                    |mut conn_rx| async move {
+                        conn_rx
+                            .recv()
+                            .await
+                            .map(|conn| (Ok::<_, std::io::Error>(conn), conn_rx))
+                    },
+                ));
+                Box::pin(
+                    axum::Server::builder(incoming)
+                        .serve(app.into_make_service())
+                        .with_graceful_shutdown(subsys.on_shutdown_requested()),
+                )
+            }
+        };
 
-        {
-            // This will cover for all scenario (abnormal or not) that the proxy had to exit. Will
-            // allow the AdminController to detect and react as needed.
-            let mut globals_write_guard = shared_states.globals.write().await;
-            let globals = &mut *globals_write_guard;
-            let input_port = &mut globals.input_ports;
-            if let Some(port_state) = input_port.get_mut(port_idx) {
-                port_state.report_proxy_server_not_running();
+        Self::update_input_port(&shared_states, |input_port| {
+            input_port.report_proxy_server_listening(instance)
+        })
+        .await;
+        log::info!(
+            "listening on {}{}",
+            bind_address,
+            if listen_config.is_tls() { " (TLS)" } else { "" }
+        );
+
+        let return_value = tokio::select! {
+            result = server => result.map_err(|err| anyhow! {err}),
+            _ = async {
+                subsys.on_shutdown_requested().await;
+                tokio::time::sleep(PROXY_SERVER_DRAIN_TIMEOUT).await;
+            } => {
+                log::warn!("drain timeout for {}", bind_address);
+                Ok(())
             }
+        };
+
+        if let Some(accept_task) = accept_task {
+            accept_task.abort();
         }
 
+        log::info!("stopped for {}", bind_address);
+
+        // This will cover for all scenario (abnormal or not) that the proxy had to exit. Will
+        // allow the AdminController to detect and react as needed.
+        Self::update_input_port(&shared_states, |input_port| {
+            input_port.report_proxy_server_not_running(instance)
+        })
+        .await;
+
         return_value
     }
+
+    fn load_tls_config(listen_config: &ProxyListenConfig) -> Result<Arc<rustls::ServerConfig>> {
+        let (cert_path, key_path) = match (&listen_config.tls_cert, &listen_config.tls_key) {
+            (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+            _ => return Err(anyhow!("proxy_tls_cert and proxy_tls_key must be both set")),
+        };
+
+        let open = |path: &str| {
+            std::fs::File::open(path)
+                .map(std::io::BufReader::new)
+                .map_err(|err| anyhow!("{}: {}", path, err))
+        };
+
+        let certs: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut open(cert_path)?)
+            .map_err(|err| anyhow!("{}: {}", cert_path, err))?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        if certs.is_empty() {
+            return Err(anyhow!("{}: no certificate found", cert_path));
+        }
+
+        // First private key of the file (PKCS8, RSA or EC).
+        let key = rustls_pemfile::read_all(&mut open(key_path)?)
+            .map_err(|err| anyhow!("{}: {}", key_path, err))?
+            .into_iter()
+            .find_map(|item| match item {
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("{}: no private key found", key_path))?;
+
+        let config = rustls::ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        Ok(Arc::new(config))
+    }
+
+    // Accept the TCP connections and do their TLS handshakes concurrently (a slow
+    // client does not delay the others). The established streams are given to hyper.
+    async fn tls_accept_loop(
+        listener: tokio::net::TcpListener,
+        tls_acceptor: tokio_rustls::TlsAcceptor,
+        conn_tx: tokio::sync::mpsc::Sender<tokio_rustls::server::TlsStream<tokio::net::TcpStream>>,
+    ) {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    // e.g. too many open files. Avoid a busy loop.
+                    log::warn!("accept failed: {}", err);
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let tls_acceptor = tls_acceptor.clone();
+            let conn_tx = conn_tx.clone();
+            tokio::spawn(async move {
+                match tokio::time::timeout(
+                    PROXY_SERVER_TLS_HANDSHAKE_TIMEOUT,
+                    tls_acceptor.accept(stream),
+                )
+                .await
+                {
+                    Ok(Ok(tls_stream)) => {
+                        let _ = conn_tx.send(tls_stream).await;
+                    }
+                    Ok(Err(err)) => log::debug!("TLS handshake failed: {}", err),
+                    Err(_) => log::debug!("TLS handshake timeout"),
+                }
+            });
+        }
+    }
+
+    async fn update_input_port<F: FnOnce(&mut InputPort)>(shared_states: &SharedStates, f: F) {
+        let mut globals_write_guard = shared_states.globals.write().await;
+        let globals = &mut *globals_write_guard;
+        if let Some(input_port) = globals.input_ports.get_mut(shared_states.port_idx) {
+            f(input_port);
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
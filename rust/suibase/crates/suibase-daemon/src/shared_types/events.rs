@@ -1,4 +1,121 @@
-use crate::basic_types::AutoSizeVec;
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+
+use crate::basic_types::{AutoSizeVec, WorkdirIdx};
+
+use super::Globals;
+
+// Maximum number of events kept in-memory per channel (oldest are dropped first).
+pub const EVENTS_MAX_PER_CHANNEL: usize = 10000;
+
+// Maximum number of events that can be queued toward a slow subscriber.
+const EVENTS_BROADCAST_CAPACITY: usize = 1000;
+
+// Watches persisted in the .state directory of each workdir (the events
+// themselves are not persisted).
+const WATCHES_FILE: &str = "watches.json";
+
+// Events are stored in separate channels (each can be queried independently):
+//
+//   User:    Events emitted by the packages published by the user (Move.toml
+//            tracked with prePublish/postPublish).
+//
+//   Console: Operational messages from the daemon itself (e.g. "localnet started",
+//            "websocket connected").
+//
+//   Watch:   Activity of the addresses/objects explicitly watched by the user.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventsChannel {
+    User,
+    Console,
+    Watch,
+}
+
+impl EventsChannel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventsChannel::User => "user",
+            EventsChannel::Console => "console",
+            EventsChannel::Watch => "watch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "user" => Some(EventsChannel::User),
+            "console" => Some(EventsChannel::Console),
+            "watch" => Some(EventsChannel::Watch),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EventsChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// What the user asked to watch (see the watch_events channel).
+//
+//   Address: Any transaction sent by this address.
+//   Object:  Any transaction that changes this object (e.g. a shared object mutated).
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WatchKind {
+    Address,
+    Object,
+}
+
+impl WatchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchKind::Address => "address",
+            WatchKind::Object => "object",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "address" => Some(WatchKind::Address),
+            "object" => Some(WatchKind::Object),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WatchTarget {
+    pub kind: WatchKind,
+    pub id: String, // Normalized to lowercase hex with a "0x" prefix.
+}
+
+impl WatchTarget {
+    // Returns None if the id is not a valid hex Sui address/object id.
+    pub fn new(kind: WatchKind, id: &str) -> Option<Self> {
+        let hex = id.trim().strip_prefix("0x")?;
+        if hex.is_empty() || hex.len() > 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Self {
+            kind,
+            id: format!("0x{}", hex.to_lowercase()),
+        })
+    }
+
+    // Unique string identifying this watch (e.g. "address:0x12ab").
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.kind.as_str(), self.id)
+    }
+
+    // Reverse of key().
+    pub fn from_key(key: &str) -> Option<Self> {
+        let (kind, id) = key.split_once(':')?;
+        Self::new(WatchKind::from_name(kind)?, id)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SuiEventData {
@@ -6,21 +123,98 @@ pub struct SuiEventData {
     pub timestamp: String,
 }
 
+impl SuiEventData {
+    pub fn new(msg: String, timestamp: String) -> Self {
+        Self { msg, timestamp }
+    }
+
+    // Event generated by the daemon itself, timestamped now.
+    pub fn new_now(msg: String) -> Self {
+        Self {
+            msg,
+            timestamp: chrono::Utc::now().timestamp_millis().to_string(),
+        }
+    }
+
+    pub fn timestamp_ms(&self) -> Option<u64> {
+        self.timestamp.parse::<u64>().ok()
+    }
+
+    // Filtering shared by the getEvents and subscribeEvents API.
+    //
+    // Timestamps are milliseconds since epoch (same as the Sui "timestampMs").
+    // after_ts is exclusive, last_ts is inclusive. An event without a valid
+    // timestamp never match a bounded range.
+    pub fn is_in_range(&self, after_ts: Option<u64>, last_ts: Option<u64>) -> bool {
+        if after_ts.is_none() && last_ts.is_none() {
+            return true;
+        }
+        match self.timestamp_ms() {
+            Some(ts) => {
+                after_ts.map_or(true, |after_ts| ts > after_ts)
+                    && last_ts.map_or(true, |last_ts| ts <= last_ts)
+            }
+            None => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EventsWorkdirData {
     pub user_events: Vec<SuiEventData>,
     pub console_events: Vec<SuiEventData>,
     pub watch_events: Vec<SuiEventData>,
+
+    // Addresses/objects watched by the user. The events workers subscribe
+    // accordingly and store the matches in watch_events.
+    pub watches: BTreeSet<WatchTarget>,
+
+    // Every newly stored event is also sent here for the API subscribers.
+    events_tx: tokio::sync::broadcast::Sender<(EventsChannel, SuiEventData)>,
 }
 
 impl EventsWorkdirData {
     pub fn new() -> Self {
+        let (events_tx, _) = tokio::sync::broadcast::channel(EVENTS_BROADCAST_CAPACITY);
         Self {
             user_events: Vec::new(),
             console_events: Vec::new(),
             watch_events: Vec::new(),
+            watches: BTreeSet::new(),
+            events_tx,
+        }
+    }
+
+    pub fn events(&self, channel: EventsChannel) -> &Vec<SuiEventData> {
+        match channel {
+            EventsChannel::User => &self.user_events,
+            EventsChannel::Console => &self.console_events,
+            EventsChannel::Watch => &self.watch_events,
         }
     }
+
+    pub fn add_event(&mut self, channel: EventsChannel, event: SuiEventData) {
+        let events = match channel {
+            EventsChannel::User => &mut self.user_events,
+            EventsChannel::Console => &mut self.console_events,
+            EventsChannel::Watch => &mut self.watch_events,
+        };
+        events.push(event.clone());
+        if events.len() > EVENTS_MAX_PER_CHANNEL {
+            let excess = events.len() - EVENTS_MAX_PER_CHANNEL;
+            events.drain(0..excess);
+        }
+        // An error only means that there is no subscriber right now.
+        let _ = self.events_tx.send((channel, event));
+    }
+
+    // Receive all events added after this call (all channels).
+    //
+    // Subscribe while holding the same lock used to read the stored
+    // events to avoid missing (or duplicating) any event.
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<(EventsChannel, SuiEventData)> {
+        self.events_tx.subscribe()
+    }
 }
 
 impl std::default::Default for EventsWorkdirData {
@@ -41,6 +235,84 @@ impl GlobalsEventsDataST {
             workdirs: AutoSizeVec::new(),
         }
     }
+
+    // Utility to add a daemon operational message to the console channel.
+    //
+    // This is a multi-thread safe call (will get the proper
+    // lock on the globals).
+    pub async fn add_console_event(globals: &Globals, workdir_idx: WorkdirIdx, msg: String) {
+        log::info!("console event: {}", msg);
+        let mut globals_write_guard = globals.events_data.write().await;
+        let globals = &mut *globals_write_guard;
+        globals
+            .workdirs
+            .get_mut(workdir_idx)
+            .add_event(EventsChannel::Console, SuiEventData::new_now(msg));
+    }
+
+    // Persist the watches of a workdir.
+    //
+    // This is a multi-thread safe call (will get the proper
+    // lock on the globals).
+    pub async fn save_watches(globals: &Globals, workdir_idx: WorkdirIdx) -> Result<()> {
+        let state_path = {
+            let workdirs_guard = globals.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+            match workdirs.workdirs.get(workdir_idx) {
+                Some(workdir) => workdir.state_path().to_path_buf(),
+                None => return Err(anyhow::anyhow!("workdir_idx {} not found", workdir_idx)),
+            }
+        };
+
+        let keys: Vec<String> = {
+            let globals_read_guard = globals.events_data.read().await;
+            let globals = &*globals_read_guard;
+            globals
+                .workdirs
+                .get_if_some(workdir_idx)
+                .map(|data| data.watches.iter().map(|target| target.key()).collect())
+                .unwrap_or_default()
+        };
+
+        // Write a temporary file first, so the file is never partially written.
+        let contents = serde_json::to_string_pretty(&keys)?;
+        std::fs::create_dir_all(&state_path)?;
+        let path = state_path.join(WATCHES_FILE);
+        let tmp_path = state_path.join(format!("{}.tmp", WATCHES_FILE));
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    // Restore the watches of a workdir. Called once per workdir on daemon startup.
+    pub async fn load_watches(globals: &Globals, workdir_idx: WorkdirIdx) -> Result<()> {
+        let state_path = {
+            let workdirs_guard = globals.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+            match workdirs.workdirs.get(workdir_idx) {
+                Some(workdir) => workdir.state_path().to_path_buf(),
+                None => return Err(anyhow::anyhow!("workdir_idx {} not found", workdir_idx)),
+            }
+        };
+
+        let keys: Vec<String> = match std::fs::read_to_string(state_path.join(WATCHES_FILE)) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(_) => return Ok(()), // Optional, so no error if does not exists.
+        };
+
+        let mut globals_write_guard = globals.events_data.write().await;
+        let globals = &mut *globals_write_guard;
+        let watches = &mut globals.workdirs.get_mut(workdir_idx).watches;
+        for key in keys {
+            match WatchTarget::from_key(&key) {
+                Some(target) => {
+                    watches.insert(target);
+                }
+                None => log::warn!("invalid watch {} in {}", key, WATCHES_FILE),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for GlobalsEventsDataST {
@@ -48,3 +320,47 @@ impl Default for GlobalsEventsDataST {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch_target() {
+        // Normalized to lowercase with the "0x" prefix.
+        let target = WatchTarget::new(WatchKind::Address, " 0xABcd ").unwrap();
+        assert_eq!(target.id, "0xabcd");
+        assert_eq!(target.key(), "address:0xabcd");
+        assert_eq!(WatchTarget::from_key(&target.key()), Some(target));
+
+        let id = format!("0x{}", "f".repeat(64));
+        assert!(WatchTarget::new(WatchKind::Object, &id).is_some());
+
+        assert!(WatchTarget::new(WatchKind::Object, "abcd").is_none()); // No prefix.
+        assert!(WatchTarget::new(WatchKind::Object, "0x").is_none());
+        assert!(WatchTarget::new(WatchKind::Object, "0xabcg").is_none());
+        assert!(WatchTarget::new(WatchKind::Object, &format!("0x{}", "f".repeat(65))).is_none());
+        assert!(WatchTarget::from_key("package:0xabcd").is_none());
+        assert!(WatchTarget::from_key("0xabcd").is_none());
+    }
+
+    #[test]
+    fn test_is_in_range() {
+        let event = SuiEventData::new("msg".to_string(), "1000".to_string());
+        assert!(event.is_in_range(None, None));
+        // after_ts is exclusive.
+        assert!(event.is_in_range(Some(999), None));
+        assert!(!event.is_in_range(Some(1000), None));
+        // last_ts is inclusive.
+        assert!(event.is_in_range(None, Some(1000)));
+        assert!(!event.is_in_range(None, Some(999)));
+        assert!(event.is_in_range(Some(999), Some(1000)));
+        assert!(!event.is_in_range(Some(1000), Some(2000)));
+
+        // No valid timestamp never match a bounded range.
+        let event = SuiEventData::new("msg".to_string(), "not a number".to_string());
+        assert!(event.is_in_range(None, None));
+        assert!(!event.is_in_range(Some(0), None));
+        assert!(!event.is_in_range(None, Some(u64::MAX)));
+    }
+}
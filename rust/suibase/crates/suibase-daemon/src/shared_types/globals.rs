@@ -18,10 +18,13 @@
 use std::sync::Arc;
 
 use crate::api::{StatusResponse, Versioned};
-use crate::basic_types::{AutoSizeVec, ManagedVec};
+use crate::basic_types::{AutoSizeVec, ManagedVec, WorkdirIdx};
 use crate::shared_types::InputPort;
 
-use super::{GlobalsEventsDataST, GlobalsPackagesConfigST, GlobalsWorkdirsST};
+use super::{
+    ConfigDiagnostic, GlobalsEventsDataST, GlobalsJobsST, GlobalsPackagesConfigST,
+    GlobalsWorkdirsST,
+};
 
 #[derive(Debug)]
 pub struct GlobalsProxyST {
@@ -44,6 +47,15 @@ impl GlobalsProxyST {
         }
         None
     }
+
+    pub fn find_input_port_by_workdir_idx(&self, workdir_idx: WorkdirIdx) -> Option<&InputPort> {
+        self.input_ports
+            .iter()
+            .find(|input_port| {
+                input_port.1.workdir_idx() == workdir_idx && !input_port.1.is_deactivated()
+            })
+            .map(|input_port| input_port.1)
+    }
 }
 
 impl Default for GlobalsProxyST {
@@ -102,6 +114,18 @@ pub struct GlobalsWorkdirConfigST {
     // are controlled by the user (suibase.yaml
     // files, workdir CLI operations).
     pub precompiled_bin: bool,
+
+    // Copied from the suibase.yaml files by the AdminController.
+    pub is_local_network: bool,
+    pub sui_faucet_enabled: bool,
+    pub sui_faucet_host_ip: String,
+    pub sui_faucet_port: u16,
+
+    // Problems found in the suibase.yaml files during the last load.
+    pub config_diagnostics: Vec<ConfigDiagnostic>,
+
+    // Effective config after merging all suibase.yaml files (for debugging).
+    pub effective_config: String,
 }
 
 #[derive(Debug)]
@@ -146,6 +170,7 @@ pub type GlobalsConfigMT = Arc<tokio::sync::RwLock<GlobalsConfigST>>;
 pub type GlobalsPackagesConfigMT = Arc<tokio::sync::RwLock<GlobalsPackagesConfigST>>;
 pub type GlobalsEventsDataMT = Arc<tokio::sync::RwLock<GlobalsEventsDataST>>;
 pub type GlobalsWorkdirsMT = Arc<tokio::sync::RwLock<GlobalsWorkdirsST>>;
+pub type GlobalsJobsMT = Arc<tokio::sync::RwLock<GlobalsJobsST>>;
 
 // A convenient way to refer to all globals at once.
 // Note: clone() conveniently increment the reference count of every field (ARC).
@@ -168,6 +193,9 @@ pub struct Globals {
 
     // In-memory access to events data of actively monitored modules.
     pub events_data: GlobalsEventsDataMT,
+
+    // Workdir commands (e.g. "localnet start") requested through the API and their output.
+    pub jobs: GlobalsJobsMT,
 }
 
 impl Globals {
@@ -179,6 +207,7 @@ impl Globals {
             status: Arc::new(tokio::sync::RwLock::new(GlobalsStatusST::new())),
             packages_config: Arc::new(tokio::sync::RwLock::new(GlobalsPackagesConfigST::new())),
             events_data: Arc::new(tokio::sync::RwLock::new(GlobalsEventsDataST::new())),
+            jobs: Arc::new(tokio::sync::RwLock::new(GlobalsJobsST::new())),
         }
     }
 }
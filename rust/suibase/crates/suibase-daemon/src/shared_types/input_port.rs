@@ -2,7 +2,7 @@ use crate::basic_types::*;
 use crate::shared_types::Link;
 use crate::shared_types::TargetServer;
 
-use super::{ServerStats, WorkdirProxyConfig};
+use super::{ProxyListenConfig, ServerStats, WorkdirProxyConfig};
 
 use std::hash::Hasher;
 use twox_hash::XxHash32;
@@ -17,9 +17,18 @@ pub struct InputPort {
     // The workdir idx (from AdminController context). Set once at construction.
     workdir_idx: WorkdirIdx,
 
-    // TCP/UDP port number. Set once at construction.
+    // TCP/UDP port number. Changed only by the AdminController, which then
+    // starts another ProxyServer on the new port (the TargetServer stats are kept).
     port_number: u16,
 
+    // proxy_port_number of the config that could not be applied yet because
+    // used by another workdir (0 when none). See AdminController::apply_port_number.
+    requested_port_number: u16,
+
+    // Bind address, TLS and authentication. Changed only by the AdminController
+    // (same as port_number).
+    listen_config: ProxyListenConfig,
+
     // Request that processing on this port be abandon.
     //
     // This is a irreversible request.
@@ -28,9 +37,16 @@ pub struct InputPort {
     // must create another PortStates instance to re-use the same TCP/UDP port).
     deactivate_request: bool,
 
-    // Indicate if a proxy_server thread is started or not for this port.
+    // Indicate if a proxy_server thread is listening or not for this port.
     proxy_server_running: bool,
 
+    // Incremented for every ProxyServer started for this port. Allows to ignore
+    // the late reports of a ProxyServer being drained after a port change.
+    proxy_server_instance: u32,
+
+    // Why the proxy server is not listening (e.g. port already in use).
+    proxy_server_error: Option<String>,
+
     // Active Configuration.
     user_request_start: bool, // true when user_request == "start"
     proxy_enabled: bool,
@@ -71,8 +87,12 @@ impl InputPort {
             workdir_name,
             workdir_idx,
             port_number: workdir_config.proxy_port_number(),
+            requested_port_number: 0,
+            listen_config: workdir_config.proxy_listen_config().clone(),
             deactivate_request: false,
             proxy_server_running: false,
+            proxy_server_instance: 0,
+            proxy_server_error: None,
             user_request_start: workdir_config.is_user_request_start(),
             proxy_enabled: workdir_config.is_proxy_enabled(),
             target_servers: ManagedVec::new(),
@@ -101,8 +121,8 @@ impl InputPort {
                             "{} modify server {} rpc from {} to {}",
                             self.workdir_name,
                             config.alias,
-                            target_server.rpc(),
-                            rpc
+                            target_server.get_config().redact(&target_server.rpc()),
+                            config.redact(rpc)
                         );
                         target_server.set_rpc(rpc.clone());
                         target_server.stats_clear();
@@ -145,6 +165,35 @@ impl InputPort {
         self.port_number
     }
 
+    pub fn set_port_number(&mut self, port_number: u16) {
+        self.port_number = port_number;
+    }
+
+    pub fn requested_port_number(&self) -> u16 {
+        self.requested_port_number
+    }
+
+    pub fn set_requested_port_number(&mut self, port_number: u16) {
+        self.requested_port_number = port_number;
+    }
+
+    pub fn listen_config(&self) -> &ProxyListenConfig {
+        &self.listen_config
+    }
+
+    pub fn set_listen_config(&mut self, listen_config: ProxyListenConfig) {
+        self.listen_config = listen_config;
+    }
+
+    // URL and "Authorization" header for the requests of the daemon itself to this port.
+    pub fn local_url(&self) -> String {
+        self.listen_config.local_url(self.port_number)
+    }
+
+    pub fn local_auth_header(&self) -> Option<String> {
+        self.listen_config.auth_header()
+    }
+
     pub fn deactivate(&mut self) {
         self.deactivate_request = true;
     }
@@ -169,12 +218,121 @@ impl InputPort {
         self.proxy_enabled = value;
     }
 
-    pub fn report_proxy_server_starting(&mut self) {
-        self.proxy_server_running = true;
+    // Returns the instance to use for the other reports of that ProxyServer.
+    pub fn report_proxy_server_starting(&mut self) -> u32 {
+        self.proxy_server_instance = self.proxy_server_instance.wrapping_add(1);
+        self.proxy_server_running = false;
+        self.proxy_server_instance
     }
 
-    pub fn report_proxy_server_not_running(&mut self) {
-        self.proxy_server_running = false;
+    pub fn report_proxy_server_listening(&mut self, instance: u32) {
+        if instance == self.proxy_server_instance {
+            self.proxy_server_running = true;
+            self.proxy_server_error = None;
+        }
+    }
+
+    pub fn report_proxy_server_error(&mut self, instance: u32, error: String) {
+        if instance == self.proxy_server_instance {
+            self.proxy_server_running = false;
+            self.proxy_server_error = Some(error);
+        }
+    }
+
+    pub fn report_proxy_server_not_running(&mut self, instance: u32) {
+        if instance == self.proxy_server_instance {
+            self.proxy_server_running = false;
+        }
+    }
+
+    pub fn is_proxy_server_running(&self) -> bool {
+        self.proxy_server_running
+    }
+
+    pub fn proxy_server_error(&self) -> Option<&String> {
+        self.proxy_server_error.as_ref()
+    }
+
+    // For problems detected by the AdminController (e.g. port conflict with another workdir).
+    pub fn set_proxy_server_error(&mut self, error: Option<String>) {
+        self.proxy_server_error = error;
+    }
+
+    // Single word status ("OK" or "DOWN") of the multi-link RPC with
+    // some more details (e.g. "load-balanced", "initializing").
+    pub fn get_multi_link_status(&self) -> (String, String) {
+        let mut healthy_server_count: usize = 0;
+        let mut neutral_health_count: usize = 0;
+        let mut server_count: usize = 0;
+        // Only the selectable servers contribute to the multi-link status.
+        for (_, target_server) in self.target_servers.iter() {
+            if !target_server.is_selectable() {
+                continue;
+            }
+            server_count += 1;
+            let health_score = target_server.health_score();
+            if health_score.is_normal() && health_score.is_sign_positive() {
+                healthy_server_count += 1;
+            }
+            if health_score == 0.0 {
+                neutral_health_count += 1;
+            }
+        }
+        let load_distribution_depth = self.selection_vectors.first().map_or(0, |v| v.len());
+
+        Self::multi_link_status(
+            &self.workdir_name,
+            self.proxy_enabled,
+            self.user_request_start,
+            server_count,
+            healthy_server_count,
+            neutral_health_count,
+            load_distribution_depth,
+        )
+    }
+
+    // Also used by the getLinks API (which works on a copy of the stats).
+    pub fn multi_link_status(
+        workdir_name: &str,
+        proxy_enabled: bool,
+        user_request_start: bool,
+        server_count: usize,
+        healthy_server_count: usize,
+        neutral_health_count: usize,
+        load_distribution_depth: usize,
+    ) -> (String, String) {
+        let load_balance_str = if load_distribution_depth > 1 {
+            ", load-balanced".to_string()
+        } else {
+            String::new()
+        };
+
+        if !proxy_enabled {
+            ("DOWN".to_string(), "proxy not enabled".to_string())
+        } else if !user_request_start {
+            ("DOWN".to_string(), format!("{} not started", workdir_name))
+        } else if server_count == 0 {
+            (
+                "DOWN".to_string(),
+                "no selectable links in suibase.yaml".to_string(),
+            )
+        } else if neutral_health_count == server_count {
+            ("DOWN".to_string(), "initializing".to_string())
+        } else if healthy_server_count == 0 {
+            ("DOWN".to_string(), "no servers available".to_string())
+        } else if healthy_server_count * 100 / server_count > 50 {
+            let resp_info = if workdir_name == "localnet" {
+                load_balance_str
+            } else {
+                format!("protected{}", load_balance_str)
+            };
+            ("OK".to_string(), resp_info)
+        } else {
+            (
+                "OK".to_string(),
+                format!(">50% degraded{}", load_balance_str),
+            )
+        }
     }
 
     fn find_best_target_server(&self) -> Option<(TargetServerIdx, String)> {
@@ -185,6 +343,9 @@ impl InputPort {
         // TODO integrate the user priority in this logic when there is no health_score yet!
 
         for (i, target_server) in self.target_servers.iter() {
+            if !target_server.is_selectable() {
+                continue;
+            }
             let score = target_server.health_score();
             if score > best_score {
                 best_score = score;
@@ -290,9 +451,18 @@ impl InputPort {
         let mut ok_idx_vec: Vec<TargetServerIdx> = Vec::new();
         let mut best_latency_avg: f64 = f64::MAX;
         let mut best_latency_avg_idx: Option<TargetServerIdx> = None;
+        //
+        // Servers not selectable are excluded from all vectors (they may still be
+        // monitored). A selectable server that is not monitored has no health
+        // until user traffic reaches it, so it is not penalized while neutral.
         for (_, target_server) in target_servers.iter() {
+            if !target_server.is_selectable() {
+                continue;
+            }
             if let Some(idx) = target_server.idx() {
-                if target_server.stats.is_healthy() {
+                if !target_server.is_monitored() && target_server.health_score() == 0.0 {
+                    ok_idx_vec.push(idx);
+                } else if target_server.stats.is_healthy() {
                     if best_latency_avg_idx.is_none()
                         || target_server.stats.avg_latency_ms() < best_latency_avg
                     {
@@ -388,3 +558,91 @@ impl ManagedElement for InputPort {
         self.idx = index;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_types::SEND_FAILED_UNSPECIFIED_ERROR;
+
+    fn new_port(links: &[(&str, bool, bool)]) -> InputPort {
+        let mut input_port = InputPort::new(0, "localnet".to_string(), &WorkdirProxyConfig::new());
+        for (alias, selectable, monitored) in links {
+            let mut link = Link::new(alias.to_string(), format!("http://{}", alias));
+            link.selectable = *selectable;
+            link.monitored = *monitored;
+            input_port.add_target_server(&link);
+        }
+        input_port
+    }
+
+    fn stats<'a>(input_port: &'a mut InputPort, alias: &str) -> &'a mut ServerStats {
+        let (_, target_server) = input_port
+            .target_servers
+            .iter_mut()
+            .find(|(_, ts)| ts.alias() == alias)
+            .unwrap();
+        &mut target_server.stats
+    }
+
+    fn aliases(input_port: &InputPort, idx_vec: &[TargetServerIdx]) -> Vec<String> {
+        idx_vec
+            .iter()
+            .map(|idx| input_port.target_servers.get(*idx).unwrap().alias())
+            .collect()
+    }
+
+    #[test]
+    fn test_update_selection_vectors() {
+        let mut input_port = new_port(&[
+            ("fast", true, true),
+            ("slow", true, true),
+            ("down", true, true),
+            ("not_selectable", false, true),
+            ("unmonitored", true, false),
+            ("unmonitored_used", true, false),
+        ]);
+        let later = EpochTimestamp::now() + tokio::time::Duration::from_millis(1);
+        stats(&mut input_port, "fast").handle_latency_report(later, 10_000);
+        stats(&mut input_port, "slow").handle_latency_report(later, 100_000);
+        stats(&mut input_port, "down").handle_send_failed(later, SEND_FAILED_UNSPECIFIED_ERROR, 0);
+        // Healthy and fastest, but must never be selected.
+        stats(&mut input_port, "not_selectable").handle_latency_report(later, 1_000);
+        // Not monitored, but health known from the user traffic.
+        stats(&mut input_port, "unmonitored_used").handle_latency_report(later, 15_000);
+
+        input_port.update_selection_vectors();
+        assert_eq!(input_port.selection_vectors.len(), 2);
+        assert_eq!(
+            aliases(&input_port, &input_port.selection_vectors[0]),
+            vec!["fast", "unmonitored_used"]
+        );
+        // The neutral unmonitored server is not penalized (not with the down ones).
+        assert_eq!(
+            aliases(&input_port, &input_port.selection_vectors[1]),
+            vec!["slow", "unmonitored"]
+        );
+        assert_eq!(
+            aliases(&input_port, &input_port.selection_worst),
+            vec!["down"]
+        );
+
+        // No healthy server yet (e.g. on startup).
+        let mut input_port = new_port(&[
+            ("down", true, true),
+            ("not_selectable", false, true),
+            ("unmonitored", true, false),
+        ]);
+        let later = EpochTimestamp::now() + tokio::time::Duration::from_millis(1);
+        stats(&mut input_port, "down").handle_send_failed(later, SEND_FAILED_UNSPECIFIED_ERROR, 0);
+        input_port.update_selection_vectors();
+        assert_eq!(input_port.selection_vectors.len(), 1);
+        assert_eq!(
+            aliases(&input_port, &input_port.selection_vectors[0]),
+            vec!["unmonitored"]
+        );
+        assert_eq!(
+            aliases(&input_port, &input_port.selection_worst),
+            vec!["down"]
+        );
+    }
+}
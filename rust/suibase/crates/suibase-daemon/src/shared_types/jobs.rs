@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+
+use crate::basic_types::WorkdirIdx;
+
+// Maximum number of completed jobs kept in-memory (oldest are dropped first).
+pub const JOBS_MAX_COMPLETED: usize = 100;
+
+// Maximum number of output lines kept per job (the remaining are dropped, and
+// replaced by JOB_OUTPUT_TRUNCATED).
+pub const JOB_OUTPUT_MAX_LINES: usize = 10000;
+pub const JOB_OUTPUT_TRUNCATED: &str = "... (output truncated)";
+
+// Workdir commands that can be requested through the API (e.g. "localnet start").
+pub const WORKDIR_JOB_COMMANDS: [&str; 4] = ["start", "stop", "regen", "update"];
+
+// Default and maximum time allowed for a job to complete (it is killed on expiration).
+//
+// Generous because "update" may rebuild the Sui binaries.
+pub const JOB_DEFAULT_TIMEOUT_SECS: u64 = 3600;
+pub const JOB_MAX_TIMEOUT_SECS: u64 = 4 * 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+    TimedOut,
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Queued => "queued",
+            JobState::Running => "running",
+            JobState::Succeeded => "succeeded",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+            JobState::TimedOut => "timed_out",
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+impl std::fmt::Display for JobState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct JobOutputLine {
+    pub is_stderr: bool,
+    pub line: String,
+}
+
+// A workdir command executed by the ShellWorker of that workdir.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub job_id: u64,
+    pub workdir_idx: WorkdirIdx,
+    pub workdir: String,
+    pub command: String,
+    pub timeout_secs: u64,
+    pub state: JobState,
+    // stdout and stderr lines, in the order they were produced.
+    pub output: Vec<JobOutputLine>,
+    // Set only when the process exited by itself (not killed on timeout/cancel).
+    pub exit_code: Option<i32>,
+}
+
+impl Job {
+    pub fn add_output_line(&mut self, is_stderr: bool, line: String) {
+        if self.output.len() < JOB_OUTPUT_MAX_LINES {
+            self.output.push(JobOutputLine { is_stderr, line });
+        } else if self.output.len() == JOB_OUTPUT_MAX_LINES {
+            self.output.push(JobOutputLine {
+                is_stderr,
+                line: JOB_OUTPUT_TRUNCATED.to_string(),
+            });
+        }
+    }
+
+    // "<workdir> <command>" as typed on the command line.
+    pub fn command_line(&self) -> String {
+        format!("{} {}", self.workdir, self.command)
+    }
+}
+
+#[derive(Debug)]
+pub struct GlobalsJobsST {
+    // Key is the job_id (ascending order is the creation order).
+    jobs: BTreeMap<u64, Job>,
+    next_job_id: u64,
+}
+
+impl GlobalsJobsST {
+    pub fn new() -> Self {
+        Self {
+            jobs: BTreeMap::new(),
+            next_job_id: 1,
+        }
+    }
+
+    // Create a job in Queued state. Returns its job_id.
+    pub fn add_job(
+        &mut self,
+        workdir_idx: WorkdirIdx,
+        workdir: String,
+        command: String,
+        timeout_secs: u64,
+    ) -> u64 {
+        let job_id = self.next_job_id;
+        self.next_job_id += 1;
+        self.jobs.insert(
+            job_id,
+            Job {
+                job_id,
+                workdir_idx,
+                workdir,
+                command,
+                timeout_secs,
+                state: JobState::Queued,
+                output: Vec::new(),
+                exit_code: None,
+            },
+        );
+        self.trim_completed();
+        job_id
+    }
+
+    pub fn get(&self, job_id: u64) -> Option<&Job> {
+        self.jobs.get(&job_id)
+    }
+
+    pub fn get_mut(&mut self, job_id: u64) -> Option<&mut Job> {
+        self.jobs.get_mut(&job_id)
+    }
+
+    fn trim_completed(&mut self) {
+        let completed: Vec<u64> = self
+            .jobs
+            .values()
+            .filter(|job| job.state.is_completed())
+            .map(|job| job.job_id)
+            .collect();
+        if completed.len() > JOBS_MAX_COMPLETED {
+            for job_id in &completed[..completed.len() - JOBS_MAX_COMPLETED] {
+                self.jobs.remove(job_id);
+            }
+        }
+    }
+}
+
+impl Default for GlobalsJobsST {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -6,8 +6,11 @@
 pub(crate) use self::events::*;
 pub(crate) use self::globals::*;
 pub(crate) use self::input_port::*;
+pub(crate) use self::jobs::*;
 pub(crate) use self::packages::*;
+pub(crate) use self::secrets::*;
 pub(crate) use self::server_stats::*;
+pub(crate) use self::suibase_yaml::*;
 pub(crate) use self::target_server::*;
 pub(crate) use self::uuid::*;
 pub(crate) use self::workdirs::*;
@@ -15,8 +18,11 @@ pub(crate) use self::workdirs::*;
 mod events;
 mod globals;
 mod input_port;
+mod jobs;
 mod packages;
+mod secrets;
 mod server_stats;
+mod suibase_yaml;
 mod target_server;
 mod uuid;
 mod workdirs;
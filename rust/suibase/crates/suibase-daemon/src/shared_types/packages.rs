@@ -1,8 +1,22 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+
 use crate::{
-    api::{PackagesConfigResponse, Versioned},
-    basic_types::AutoSizeVec,
+    api::{MoveConfig, PackageInstance, PackagesConfigResponse, SuiObjectInstance, Versioned},
+    basic_types::{AutoSizeVec, WorkdirIdx},
 };
 
+use super::Globals;
+
+// Persisted in the .state directory of each workdir.
+const PACKAGES_CONFIG_FILE: &str = "packages-config.json";
+
+// MoveConfigs merged with mergeMoveConfigs (from_uuid -> into_uuid), so that
+// their published-data is not scanned back as a distinct MoveConfig.
+const PACKAGES_MERGED_FILE: &str = "packages-merged.json";
+
 #[derive(Debug, Clone)]
 pub struct PackagesWorkdirConfig {
     // Mostly store everything in the same struct
@@ -39,6 +53,399 @@ impl GlobalsPackagesConfigST {
             workdirs: AutoSizeVec::new(),
         }
     }
+
+    // Convert the "objectChanges" of a publish transaction into the init objects
+    // (everything created, including the UpgradeCap, but not the package itself).
+    pub fn parse_init_objects(
+        object_changes: &serde_json::Value,
+    ) -> Option<Vec<SuiObjectInstance>> {
+        let object_changes = object_changes.as_array()?;
+        let mut init_objects = Vec::new();
+        for change in object_changes {
+            if change["type"].as_str() != Some("created") {
+                continue;
+            }
+            let object_id = match change["objectId"].as_str() {
+                Some(object_id) => object_id,
+                None => continue,
+            };
+            let mut object = SuiObjectInstance::new(object_id.to_string());
+            if let Some(object_type) = change["objectType"].as_str() {
+                object.set_object_type(object_type.to_string());
+            }
+            if let Some(owner) = Self::owner_to_string(&change["owner"]) {
+                object.set_owner(owner);
+            }
+            init_objects.push(object);
+        }
+        Some(init_objects)
+    }
+
+    fn owner_to_string(owner: &serde_json::Value) -> Option<String> {
+        if let Some(owner) = owner.as_str() {
+            // e.g. "Immutable"
+            return Some(owner.to_lowercase());
+        }
+        if let Some(address) = owner["AddressOwner"].as_str() {
+            return Some(address.to_string());
+        }
+        if let Some(object_id) = owner["ObjectOwner"].as_str() {
+            return Some(format!("object:{}", object_id));
+        }
+        if owner.get("Shared").is_some() {
+            return Some("shared".to_string());
+        }
+        None
+    }
+
+    // Read the "objectChanges" from the publish-output.json of a publication directory.
+    pub fn read_object_changes(publication_path: &Path) -> Option<serde_json::Value> {
+        let publish_output = publication_path.join("publish-output.json");
+        let contents = std::fs::read_to_string(&publish_output).ok()?;
+        match serde_json::from_str::<serde_json::Value>(&contents) {
+            Ok(mut json) => match json["objectChanges"].take() {
+                serde_json::Value::Null => None,
+                object_changes => Some(object_changes),
+            },
+            Err(e) => {
+                log::warn!("Invalid JSON in {:?}: {}", publish_output, e);
+                None
+            }
+        }
+    }
+
+    // Create a PackageInstance, with as much details as can be found in
+    // the "objectChanges" of its publish (or upgrade) transaction.
+    pub fn new_package_instance(
+        package_id: String,
+        package_name: String,
+        package_timestamp: String,
+        object_changes: Option<&serde_json::Value>,
+    ) -> PackageInstance {
+        let mut package_instance =
+            PackageInstance::new(package_id, package_name, package_timestamp);
+        let object_changes = match object_changes {
+            Some(object_changes) => object_changes,
+            None => return package_instance,
+        };
+
+        package_instance.init_objects = Self::parse_init_objects(object_changes);
+
+        if let Some(object_changes) = object_changes.as_array() {
+            for change in object_changes {
+                let object_type = change["objectType"].as_str().unwrap_or_default();
+                match change["type"].as_str() {
+                    Some("published") => {
+                        package_instance.version = change["version"]
+                            .as_str()
+                            .and_then(|v| v.parse::<u64>().ok())
+                            .or_else(|| change["version"].as_u64());
+                    }
+                    // The UpgradeCap is created by the original publication
+                    // and mutated by every upgrade.
+                    Some("created") | Some("mutated")
+                        if object_type.ends_with("::package::UpgradeCap") =>
+                    {
+                        package_instance.upgrade_cap_id =
+                            change["objectId"].as_str().map(|s| s.to_string());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        package_instance
+    }
+
+    // (Re)compute the upgrade lineage of all the publications of a MoveConfig.
+    //
+    // Publications sharing the same UpgradeCap are versions of the same package.
+    pub fn update_lineage(move_config: &mut MoveConfig) {
+        let timestamp = |p: &PackageInstance| p.package_timestamp.parse::<u64>().unwrap_or(0);
+
+        // Oldest first, so the original publication of a lineage is seen first.
+        let mut instances: Vec<&mut PackageInstance> = move_config
+            .older_packages
+            .iter_mut()
+            .chain(move_config.latest_package.iter_mut())
+            .collect();
+        instances.sort_by_key(|p| timestamp(&**p));
+
+        let mut originals: HashMap<String, String> = HashMap::new(); // UpgradeCap id -> original id
+        for instance in instances {
+            let original_package_id = match &instance.upgrade_cap_id {
+                Some(cap_id) => originals
+                    .entry(cap_id.clone())
+                    .or_insert_with(|| instance.package_id.clone())
+                    .clone(),
+                None => instance.package_id.clone(),
+            };
+            instance.original_package_id = Some(original_package_id);
+        }
+
+        let latest = match &move_config.latest_package {
+            Some(latest) => latest,
+            None => {
+                move_config.original_package_id = None;
+                move_config.upgrade_cap_id = None;
+                move_config.version_chain.clear();
+                return;
+            }
+        };
+
+        let mut chain: Vec<&PackageInstance> = move_config
+            .older_packages
+            .iter()
+            .chain(std::iter::once(latest))
+            .filter(|p| p.original_package_id == latest.original_package_id)
+            .collect();
+        chain.sort_by_key(|p| (p.version.unwrap_or(0), timestamp(*p)));
+        let version_chain: Vec<String> = chain.iter().map(|p| p.package_id.clone()).collect();
+
+        move_config.original_package_id = latest.original_package_id.clone();
+        move_config.upgrade_cap_id = latest.upgrade_cap_id.clone();
+        move_config.version_chain = version_chain;
+    }
+
+    // Persist the move configs of a workdir (all published packages).
+    //
+    // This is a multi-thread safe call (will get the proper
+    // lock on the globals).
+    pub async fn save_to_file(globals: &Globals, workdir_idx: WorkdirIdx) -> Result<()> {
+        let state_path = {
+            let workdirs_guard = globals.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+            match workdirs.workdirs.get(workdir_idx) {
+                Some(workdir) => workdir.state_path().to_path_buf(),
+                None => return Err(anyhow::anyhow!("workdir_idx {} not found", workdir_idx)),
+            }
+        };
+
+        let move_configs = {
+            let globals_read_guard = globals.packages_config.read().await;
+            let globals = &*globals_read_guard;
+            globals
+                .workdirs
+                .get_if_some(workdir_idx)
+                .and_then(|config| config.ui.as_ref())
+                .and_then(|ui| ui.get_data().move_configs.clone())
+                .unwrap_or_default()
+        };
+
+        // Write a temporary file first, so the file is never partially written.
+        let contents = serde_json::to_string_pretty(&move_configs)?;
+        std::fs::create_dir_all(&state_path)?;
+        let path = state_path.join(PACKAGES_CONFIG_FILE);
+        let tmp_path = state_path.join(format!("{}.tmp", PACKAGES_CONFIG_FILE));
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    // Rebuild the move configs of a workdir from the persisted file, completed
+    // with whatever is found in the published-data directory.
+    //
+    // Called once per workdir on daemon startup. Does nothing if the globals
+    // were already initialized (e.g. a postPublish was already received).
+    pub async fn load_from_files(globals: &Globals, workdir_idx: WorkdirIdx) -> Result<()> {
+        let (workdir_path, state_path) = {
+            let workdirs_guard = globals.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+            match workdirs.workdirs.get(workdir_idx) {
+                Some(workdir) => (
+                    workdir.path().to_path_buf(),
+                    workdir.state_path().to_path_buf(),
+                ),
+                None => return Err(anyhow::anyhow!("workdir_idx {} not found", workdir_idx)),
+            }
+        };
+
+        let mut move_configs: HashMap<String, MoveConfig> =
+            match std::fs::read_to_string(state_path.join(PACKAGES_CONFIG_FILE)) {
+                Ok(contents) => serde_json::from_str(&contents)?,
+                Err(_) => HashMap::new(), // Optional, so no error if does not exists.
+            };
+
+        let merged_uuids = Self::read_merged_uuids(&state_path)?;
+        let scanned =
+            Self::scan_published_data(&workdir_path.join("published-data"), &merged_uuids);
+        Self::merge_move_configs(&mut move_configs, scanned);
+        for move_config in move_configs.values_mut() {
+            Self::update_lineage(move_config);
+        }
+
+        if move_configs.is_empty() {
+            return Ok(());
+        }
+
+        let mut globals_write_guard = globals.packages_config.write().await;
+        let globals = &mut *globals_write_guard;
+        let globals = globals.workdirs.get_mut(workdir_idx);
+        if globals.ui.is_none() {
+            let mut config_resp = PackagesConfigResponse::new();
+            config_resp.move_configs = Some(move_configs);
+            globals.ui = Some(Versioned::new(config_resp));
+            globals.last_ui_update = tokio::time::Instant::now();
+        }
+        Ok(())
+    }
+
+    // Record that the MoveConfig from_uuid was merged into into_uuid.
+    //
+    // This is a multi-thread safe call (will get the proper
+    // lock on the globals).
+    pub async fn save_merge_record(
+        globals: &Globals,
+        workdir_idx: WorkdirIdx,
+        from_uuid: &str,
+        into_uuid: &str,
+    ) -> Result<()> {
+        let state_path = {
+            let workdirs_guard = globals.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+            match workdirs.workdirs.get(workdir_idx) {
+                Some(workdir) => workdir.state_path().to_path_buf(),
+                None => return Err(anyhow::anyhow!("workdir_idx {} not found", workdir_idx)),
+            }
+        };
+
+        let mut merged_uuids = Self::read_merged_uuids(&state_path)?;
+        // Keep every record pointing to an existing MoveConfig (into_uuid may
+        // itself have been merged into from_uuid earlier).
+        merged_uuids.remove(into_uuid);
+        for target in merged_uuids.values_mut() {
+            if target == from_uuid {
+                *target = into_uuid.to_string();
+            }
+        }
+        merged_uuids.insert(from_uuid.to_string(), into_uuid.to_string());
+
+        // Write a temporary file first, so the file is never partially written.
+        let contents = serde_json::to_string_pretty(&merged_uuids)?;
+        std::fs::create_dir_all(&state_path)?;
+        let path = state_path.join(PACKAGES_MERGED_FILE);
+        let tmp_path = state_path.join(format!("{}.tmp", PACKAGES_MERGED_FILE));
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn read_merged_uuids(state_path: &Path) -> Result<HashMap<String, String>> {
+        match std::fs::read_to_string(state_path.join(PACKAGES_MERGED_FILE)) {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(_) => Ok(HashMap::new()), // Optional, so no error if does not exists.
+        }
+    }
+
+    // Scan the published-data/<package_name>/<uuid>/<timestamp>/ directories
+    // created by the publish script.
+    //
+    // The publications of a merged uuid are added to the MoveConfig it was
+    // merged into.
+    //
+    // The MoveConfig path is unknown from this scan (only the persisted
+    // file or a new publication can provide it).
+    fn scan_published_data(
+        published_data_path: &Path,
+        merged_uuids: &HashMap<String, String>,
+    ) -> HashMap<String, MoveConfig> {
+        let mut move_configs: HashMap<String, MoveConfig> = HashMap::new();
+
+        let subdirs = |path: &Path| -> Vec<(String, std::path::PathBuf)> {
+            let mut ret = Vec::new();
+            if let Ok(entries) = std::fs::read_dir(path) {
+                for entry in entries.flatten() {
+                    // Skip the "most-recent" symlinks.
+                    if let Ok(file_type) = entry.file_type() {
+                        if file_type.is_dir() {
+                            ret.push((
+                                entry.file_name().to_string_lossy().to_string(),
+                                entry.path(),
+                            ));
+                        }
+                    }
+                }
+            }
+            ret
+        };
+
+        for (package_name, package_path) in subdirs(published_data_path) {
+            for (package_uuid, uuid_path) in subdirs(&package_path) {
+                for (package_timestamp, publication_path) in subdirs(&uuid_path) {
+                    let package_id = match Self::read_package_id(&publication_path) {
+                        Some(package_id) => package_id,
+                        None => continue, // Publication likely failed.
+                    };
+                    let object_changes = Self::read_object_changes(&publication_path);
+                    let package_instance = Self::new_package_instance(
+                        package_id,
+                        package_name.clone(),
+                        package_timestamp,
+                        object_changes.as_ref(),
+                    );
+                    let package_uuid = merged_uuids.get(&package_uuid).unwrap_or(&package_uuid);
+                    let move_config = move_configs.entry(package_uuid.clone()).or_default();
+                    Self::add_package_instance(move_config, package_instance);
+                }
+            }
+        }
+        move_configs
+    }
+
+    // The package-id.json is a JSON array with a single package id.
+    fn read_package_id(publication_path: &Path) -> Option<String> {
+        let contents = std::fs::read_to_string(publication_path.join("package-id.json")).ok()?;
+        let json = serde_json::from_str::<serde_json::Value>(&contents).ok()?;
+        json[0].as_str().map(|s| s.to_string())
+    }
+
+    // Add all the publications of "from" into "into" (the path of "into" is kept).
+    pub fn merge_move_config(into: &mut MoveConfig, from: MoveConfig) {
+        let instances = from.latest_package.into_iter().chain(from.older_packages);
+        for package_instance in instances {
+            let is_known = into
+                .latest_package
+                .iter()
+                .chain(into.older_packages.iter())
+                .any(|known| known.package_id == package_instance.package_id);
+            if !is_known {
+                Self::add_package_instance(into, package_instance);
+            }
+        }
+        if into.path.is_none() {
+            into.path = from.path;
+        }
+        Self::update_lineage(into);
+    }
+
+    fn merge_move_configs(
+        move_configs: &mut HashMap<String, MoveConfig>,
+        scanned: HashMap<String, MoveConfig>,
+    ) {
+        for (package_uuid, scanned_config) in scanned {
+            let move_config = move_configs.entry(package_uuid).or_default();
+            Self::merge_move_config(move_config, scanned_config);
+        }
+    }
+
+    // Add a publication, keeping the most recent one as the latest_package.
+    fn add_package_instance(move_config: &mut MoveConfig, package_instance: PackageInstance) {
+        let timestamp = |p: &PackageInstance| p.package_timestamp.parse::<u64>().unwrap_or(0);
+        match move_config.latest_package.take() {
+            None => move_config.latest_package = Some(package_instance),
+            Some(latest) => {
+                if timestamp(&package_instance) > timestamp(&latest) {
+                    move_config.older_packages.push(latest);
+                    move_config.latest_package = Some(package_instance);
+                } else {
+                    move_config.older_packages.push(package_instance);
+                    move_config.latest_package = Some(latest);
+                }
+            }
+        }
+        move_config
+            .older_packages
+            .sort_by_key(|p| p.package_timestamp.parse::<u64>().unwrap_or(0));
+    }
 }
 
 impl Default for GlobalsPackagesConfigST {
@@ -46,3 +453,280 @@ impl Default for GlobalsPackagesConfigST {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+    use crate::shared_types::GlobalsWorkdirsST;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    // Globals with the workdirs in a temporary suibase home.
+    async fn test_globals(suibase_home: &Path) -> (Globals, WorkdirIdx) {
+        let globals = Globals::new();
+        *globals.workdirs.write().await =
+            GlobalsWorkdirsST::with_suibase_home(suibase_home.to_path_buf());
+        let workdir_idx =
+            GlobalsWorkdirsST::find_workdir_idx_by_name(&globals, &"localnet".to_string())
+                .await
+                .unwrap();
+        (globals, workdir_idx)
+    }
+
+    // Same files as written by the publish script.
+    fn write_publication(
+        suibase_home: &Path,
+        uuid: &str,
+        timestamp: &str,
+        package_id: &str,
+    ) -> PathBuf {
+        let path = suibase_home
+            .join("workdirs/localnet/published-data/demo")
+            .join(uuid)
+            .join(timestamp);
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(
+            path.join("package-id.json"),
+            json!([package_id]).to_string(),
+        )
+        .unwrap();
+        let publish_output = json!({ "objectChanges": [
+            { "type": "published", "packageId": package_id, "version": "1" },
+            { "type": "created", "objectId": "0xcap", "objectType": "0x2::package::UpgradeCap",
+              "owner": { "AddressOwner": "0xa11ce" } },
+        ]});
+        std::fs::write(path.join("publish-output.json"), publish_output.to_string()).unwrap();
+        path
+    }
+
+    async fn move_configs(
+        globals: &Globals,
+        workdir_idx: WorkdirIdx,
+    ) -> HashMap<String, MoveConfig> {
+        let globals_read_guard = globals.packages_config.read().await;
+        let globals = &*globals_read_guard;
+        globals
+            .workdirs
+            .get_if_some(workdir_idx)
+            .and_then(|config| config.ui.as_ref())
+            .and_then(|ui| ui.get_data().move_configs.clone())
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load_round_trip() {
+        let temp_dir = TempDir::new("packages");
+        let suibase_home = temp_dir.path();
+        let (globals, workdir_idx) = test_globals(suibase_home).await;
+
+        // Nothing to load.
+        GlobalsPackagesConfigST::load_from_files(&globals, workdir_idx)
+            .await
+            .unwrap();
+        assert!(move_configs(&globals, workdir_idx).await.is_empty());
+
+        // Publication found by scanning the published-data.
+        write_publication(suibase_home, "uuid1", "1000", "0xp1");
+        GlobalsPackagesConfigST::load_from_files(&globals, workdir_idx)
+            .await
+            .unwrap();
+        let configs = move_configs(&globals, workdir_idx).await;
+        let latest = configs["uuid1"].latest_package.as_ref().unwrap();
+        assert_eq!(latest.package_id, "0xp1");
+        assert_eq!(latest.package_name, "demo");
+        assert_eq!(latest.upgrade_cap_id.as_deref(), Some("0xcap"));
+        assert_eq!(configs["uuid1"].path, None);
+
+        // The path is only known from the persisted file.
+        {
+            let mut globals_write_guard = globals.packages_config.write().await;
+            let globals = &mut *globals_write_guard;
+            let ui = globals.workdirs.get_mut(workdir_idx).ui.as_mut().unwrap();
+            let mut config_resp = ui.get_data().clone();
+            config_resp
+                .move_configs
+                .as_mut()
+                .unwrap()
+                .get_mut("uuid1")
+                .unwrap()
+                .path = Some("/home/demo/move".to_string());
+            ui.set(&config_resp);
+        }
+        GlobalsPackagesConfigST::save_to_file(&globals, workdir_idx)
+            .await
+            .unwrap();
+        let state_path = suibase_home.join("workdirs/localnet/.state");
+        assert!(state_path.join(PACKAGES_CONFIG_FILE).exists());
+        assert!(!state_path
+            .join(format!("{}.tmp", PACKAGES_CONFIG_FILE))
+            .exists());
+
+        // Reload in a "new daemon" with one more publication on the filesystem.
+        write_publication(suibase_home, "uuid1", "2000", "0xp2");
+        let (globals, workdir_idx) = test_globals(suibase_home).await;
+        GlobalsPackagesConfigST::load_from_files(&globals, workdir_idx)
+            .await
+            .unwrap();
+        let configs = move_configs(&globals, workdir_idx).await;
+        assert_eq!(configs.len(), 1);
+        let config = &configs["uuid1"];
+        assert_eq!(config.path.as_deref(), Some("/home/demo/move"));
+        assert_eq!(config.latest_package.as_ref().unwrap().package_id, "0xp2");
+        let older: Vec<&str> = config
+            .older_packages
+            .iter()
+            .map(|p| p.package_id.as_str())
+            .collect();
+        assert_eq!(older, vec!["0xp1"]); // Not duplicated by the scan.
+    }
+
+    #[test]
+    fn test_scan_published_data() {
+        let temp_dir = TempDir::new("packages");
+        let suibase_home = temp_dir.path();
+        write_publication(suibase_home, "uuid1", "1000", "0xp1");
+        write_publication(suibase_home, "uuid2", "3000", "0xp3");
+        // Failed publication (no package-id.json) is ignored.
+        let failed = write_publication(suibase_home, "uuid2", "4000", "0xp4");
+        std::fs::remove_file(failed.join("package-id.json")).unwrap();
+
+        let published_data = suibase_home.join("workdirs/localnet/published-data");
+        let scanned =
+            GlobalsPackagesConfigST::scan_published_data(&published_data, &HashMap::new());
+        assert_eq!(scanned.len(), 2);
+        let latest = scanned["uuid2"].latest_package.as_ref().unwrap();
+        assert_eq!(latest.package_id, "0xp3");
+        assert_eq!(latest.package_timestamp, "3000");
+        assert!(scanned["uuid2"].older_packages.is_empty());
+        let init_objects = latest.init_objects.as_ref().unwrap();
+        assert_eq!(init_objects.len(), 1);
+        assert_eq!(init_objects[0].owner().map(|s| s.as_str()), Some("0xa11ce"));
+
+        // Publications of a merged uuid go into the MoveConfig it was merged into.
+        let merged_uuids = HashMap::from([("uuid1".to_string(), "uuid2".to_string())]);
+        let scanned = GlobalsPackagesConfigST::scan_published_data(&published_data, &merged_uuids);
+        assert_eq!(scanned.len(), 1);
+        assert_eq!(scanned["uuid2"].older_packages[0].package_id, "0xp1");
+    }
+
+    #[test]
+    fn test_add_package_instance() {
+        let instance = |id: &str, ts: &str| {
+            PackageInstance::new(id.to_string(), "demo".to_string(), ts.to_string())
+        };
+        let mut move_config = MoveConfig::default();
+        GlobalsPackagesConfigST::add_package_instance(&mut move_config, instance("0x2", "2000"));
+        GlobalsPackagesConfigST::add_package_instance(&mut move_config, instance("0x3", "3000"));
+        // Out of order (e.g. found by a scan) is kept as an older package.
+        GlobalsPackagesConfigST::add_package_instance(&mut move_config, instance("0x1", "1000"));
+
+        assert_eq!(move_config.latest_package.unwrap().package_id, "0x3");
+        let older: Vec<&str> = move_config
+            .older_packages
+            .iter()
+            .map(|p| p.package_id.as_str())
+            .collect();
+        assert_eq!(older, vec!["0x1", "0x2"]);
+    }
+
+    #[test]
+    fn test_new_package_instance() {
+        // Without objectChanges only the publish script data is known.
+        let instance = GlobalsPackagesConfigST::new_package_instance(
+            "0xp1".to_string(),
+            "demo".to_string(),
+            "1000".to_string(),
+            None,
+        );
+        assert_eq!(instance.init_objects, None);
+        assert_eq!(instance.version, None);
+        assert_eq!(instance.upgrade_cap_id, None);
+
+        let object_changes = json!([
+            { "type": "published", "packageId": "0xp2", "version": "2" },
+            { "type": "mutated", "objectId": "0xcap", "objectType": "0x2::package::UpgradeCap",
+              "owner": { "AddressOwner": "0xa11ce" } },
+            { "type": "created", "objectId": "0xo1", "objectType": "0xp2::demo::Shop",
+              "owner": { "Shared": { "initial_shared_version": 3 } } },
+            { "type": "mutated", "objectId": "0xgas", "objectType": "0x2::coin::Coin<0x2::sui::SUI>",
+              "owner": { "AddressOwner": "0xa11ce" } },
+        ]);
+        let instance = GlobalsPackagesConfigST::new_package_instance(
+            "0xp2".to_string(),
+            "demo".to_string(),
+            "2000".to_string(),
+            Some(&object_changes),
+        );
+        assert_eq!(instance.version, Some(2));
+        assert_eq!(instance.upgrade_cap_id.as_deref(), Some("0xcap"));
+        // Only the created objects (the mutated UpgradeCap is not an init object).
+        let init_objects = instance.init_objects.unwrap();
+        assert_eq!(init_objects.len(), 1);
+        assert_eq!(init_objects[0].object_id(), "0xo1");
+        assert_eq!(init_objects[0].owner().map(|s| s.as_str()), Some("shared"));
+
+        // Numeric version.
+        let object_changes = json!([{ "type": "published", "version": 1 }]);
+        let instance = GlobalsPackagesConfigST::new_package_instance(
+            "0xp1".to_string(),
+            "demo".to_string(),
+            "1000".to_string(),
+            Some(&object_changes),
+        );
+        assert_eq!(instance.version, Some(1));
+        assert_eq!(instance.init_objects, Some(vec![]));
+    }
+
+    #[test]
+    fn test_update_lineage() {
+        let instance = |id: &str, ts: &str, cap: Option<&str>, version: u64| {
+            let mut p = PackageInstance::new(id.to_string(), "demo".to_string(), ts.to_string());
+            p.upgrade_cap_id = cap.map(|s| s.to_string());
+            p.version = Some(version);
+            p
+        };
+
+        // No publication.
+        let mut move_config = MoveConfig {
+            version_chain: vec!["0xstale".to_string()],
+            ..Default::default()
+        };
+        GlobalsPackagesConfigST::update_lineage(&mut move_config);
+        assert!(move_config.version_chain.is_empty());
+        assert_eq!(move_config.original_package_id, None);
+
+        // 0xa1 upgraded to 0xa2 then 0xa3, with an unrelated re-publication
+        // (0xb1, new UpgradeCap) in between.
+        let mut move_config = MoveConfig {
+            older_packages: vec![
+                instance("0xa1", "1000", Some("0xcap_a"), 1),
+                instance("0xb1", "1500", Some("0xcap_b"), 1),
+                instance("0xa2", "2000", Some("0xcap_a"), 2),
+            ],
+            latest_package: Some(instance("0xa3", "3000", Some("0xcap_a"), 3)),
+            ..Default::default()
+        };
+        GlobalsPackagesConfigST::update_lineage(&mut move_config);
+        assert_eq!(move_config.original_package_id.as_deref(), Some("0xa1"));
+        assert_eq!(move_config.upgrade_cap_id.as_deref(), Some("0xcap_a"));
+        assert_eq!(move_config.version_chain, vec!["0xa1", "0xa2", "0xa3"]);
+        let originals: Vec<&str> = move_config
+            .older_packages
+            .iter()
+            .map(|p| p.original_package_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(originals, vec!["0xa1", "0xb1", "0xa1"]);
+
+        // Without UpgradeCap (e.g. unknown objectChanges), each publication is its own lineage.
+        let mut move_config = MoveConfig {
+            older_packages: vec![instance("0xc1", "1000", None, 1)],
+            latest_package: Some(instance("0xc2", "2000", None, 1)),
+            ..Default::default()
+        };
+        GlobalsPackagesConfigST::update_lineage(&mut move_config);
+        assert_eq!(move_config.original_package_id.as_deref(), Some("0xc2"));
+        assert_eq!(move_config.upgrade_cap_id, None);
+        assert_eq!(move_config.version_chain, vec!["0xc2"]);
+    }
+}
@@ -0,0 +1,211 @@
+// Values for the ${NAME} references in the links of the suibase.yaml files.
+//
+// A reference is resolved first from the secrets.yaml files (the one in the
+// workdir has precedence over the common one), then from the environment of
+// the daemon. This keeps API keys out of the suibase.yaml files.
+//
+// Example of ~/suibase/workdirs/mainnet/secrets.yaml:
+//
+//   MY_PROVIDER_KEY: "abcdef123456"
+//
+// Every value substituted in a link is treated as a secret (see Link::redact).
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+
+use super::{ConfigDiagnostic, ConfigDiagnosticLevel};
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct ConfigSecrets {
+    values: HashMap<String, String>,
+}
+
+impl std::fmt::Debug for ConfigSecrets {
+    // Never display the values.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<&String> = self.values.keys().collect();
+        names.sort();
+        f.debug_struct("ConfigSecrets")
+            .field("names", &names)
+            .finish()
+    }
+}
+
+impl ConfigSecrets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Merge the variables of a secrets.yaml file (a variable already defined
+    // is replaced). Problems are appended to diagnostics, without the values.
+    pub fn load_and_merge_from_file(
+        &mut self,
+        path: &str,
+        diagnostics: &mut Vec<ConfigDiagnostic>,
+    ) -> anyhow::Result<()> {
+        let contents = std::fs::read_to_string(path)?;
+
+        let mut add = |level: ConfigDiagnosticLevel, line: Option<usize>, message: String| {
+            diagnostics.push(ConfigDiagnostic {
+                level,
+                path: path.to_string(),
+                line,
+                message,
+            })
+        };
+
+        if let Ok(metadata) = std::fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                add(
+                    ConfigDiagnosticLevel::Warning,
+                    None,
+                    format!("readable by other users (do 'chmod 600 {}')", path),
+                );
+            }
+        }
+
+        let yaml: serde_yaml::Value = match serde_yaml::from_str(&contents) {
+            Ok(yaml) => yaml,
+            Err(e) => {
+                let line = e.location().map(|location| location.line());
+                add(
+                    ConfigDiagnosticLevel::Error,
+                    line,
+                    "YAML syntax error".to_string(),
+                );
+                return Err(e.into());
+            }
+        };
+        let mapping = match yaml {
+            serde_yaml::Value::Mapping(mapping) => mapping,
+            // Empty file (or only comments).
+            serde_yaml::Value::Null => return Ok(()),
+            _ => {
+                add(
+                    ConfigDiagnosticLevel::Error,
+                    Some(1),
+                    "expected a mapping of 'NAME: value'".to_string(),
+                );
+                return Ok(());
+            }
+        };
+
+        for (name, value) in mapping {
+            let name = match name.as_str() {
+                Some(name) if Self::is_valid_name(name) => name.to_string(),
+                _ => {
+                    add(
+                        ConfigDiagnosticLevel::Warning,
+                        None,
+                        format!("invalid variable name {:?} (ignored)", name),
+                    );
+                    continue;
+                }
+            };
+            let value = match value {
+                serde_yaml::Value::String(value) => value,
+                serde_yaml::Value::Number(value) => value.to_string(),
+                _ => {
+                    add(
+                        ConfigDiagnosticLevel::Error,
+                        None,
+                        format!("invalid value for '{}' (ignored): expected a string", name),
+                    );
+                    continue;
+                }
+            };
+            self.values.insert(name, value);
+        }
+        Ok(())
+    }
+
+    fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    fn lookup(&self, name: &str) -> Option<String> {
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    // Replace every ${NAME} reference in value.
+    //
+    // Returns the resolved string with the values substituted (for redaction),
+    // or a message describing the first problem (never includes a value).
+    pub fn interpolate(&self, value: &str) -> Result<(String, Vec<String>), String> {
+        let mut resolved = String::with_capacity(value.len());
+        let mut substituted: Vec<String> = Vec::new();
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            resolved.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let end = after
+                .find('}')
+                .ok_or_else(|| "unterminated '${'".to_string())?;
+            let name = &after[..end];
+            if !Self::is_valid_name(name) {
+                return Err(format!("invalid variable name '{}'", name));
+            }
+            let secret = self
+                .lookup(name)
+                .ok_or_else(|| format!("undefined variable '{}'", name))?;
+            resolved.push_str(&secret);
+            substituted.push(secret);
+            rest = &after[end + 1..];
+        }
+        resolved.push_str(rest);
+        Ok((resolved, substituted))
+    }
+
+    // Replace every ${NAME} reference with a placeholder. Allows to validate
+    // the rest of a URL before the values are known.
+    pub fn without_references(value: &str) -> String {
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${") {
+            match rest[start..].find('}') {
+                Some(end) => {
+                    out.push_str(&rest[..start]);
+                    out.push('x');
+                    rest = &rest[start + end + 1..];
+                }
+                None => break,
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate() {
+        let mut secrets = ConfigSecrets::new();
+        secrets
+            .values
+            .insert("KEY".to_string(), "abc123".to_string());
+
+        let (resolved, substituted) = secrets.interpolate("https://x.io/v1/${KEY}").unwrap();
+        assert_eq!(resolved, "https://x.io/v1/abc123");
+        assert_eq!(substituted, vec!["abc123".to_string()]);
+
+        assert_eq!(
+            secrets.interpolate("no reference").unwrap().0,
+            "no reference"
+        );
+        assert!(secrets
+            .interpolate("${SUIBASE_UNDEFINED_VAR_TEST}")
+            .is_err());
+        assert!(secrets.interpolate("${KEY").is_err());
+        assert!(!format!("{:?}", secrets).contains("abc123"));
+
+        assert_eq!(
+            ConfigSecrets::without_references("https://${HOST}/${KEY}"),
+            "https://x/x"
+        );
+    }
+}
@@ -0,0 +1,642 @@
+// Typed view of the keys of a suibase.yaml file that are relevant to the daemon.
+//
+// The file is first parsed as generic YAML, then each key is converted to its
+// expected type individually. That way, a single bad value is reported (and
+// ignored) without losing the rest of the file.
+//
+// Problems are accumulated as ConfigDiagnostic, with the line number of the
+// offending key when it can be located in the file.
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use super::ConfigSecrets;
+
+// Keys interpreted by the daemon.
+const DAEMON_KEYS: [&str; 14] = [
+    "network_type",
+    "proxy_enabled",
+    "links_overrides",
+    "proxy_port_number",
+    "proxy_bind_address",
+    "proxy_tls_cert",
+    "proxy_tls_key",
+    "proxy_auth_token",
+    "sui_faucet_enabled",
+    "sui_faucet_host_ip",
+    "sui_faucet_port",
+    "links",
+    "api_allowed_origins",
+    "api_auth_read_only",
+];
+
+// Keys meaningful only in the common suibase.yaml (they apply to the daemon API,
+// not to a workdir).
+pub const COMMON_ONLY_KEYS: [&str; 2] = ["api_allowed_origins", "api_auth_read_only"];
+
+// Keys meaningful only in the suibase.yaml of a workdir (ignored in the common file).
+pub const WORKDIR_ONLY_KEYS: [&str; 10] = [
+    "network_type",
+    "proxy_port_number",
+    "proxy_bind_address",
+    "proxy_tls_cert",
+    "proxy_tls_key",
+    "proxy_auth_token",
+    "sui_faucet_enabled",
+    "sui_faucet_host_ip",
+    "sui_faucet_port",
+    "links",
+];
+
+// Keys used only by the bash scripts (valid, but not interpreted by the daemon).
+const SCRIPTS_ONLY_KEYS: [&str; 19] = [
+    "auto_key_generation",
+    "terminal_color",
+    "initial_fund_per_address",
+    "sui_faucet_genesis_funding",
+    "sui_faucet_max_request_per_second",
+    "sui_faucet_num_coins",
+    "sui_faucet_coin_value",
+    "sui_faucet_request_buffer_size",
+    "sui_faucet_client_timeout_secs",
+    "default_repo_url",
+    "default_repo_branch",
+    "github_token",
+    "precompiled_bin",
+    "cargo_release",
+    "cargo_add_options",
+    "proxy_host_ip",
+    "suibase_api_port_number",
+    "add_private_keys",
+    "force_tag",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigDiagnosticLevel {
+    Error,
+    Warning,
+}
+
+impl ConfigDiagnosticLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfigDiagnosticLevel::Error => "error",
+            ConfigDiagnosticLevel::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub level: ConfigDiagnosticLevel,
+    pub path: String,
+    pub line: Option<usize>, // 1-based
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{}: {}: {}",
+                self.path,
+                line,
+                self.level.as_str(),
+                self.message
+            ),
+            None => write!(
+                f,
+                "{}: {}: {}",
+                self.path,
+                self.level.as_str(),
+                self.message
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuibaseYamlLink {
+    pub alias: String,
+    pub enabled: Option<bool>,
+    pub selectable: Option<bool>, // Default to 'enabled'.
+    pub monitored: Option<bool>,  // Default to 'enabled'.
+    pub rpc: Option<String>,
+    pub metrics: Option<String>,
+    pub ws: Option<String>,
+    pub priority: Option<u8>,
+    pub remove: Option<bool>, // Remove the alias defined by previous files.
+    // Custom request headers (e.g. "Authorization"). Names are lowercase once parsed.
+    pub headers: Option<BTreeMap<String, String>>,
+    // Anything else (reported as unknown).
+    #[serde(flatten)]
+    pub unknown_keys: HashMap<String, serde_yaml::Value>,
+    // Line of the alias in the file (1-based).
+    #[serde(skip)]
+    pub line: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub struct SuibaseYaml {
+    // None when not specified in the file.
+    pub network_type: Option<String>, // "local", "remote" or "switchable"
+    pub proxy_enabled: Option<bool>,
+    pub links_overrides: Option<bool>,
+    pub proxy_port_number: Option<u16>,
+    pub proxy_bind_address: Option<std::net::IpAddr>,
+    pub proxy_tls_cert: Option<String>,   // Path of a PEM file.
+    pub proxy_tls_key: Option<String>,    // Path of a PEM file.
+    pub proxy_auth_token: Option<String>, // Can have ${NAME} references.
+    pub sui_faucet_enabled: Option<bool>,
+    pub sui_faucet_host_ip: Option<String>,
+    pub sui_faucet_port: Option<u16>,
+    // Valid links only, in the order of the file.
+    pub links: Vec<SuibaseYamlLink>,
+    // Daemon API access (common file only).
+    pub api_allowed_origins: Option<Vec<String>>,
+    pub api_auth_read_only: Option<bool>,
+    // Line of each top-level key in the file (1-based).
+    pub key_lines: HashMap<String, usize>,
+}
+
+impl SuibaseYaml {
+    // Errors are returned only when the file can't be used at all (e.g. YAML syntax error).
+    //
+    // All problems (including the returned error) are appended to diagnostics.
+    pub fn parse(
+        contents: &str,
+        path: &str,
+        common: bool,
+        diagnostics: &mut Vec<ConfigDiagnostic>,
+    ) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            contents,
+            path,
+            diagnostics,
+        };
+        parser.parse(common)
+    }
+}
+
+struct Parser<'a> {
+    contents: &'a str,
+    path: &'a str,
+    diagnostics: &'a mut Vec<ConfigDiagnostic>,
+}
+
+impl<'a> Parser<'a> {
+    fn add(&mut self, level: ConfigDiagnosticLevel, line: Option<usize>, message: String) {
+        self.diagnostics.push(ConfigDiagnostic {
+            level,
+            path: self.path.to_string(),
+            line,
+            message,
+        });
+    }
+
+    fn error(&mut self, line: Option<usize>, message: String) {
+        self.add(ConfigDiagnosticLevel::Error, line, message);
+    }
+
+    fn warning(&mut self, line: Option<usize>, message: String) {
+        self.add(ConfigDiagnosticLevel::Warning, line, message);
+    }
+
+    // Line of a top-level "key:" (serde_yaml::Value does not keep locations).
+    fn key_line(&self, key: &str) -> Option<usize> {
+        self.contents
+            .lines()
+            .position(|line| {
+                line.strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start().starts_with(':'))
+            })
+            .map(|idx| idx + 1)
+    }
+
+    // Line of the nth "alias: <alias>" (0 for the first one).
+    fn alias_line(&self, alias: &str, occurrence: usize) -> Option<usize> {
+        self.contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim_start().trim_start_matches('-').trim_start();
+                line.strip_prefix("alias")
+                    .map(|rest| rest.trim_start())
+                    .and_then(|rest| rest.strip_prefix(':'))
+                    .is_some_and(|value| {
+                        value.trim().trim_matches(|c| c == '"' || c == '\'') == alias
+                    })
+            })
+            .nth(occurrence)
+            .map(|(idx, _)| idx + 1)
+    }
+
+    fn get_typed<T: DeserializeOwned>(
+        &mut self,
+        mapping: &serde_yaml::Mapping,
+        key: &str,
+    ) -> Option<T> {
+        let value = mapping.get(key)?;
+        if value.is_null() {
+            return None;
+        }
+        match serde_yaml::from_value::<T>(value.clone()) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                self.error(
+                    self.key_line(key),
+                    format!("invalid value for '{}' (ignored): {}", key, e),
+                );
+                None
+            }
+        }
+    }
+
+    fn get_port(&mut self, mapping: &serde_yaml::Mapping, key: &str) -> Option<u16> {
+        match self.get_typed::<u16>(mapping, key) {
+            Some(0) => {
+                self.error(
+                    self.key_line(key),
+                    format!("invalid value for '{}' (ignored): port 0", key),
+                );
+                None
+            }
+            port => port,
+        }
+    }
+
+    // Returns the url if valid for one of the schemes.
+    fn validate_url(
+        &mut self,
+        line: Option<usize>,
+        alias: &str,
+        key: &str,
+        url: Option<String>,
+        schemes: &[&str],
+    ) -> Option<String> {
+        let url = url?;
+        // The ${NAME} references are resolved later (see ConfigSecrets).
+        match reqwest::Url::parse(&ConfigSecrets::without_references(&url)) {
+            Ok(parsed) => {
+                if !schemes.contains(&parsed.scheme()) {
+                    self.error(
+                        line,
+                        format!(
+                            "link '{}' {} '{}' has unsupported scheme '{}' (expected {})",
+                            alias,
+                            key,
+                            url,
+                            parsed.scheme(),
+                            schemes.join(" or ")
+                        ),
+                    );
+                    None
+                } else if parsed.host_str().is_none() {
+                    self.error(
+                        line,
+                        format!("link '{}' {} '{}' has no host", alias, key, url),
+                    );
+                    None
+                } else {
+                    Some(url)
+                }
+            }
+            Err(e) => {
+                self.error(
+                    line,
+                    format!("link '{}' {} '{}' is invalid: {}", alias, key, url, e),
+                );
+                None
+            }
+        }
+    }
+
+    // Returns the headers with a valid name (normalized to lowercase).
+    fn validate_headers(
+        &mut self,
+        line: Option<usize>,
+        alias: &str,
+        headers: BTreeMap<String, String>,
+    ) -> BTreeMap<String, String> {
+        let mut valid = BTreeMap::new();
+        for (name, value) in headers {
+            match reqwest::header::HeaderName::from_bytes(name.as_bytes()) {
+                Ok(header_name) => {
+                    valid.insert(header_name.as_str().to_string(), value);
+                }
+                Err(_) => self.error(
+                    line,
+                    format!(
+                        "link '{}' header '{}' is not a valid header name (ignored)",
+                        alias, name
+                    ),
+                ),
+            }
+        }
+        valid
+    }
+
+    fn parse(&mut self, common: bool) -> anyhow::Result<SuibaseYaml> {
+        let yaml: serde_yaml::Value = match serde_yaml::from_str(self.contents) {
+            Ok(yaml) => yaml,
+            Err(e) => {
+                let line = e.location().map(|location| location.line());
+                self.error(line, format!("YAML syntax error: {}", e));
+                return Err(e.into());
+            }
+        };
+
+        let mut config = SuibaseYaml::default();
+        let mapping = match yaml {
+            serde_yaml::Value::Mapping(mapping) => mapping,
+            // Empty file (or only comments).
+            serde_yaml::Value::Null => return Ok(config),
+            _ => {
+                self.error(Some(1), "expected a mapping of 'key: value'".to_string());
+                return Ok(config);
+            }
+        };
+
+        // Report unknown keys (likely typos) and misplaced keys.
+        for key in mapping.keys() {
+            let key = match key.as_str() {
+                Some(key) => key,
+                None => {
+                    self.warning(None, format!("non-string key {:?} (ignored)", key));
+                    continue;
+                }
+            };
+            if let Some(line) = self.key_line(key) {
+                config.key_lines.insert(key.to_string(), line);
+            }
+            if common && WORKDIR_ONLY_KEYS.contains(&key) {
+                self.warning(
+                    self.key_line(key),
+                    format!(
+                        "'{}' is workdir specific and ignored in the common suibase.yaml",
+                        key
+                    ),
+                );
+            } else if !common && COMMON_ONLY_KEYS.contains(&key) {
+                self.warning(
+                    self.key_line(key),
+                    format!(
+                        "'{}' is used only in the common suibase.yaml (ignored)",
+                        key
+                    ),
+                );
+            } else if !DAEMON_KEYS.contains(&key) && !SCRIPTS_ONLY_KEYS.contains(&key) {
+                self.warning(
+                    self.key_line(key),
+                    format!("unknown key '{}' (ignored)", key),
+                );
+            }
+        }
+
+        // proxy_enabled can be "true", "false" or "dev" for testing.
+        //
+        // "dev" is similar to "true" but allows for foreground execution
+        // of the suibase-daemon... only the bash scripts care for this.
+        match mapping.get("proxy_enabled") {
+            Some(serde_yaml::Value::Bool(proxy_enabled)) => {
+                config.proxy_enabled = Some(*proxy_enabled);
+            }
+            Some(serde_yaml::Value::String(proxy_enabled)) => {
+                if !["true", "false", "dev"].contains(&proxy_enabled.as_str()) {
+                    self.warning(
+                        self.key_line("proxy_enabled"),
+                        format!(
+                            "unexpected proxy_enabled '{}' (expected true, false or dev)",
+                            proxy_enabled
+                        ),
+                    );
+                }
+                config.proxy_enabled = Some(proxy_enabled != "false");
+            }
+            Some(serde_yaml::Value::Null) | None => {}
+            Some(_) => {
+                self.error(
+                    self.key_line("proxy_enabled"),
+                    "invalid value for 'proxy_enabled' (ignored): expected true, false or dev"
+                        .to_string(),
+                );
+            }
+        }
+
+        config.links_overrides = self.get_typed(&mapping, "links_overrides");
+
+        // Daemon API access (meaningful only in the common file, warned above).
+        if common {
+            config.api_allowed_origins = self.get_typed(&mapping, "api_allowed_origins");
+            config.api_auth_read_only = self.get_typed(&mapping, "api_auth_read_only");
+        }
+
+        // Remaining variables do not make sense in common files (warned above).
+        if common {
+            return Ok(config);
+        }
+
+        config.network_type = self.get_typed(&mapping, "network_type");
+        if let Some(network_type) = &config.network_type {
+            if !["local", "remote", "switchable"].contains(&network_type.as_str()) {
+                self.error(
+                    self.key_line("network_type"),
+                    format!(
+                        "invalid network_type '{}' (ignored): expected local, remote or switchable",
+                        network_type
+                    ),
+                );
+                config.network_type = None;
+            }
+        }
+
+        config.proxy_port_number = self.get_port(&mapping, "proxy_port_number");
+
+        // How the proxy port accepts connections (see ProxyListenConfig).
+        if let Some(bind_address) = self.get_typed::<String>(&mapping, "proxy_bind_address") {
+            match bind_address.parse::<std::net::IpAddr>() {
+                Ok(bind_address) => config.proxy_bind_address = Some(bind_address),
+                Err(_) => self.error(
+                    self.key_line("proxy_bind_address"),
+                    format!(
+                        "invalid IP address '{}' for 'proxy_bind_address' (ignored)",
+                        bind_address
+                    ),
+                ),
+            }
+        }
+        config.proxy_tls_cert = self.get_typed(&mapping, "proxy_tls_cert");
+        config.proxy_tls_key = self.get_typed(&mapping, "proxy_tls_key");
+        config.proxy_auth_token = self.get_typed(&mapping, "proxy_auth_token");
+
+        // Faucet config (used only for the status of localnet).
+        config.sui_faucet_enabled = self.get_typed(&mapping, "sui_faucet_enabled");
+        config.sui_faucet_host_ip = self.get_typed(&mapping, "sui_faucet_host_ip");
+        if let Some(host_ip) = &config.sui_faucet_host_ip {
+            if host_ip.parse::<std::net::IpAddr>().is_err() {
+                self.error(
+                    self.key_line("sui_faucet_host_ip"),
+                    format!(
+                        "invalid IP address '{}' for 'sui_faucet_host_ip' (ignored)",
+                        host_ip
+                    ),
+                );
+                config.sui_faucet_host_ip = None;
+            }
+        }
+        config.sui_faucet_port = self.get_port(&mapping, "sui_faucet_port");
+
+        match mapping.get("links") {
+            Some(serde_yaml::Value::Sequence(links)) => self.parse_links(links, &mut config),
+            Some(serde_yaml::Value::Null) | None => {}
+            Some(_) => {
+                self.error(
+                    self.key_line("links"),
+                    "invalid value for 'links' (ignored): expected a list".to_string(),
+                );
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn parse_links(&mut self, links: &[serde_yaml::Value], config: &mut SuibaseYaml) {
+        let links_line = self.key_line("links");
+        // Number of time each alias was seen (to locate duplicates).
+        let mut alias_occurrences: HashMap<String, usize> = HashMap::new();
+
+        for (link_idx, link) in links.iter().enumerate() {
+            let mut link = match serde_yaml::from_value::<SuibaseYamlLink>(link.clone()) {
+                Ok(link) => link,
+                Err(e) => {
+                    // Try to locate the link by its alias (when at least that is valid).
+                    let line = link
+                        .get("alias")
+                        .and_then(|alias| alias.as_str())
+                        .and_then(|alias| self.alias_line(alias, 0))
+                        .or(links_line);
+                    self.error(
+                        line,
+                        format!("invalid link #{} (ignored): {}", link_idx + 1, e),
+                    );
+                    continue;
+                }
+            };
+
+            let occurrence = alias_occurrences.entry(link.alias.clone()).or_insert(0);
+            let line = self.alias_line(&link.alias, *occurrence).or(links_line);
+            *occurrence += 1;
+            if *occurrence > 1 {
+                let first_line = self.alias_line(&link.alias, 0);
+                self.warning(
+                    line,
+                    match first_line {
+                        Some(first_line) => format!(
+                            "duplicate alias '{}' is merged into the one on line {}",
+                            link.alias, first_line
+                        ),
+                        None => format!(
+                            "duplicate alias '{}' is merged into a previous one",
+                            link.alias
+                        ),
+                    },
+                );
+            }
+
+            let mut unknown_keys: Vec<&String> = link.unknown_keys.keys().collect();
+            unknown_keys.sort();
+            for key in unknown_keys {
+                self.warning(
+                    line,
+                    format!("link '{}' has unknown key '{}' (ignored)", link.alias, key),
+                );
+            }
+
+            let alias = link.alias.clone();
+            link.rpc = self.validate_url(line, &alias, "rpc", link.rpc.take(), &["http", "https"]);
+            link.metrics = self.validate_url(
+                line,
+                &alias,
+                "metrics",
+                link.metrics.take(),
+                &["http", "https"],
+            );
+            link.ws = self.validate_url(line, &alias, "ws", link.ws.take(), &["ws", "wss"]);
+            if let Some(headers) = link.headers.take() {
+                link.headers = Some(self.validate_headers(line, &alias, headers));
+            }
+
+            link.line = line;
+            config.links.push(link);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_with_diagnostics() {
+        let contents = "\
+proxy_enabled: true
+proxy_port_number: 70000
+proxy_enabld: false
+links:
+  - alias: \"good\"
+    rpc: \"https://good.io:443\"
+    ws: \"https://good.io:443\"
+  - alias: \"good\"
+    rpc: \"http://other.io\"
+    prority: 10
+  - rpc: \"http://noalias.io\"
+";
+        let mut diagnostics = Vec::new();
+        let config = SuibaseYaml::parse(contents, "suibase.yaml", false, &mut diagnostics).unwrap();
+
+        assert_eq!(config.proxy_enabled, Some(true));
+        assert_eq!(config.proxy_port_number, None);
+        assert_eq!(config.links.len(), 2);
+        assert_eq!(config.links[0].ws, None);
+        assert_eq!(config.links[1].rpc.as_deref(), Some("http://other.io"));
+        assert_eq!(config.links[0].line, Some(5));
+        assert_eq!(config.links[1].line, Some(8));
+        assert_eq!(config.key_lines["proxy_enabled"], 1);
+        assert_eq!(config.key_lines["links"], 4);
+
+        let lines: Vec<(ConfigDiagnosticLevel, Option<usize>)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.level, diagnostic.line))
+            .collect();
+        assert!(lines.contains(&(ConfigDiagnosticLevel::Warning, Some(3)))); // proxy_enabld
+        assert!(lines.contains(&(ConfigDiagnosticLevel::Error, Some(2)))); // port range
+        assert!(lines.contains(&(ConfigDiagnosticLevel::Error, Some(5)))); // ws scheme
+        assert!(lines.contains(&(ConfigDiagnosticLevel::Warning, Some(8)))); // duplicate, prority
+        assert!(lines.contains(&(ConfigDiagnosticLevel::Error, Some(4)))); // missing alias
+        assert_eq!(diagnostics.len(), 6);
+    }
+
+    #[test]
+    fn test_parse_common_file() {
+        let contents = "proxy_enabled: \"dev\"\nproxy_port_number: 44340\n";
+        let mut diagnostics = Vec::new();
+        let config = SuibaseYaml::parse(contents, "common", true, &mut diagnostics).unwrap();
+
+        assert_eq!(config.proxy_enabled, Some(true));
+        assert_eq!(config.proxy_port_number, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, ConfigDiagnosticLevel::Warning);
+        assert_eq!(diagnostics[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_parse_syntax_error() {
+        let mut diagnostics = Vec::new();
+        let result = SuibaseYaml::parse("links: [\n", "bad", false, &mut diagnostics);
+        assert!(result.is_err());
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].line.is_some());
+    }
+}
@@ -1,5 +1,7 @@
 use crate::basic_types::*;
 
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
 use crate::shared_types::Link;
 use crate::shared_types::ServerStats;
 
@@ -7,6 +9,7 @@ use crate::shared_types::ServerStats;
 pub struct TargetServer {
     idx: Option<ManagedVecUSize>,
     config: Link,
+    headers: HeaderMap, // Built from config.headers (values flagged as sensitive).
     pub stats: ServerStats,
 }
 
@@ -14,13 +17,36 @@ impl TargetServer {
     pub fn new(config: Link) -> Self {
         // alias is the 'key' and can't be changed after construction.
         let alias = config.alias.clone();
+        let headers = Self::build_headers(&config);
         Self {
             idx: None,
             config,
+            headers,
             stats: ServerStats::new(alias),
         }
     }
 
+    fn build_headers(config: &Link) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            // Already validated when loading the config.
+            if let (Ok(name), Ok(mut value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                // Never displayed by Debug.
+                value.set_sensitive(true);
+                headers.insert(name, value);
+            }
+        }
+        headers
+    }
+
+    // Custom headers to add to every request forwarded to this server.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
     pub fn alias(&self) -> String {
         self.config.alias.clone()
     }
@@ -36,6 +62,11 @@ impl TargetServer {
             .map_or_else(String::new, |rpc| rpc.clone())
     }
 
+    // For display (secrets masked).
+    pub fn rpc_redacted(&self) -> String {
+        self.config.redact(&self.rpc())
+    }
+
     pub fn set_rpc(&mut self, rpc: String) {
         self.config.rpc = Some(rpc);
     }
@@ -57,6 +88,7 @@ impl TargetServer {
     }
 
     pub fn set_config(&mut self, config: Link) {
+        self.headers = Self::build_headers(&config);
         self.config = config
     }
 }
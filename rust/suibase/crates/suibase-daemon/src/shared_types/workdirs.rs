@@ -6,7 +6,8 @@
 // the user filesystem (for relevant files only).
 //
 use home::home_dir;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
 use crate::basic_types::*;
 
@@ -14,14 +15,22 @@ use std::path::{Path, PathBuf};
 
 use anyhow::Result;
 
-use super::Globals;
+use super::{ConfigDiagnostic, ConfigDiagnosticLevel, ConfigSecrets, Globals, SuibaseYaml};
 
 // List of workdir planned to be always supported.
 pub const WORKDIRS_KEYS: [&str; 4] = ["mainnet", "testnet", "devnet", "localnet"];
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+// Directories in ~/suibase/workdirs that are never a custom workdir.
+//
+// Any other sub-directory is a custom workdir (e.g. a private network or a
+// second localnet) configured by its own suibase.yaml (there is no default).
+const NOT_CUSTOM_WORKDIRS: [&str; 3] = ["common", "active", "cargobin"];
+
+#[derive(Eq, PartialEq, Clone)]
 pub struct Link {
     // A link in a suibase.yaml file.
+    //
+    // The ${NAME} references are already resolved (see ConfigSecrets).
     pub alias: String,
     pub selectable: bool,
     pub monitored: bool,
@@ -29,6 +38,8 @@ pub struct Link {
     pub metrics: Option<String>,
     pub ws: Option<String>,
     pub priority: u8,
+    pub headers: BTreeMap<String, String>, // Added to the requests forwarded to rpc.
+    pub secrets: Vec<String>,              // Values substituted for ${NAME} references.
 }
 
 impl Link {
@@ -41,6 +52,184 @@ impl Link {
             metrics: None,
             ws: None,
             priority: u8::MAX,
+            headers: BTreeMap::new(),
+            secrets: Vec::new(),
+        }
+    }
+
+    // Mask the secrets within a string (e.g. the rpc URL) before displaying it.
+    pub fn redact(&self, text: &str) -> String {
+        let mut secrets: Vec<&String> = self.secrets.iter().filter(|s| !s.is_empty()).collect();
+        // Longest first, in case a secret contains another.
+        secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+        let mut text = text.to_string();
+        for secret in secrets {
+            text = text.replace(secret.as_str(), "****");
+        }
+        text
+    }
+}
+
+impl std::fmt::Debug for Link {
+    // Secrets are masked and the header values are never displayed.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redact = |value: &Option<String>| value.as_ref().map(|value| self.redact(value));
+        let header_names: Vec<&String> = self.headers.keys().collect();
+        f.debug_struct("Link")
+            .field("alias", &self.alias)
+            .field("selectable", &self.selectable)
+            .field("monitored", &self.monitored)
+            .field("rpc", &redact(&self.rpc))
+            .field("metrics", &redact(&self.metrics))
+            .field("ws", &redact(&self.ws))
+            .field("priority", &self.priority)
+            .field("headers", &header_names)
+            .finish()
+    }
+}
+
+// How a proxy port accepts connections (proxy_bind_address, proxy_tls_cert,
+// proxy_tls_key and proxy_auth_token in suibase.yaml).
+//
+// The default is plain HTTP on the loopback interface without authentication.
+#[derive(Eq, PartialEq, Clone)]
+pub struct ProxyListenConfig {
+    pub bind_address: IpAddr,
+    pub tls_cert: Option<String>, // TLS is enabled when both are set.
+    pub tls_key: Option<String>,
+    // Expected "Authorization: Bearer <token>" (${NAME} references resolved).
+    //
+    // An empty token (e.g. an undefined ${NAME}) rejects every request.
+    pub auth_token: Option<String>,
+    auth_token_template: Option<String>, // As in the file.
+}
+
+impl Default for ProxyListenConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            tls_cert: None,
+            tls_key: None,
+            auth_token: None,
+            auth_token_template: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for ProxyListenConfig {
+    // Never display the token.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProxyListenConfig")
+            .field("bind_address", &self.bind_address)
+            .field("tls_cert", &self.tls_cert)
+            .field("tls_key", &self.tls_key)
+            .field("auth_token", &self.auth_token.as_ref().map(|_| "****"))
+            .finish()
+    }
+}
+
+impl ProxyListenConfig {
+    pub fn is_tls(&self) -> bool {
+        self.tls_cert.is_some() && self.tls_key.is_some()
+    }
+
+    // URL for the requests that the daemon itself sends to the proxy port
+    // (e.g. health checks). Uses loopback when bound to all interfaces.
+    pub fn local_url(&self, port_number: u16) -> String {
+        let ip = match self.bind_address {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        let scheme = if self.is_tls() { "https" } else { "http" };
+        format!("{}://{}", scheme, SocketAddr::new(ip, port_number))
+    }
+
+    // Value of the "Authorization" header for these same requests.
+    pub fn auth_header(&self) -> Option<String> {
+        self.auth_token
+            .as_ref()
+            .map(|token| format!("Bearer {}", token))
+    }
+}
+
+// Members of a link as merged from the suibase.yaml files loaded so far
+// (None when not specified by any of them).
+//
+// Values are as in the files (${NAME} references not resolved).
+#[derive(Default, Eq, PartialEq, Clone)]
+struct MergedLink {
+    enabled: Option<bool>,
+    selectable: Option<bool>,
+    monitored: Option<bool>,
+    rpc: Option<String>,
+    metrics: Option<String>,
+    ws: Option<String>,
+    priority: Option<u8>,
+    headers: BTreeMap<String, String>, // Merged by header name.
+}
+
+impl std::fmt::Debug for MergedLink {
+    // A header value might be a literal secret, so display only the names.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header_names: Vec<&String> = self.headers.keys().collect();
+        f.debug_struct("MergedLink")
+            .field("enabled", &self.enabled)
+            .field("selectable", &self.selectable)
+            .field("monitored", &self.monitored)
+            .field("rpc", &self.rpc)
+            .field("metrics", &self.metrics)
+            .field("ws", &self.ws)
+            .field("priority", &self.priority)
+            .field("headers", &header_names)
+            .finish()
+    }
+}
+
+impl MergedLink {
+    // Merge a member. Returns true when the value was specified.
+    fn merge<T>(member: &mut Option<T>, value: Option<T>) -> bool {
+        match value {
+            Some(value) => {
+                *member = Some(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn to_link(&self, alias: &str) -> Link {
+        // Default of "enabled" is true. Allow the user to disable a single link.
+        //
+        // "selectable" and "monitored" allow finer control (e.g. monitor a
+        // server without routing user traffic to it). Both default to "enabled".
+        let enabled = self.enabled.unwrap_or(true);
+        Link {
+            alias: alias.to_string(),
+            selectable: self.selectable.unwrap_or(enabled),
+            monitored: self.monitored.unwrap_or(enabled),
+            rpc: self.rpc.clone(),
+            metrics: self.metrics.clone(),
+            ws: self.ws.clone(),
+            priority: self.priority.unwrap_or(u8::MAX),
+            headers: self.headers.clone(),
+            secrets: Vec::new(),
+        }
+    }
+}
+
+// Where a value of the effective config was set.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ConfigSource {
+    path: String,
+    line: Option<usize>, // 1-based
+}
+
+impl ConfigSource {
+    fn new(path: &str, line: Option<usize>) -> Self {
+        Self {
+            path: path.to_string(),
+            line,
         }
     }
 }
@@ -51,10 +240,25 @@ pub struct WorkdirProxyConfig {
     // except for 'user_request' which is loaded from '.state/user_request'.
     user_request: Option<String>,
     user_request_start: bool, // true when user_request == "start"
+    network_type: Option<String>,
     proxy_enabled: bool,
     proxy_port_number: u16,
+    proxy_listen: ProxyListenConfig,
     links_overrides: bool,
     links: HashMap<String, Link>,
+    merged_links: HashMap<String, MergedLink>, // 'links' are built from these.
+    sui_faucet_enabled: bool,
+    sui_faucet_host_ip: String,
+    sui_faucet_port: u16,
+    // Problems found while loading the suibase.yaml files (in loading order).
+    diagnostics: Vec<ConfigDiagnostic>,
+    // Problems found while resolving the ${NAME} references (links and proxy_auth_token).
+    resolve_diagnostics: Vec<ConfigDiagnostic>,
+    // Loaded from the secrets.yaml files (must be loaded before the suibase.yaml files).
+    secrets: ConfigSecrets,
+    // File (and line) that did set each value. The key is the variable
+    // name, or "links.<alias>.<member>" for a link.
+    sources: BTreeMap<String, ConfigSource>,
 }
 
 impl WorkdirProxyConfig {
@@ -62,10 +266,20 @@ impl WorkdirProxyConfig {
         Self {
             user_request: None,
             user_request_start: false,
+            network_type: None,
             proxy_enabled: false,
             proxy_port_number: 0,
+            proxy_listen: ProxyListenConfig::default(),
             links_overrides: false,
             links: HashMap::new(),
+            merged_links: HashMap::new(),
+            sui_faucet_enabled: false,
+            sui_faucet_host_ip: "0.0.0.0".to_string(),
+            sui_faucet_port: 0,
+            diagnostics: Vec::new(),
+            resolve_diagnostics: Vec::new(),
+            secrets: ConfigSecrets::new(),
+            sources: BTreeMap::new(),
         }
     }
 
@@ -77,6 +291,11 @@ impl WorkdirProxyConfig {
         self.user_request_start
     }
 
+    // True when the workdir runs its own local Sui process (e.g. localnet).
+    pub fn is_local_network(&self) -> bool {
+        self.network_type.as_deref() == Some("local")
+    }
+
     pub fn is_proxy_enabled(&self) -> bool {
         self.proxy_enabled
     }
@@ -85,6 +304,10 @@ impl WorkdirProxyConfig {
         self.proxy_port_number
     }
 
+    pub fn proxy_listen_config(&self) -> &ProxyListenConfig {
+        &self.proxy_listen
+    }
+
     pub fn links_overrides(&self) -> bool {
         self.links_overrides
     }
@@ -93,6 +316,32 @@ impl WorkdirProxyConfig {
         &self.links
     }
 
+    pub fn is_sui_faucet_enabled(&self) -> bool {
+        self.sui_faucet_enabled
+    }
+
+    pub fn sui_faucet_host_ip(&self) -> &str {
+        &self.sui_faucet_host_ip
+    }
+
+    pub fn sui_faucet_port(&self) -> u16 {
+        self.sui_faucet_port
+    }
+
+    pub fn diagnostics(&self) -> Vec<ConfigDiagnostic> {
+        self.diagnostics
+            .iter()
+            .chain(self.resolve_diagnostics.iter())
+            .cloned()
+            .collect()
+    }
+
+    // Optional file with the values for the ${NAME} references in the links.
+    pub fn load_secrets_file(&mut self, path: &str) -> Result<()> {
+        self.secrets
+            .load_and_merge_from_file(path, &mut self.diagnostics)
+    }
+
     pub fn load_state_file(&mut self, path: &str) -> Result<()> {
         if let Ok(contents) = std::fs::read_to_string(path) {
             // Trim trailing newline.
@@ -122,7 +371,10 @@ impl WorkdirProxyConfig {
         // For most variables, if a new value is defined in the
         // file it will overwrite.
         //
-        // For most lists (e.g. links), they are merged.
+        // For most lists (e.g. links), they are merged. A link with the
+        // same alias is merged member by member (so a file can change only
+        // the priority of a default link). Use 'remove: true' to delete a
+        // link defined by a previous file.
         //
         // =====
         //
@@ -135,74 +387,395 @@ impl WorkdirProxyConfig {
         //    rpc: "http://0.0.0.0:9000"
         //    ws: "ws://0.0.0.0:9000"
         //    priority: 12
-        //  - alias: "localnet"
-        //    enabled: false
-        //    rpc: "http://0.0.0.0:9000"
+        //  - alias: "sui.io"
+        //    priority: 5
+        //  - alias: "suiscan.xyz"
+        //    remove: true
         let contents = std::fs::read_to_string(path)?;
-        let yaml: serde_yaml::Value = serde_yaml::from_str(&contents)?;
-
-        // TODO: Lots of robustness could be added here...
+        let yaml = SuibaseYaml::parse(&contents, path, common, &mut self.diagnostics)?;
+        let key_line = |key: &str| yaml.key_lines.get(key).copied();
 
-        // proxy_enabled can be "true", "false" or "dev" for testing.
-        //
-        // "dev" is similar to "true" but allows for foreground execution
-        // of the suibase-daemon... only the bash scripts care for this.
-        if let Some(proxy_enabled) = yaml["proxy_enabled"].as_bool() {
+        if let Some(proxy_enabled) = yaml.proxy_enabled {
             self.proxy_enabled = proxy_enabled;
-        }
-        if let Some(proxy_enabled) = yaml["proxy_enabled"].as_str() {
-            self.proxy_enabled = proxy_enabled != "false";
+            self.set_source("proxy_enabled", path, key_line("proxy_enabled"));
         }
 
-        if let Some(links_overrides) = yaml["links_overrides"].as_bool() {
+        if let Some(links_overrides) = yaml.links_overrides {
             // Clear all the previous links!
+            self.merged_links.clear();
+            self.sources.retain(|key, _| !key.starts_with("links."));
             self.links.clear();
             self.links_overrides = links_overrides;
+            self.set_source("links_overrides", path, key_line("links_overrides"));
         }
 
-        // Remaining variables do not make sense in common files, so ignore them.
-        // TODO: Implement warning user for bad usage...
+        // Remaining variables do not make sense in common files (the
+        // parser warns about them).
         if common {
             return Ok(());
         }
 
-        if let Some(proxy_port_number) = yaml["proxy_port_number"].as_u64() {
-            self.proxy_port_number = proxy_port_number as u16;
-        }
-
-        if let Some(links) = yaml["links"].as_sequence() {
-            for link in links {
-                if let Some(alias) = link["alias"].as_str() {
-                    // TODO: Consider implementing link level member merging.
-
-                    // Default of "enabled" is true. Allow the user to disable a single link.
-                    //
-                    // May allow later user finer control with "selectable" and "monitored".
-                    let enabled = link["enabled"].as_bool().unwrap_or(true);
-                    let selectable = enabled;
-                    let monitored = enabled;
-
-                    let rpc = link["rpc"].as_str().map(|s| s.to_string()); // Optional
-                    let metrics = link["metrics"].as_str().map(|s| s.to_string()); // Optional
-                    let ws = link["ws"].as_str().map(|s| s.to_string()); // Optional
-                    let priority = link["priority"].as_u64().unwrap_or(u64::MAX) as u8;
-                    let link = Link {
-                        alias: alias.to_string(),
-                        selectable,
-                        monitored,
-                        rpc,
-                        metrics,
-                        ws,
-                        priority,
-                    };
-                    // Replace if already present.
-                    self.links.insert(alias.to_string(), link);
+        if let Some(network_type) = yaml.network_type {
+            self.network_type = Some(network_type);
+            self.set_source("network_type", path, key_line("network_type"));
+        }
+
+        if let Some(proxy_port_number) = yaml.proxy_port_number {
+            self.proxy_port_number = proxy_port_number;
+            self.set_source("proxy_port_number", path, key_line("proxy_port_number"));
+        }
+
+        if let Some(bind_address) = yaml.proxy_bind_address {
+            self.proxy_listen.bind_address = bind_address;
+            self.set_source("proxy_bind_address", path, key_line("proxy_bind_address"));
+        }
+        if let Some(tls_cert) = yaml.proxy_tls_cert {
+            self.proxy_listen.tls_cert = Some(Self::expand_home(&tls_cert));
+            self.set_source("proxy_tls_cert", path, key_line("proxy_tls_cert"));
+        }
+        if let Some(tls_key) = yaml.proxy_tls_key {
+            self.proxy_listen.tls_key = Some(Self::expand_home(&tls_key));
+            self.set_source("proxy_tls_key", path, key_line("proxy_tls_key"));
+        }
+        if let Some(auth_token) = yaml.proxy_auth_token {
+            self.proxy_listen.auth_token_template = Some(auth_token);
+            self.set_source("proxy_auth_token", path, key_line("proxy_auth_token"));
+        }
+
+        // Faucet config (used only for the status of localnet).
+        if let Some(sui_faucet_enabled) = yaml.sui_faucet_enabled {
+            self.sui_faucet_enabled = sui_faucet_enabled;
+            self.set_source("sui_faucet_enabled", path, key_line("sui_faucet_enabled"));
+        }
+        if let Some(sui_faucet_host_ip) = yaml.sui_faucet_host_ip {
+            self.sui_faucet_host_ip = sui_faucet_host_ip;
+            self.set_source("sui_faucet_host_ip", path, key_line("sui_faucet_host_ip"));
+        }
+        if let Some(sui_faucet_port) = yaml.sui_faucet_port {
+            self.sui_faucet_port = sui_faucet_port;
+            self.set_source("sui_faucet_port", path, key_line("sui_faucet_port"));
+        }
+
+        for link in yaml.links {
+            let alias_key = format!("links.{}", link.alias);
+
+            if link.remove == Some(true) {
+                self.merged_links.remove(&link.alias);
+                let members_prefix = format!("{}.", alias_key);
+                self.sources
+                    .retain(|key, _| key != &alias_key && !key.starts_with(&members_prefix));
+                continue;
+            }
+
+            if !self.merged_links.contains_key(&link.alias) {
+                // A new link must be complete (only overrides can be partial).
+                if link.rpc.is_none() {
+                    self.diagnostics.push(ConfigDiagnostic {
+                        level: ConfigDiagnosticLevel::Error,
+                        path: path.to_string(),
+                        line: link.line,
+                        message: format!(
+                            "link '{}' has no rpc and does not override an existing link",
+                            link.alias
+                        ),
+                    });
+                    continue;
+                }
+                // Remember which file did first define the link.
+                self.sources
+                    .insert(alias_key.clone(), ConfigSource::new(path, link.line));
+            }
+            let merged = self.merged_links.entry(link.alias).or_default();
+            let members = [
+                (
+                    "enabled",
+                    MergedLink::merge(&mut merged.enabled, link.enabled),
+                ),
+                (
+                    "selectable",
+                    MergedLink::merge(&mut merged.selectable, link.selectable),
+                ),
+                (
+                    "monitored",
+                    MergedLink::merge(&mut merged.monitored, link.monitored),
+                ),
+                ("rpc", MergedLink::merge(&mut merged.rpc, link.rpc)),
+                (
+                    "metrics",
+                    MergedLink::merge(&mut merged.metrics, link.metrics),
+                ),
+                ("ws", MergedLink::merge(&mut merged.ws, link.ws)),
+                (
+                    "priority",
+                    MergedLink::merge(&mut merged.priority, link.priority),
+                ),
+            ];
+            for (member, is_specified) in members {
+                if is_specified {
+                    self.sources.insert(
+                        format!("{}.{}", alias_key, member),
+                        ConfigSource::new(path, link.line),
+                    );
                 }
             }
+            for (name, value) in link.headers.unwrap_or_default() {
+                self.sources.insert(
+                    format!("{}.headers.{}", alias_key, name),
+                    ConfigSource::new(path, link.line),
+                );
+                merged.headers.insert(name, value);
+            }
         }
 
+        self.rebuild_links();
+
         Ok(())
     }
+
+    // Allows "~/" in the paths of the suibase.yaml files.
+    fn expand_home(path: &str) -> String {
+        match (path.strip_prefix("~/"), home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+            _ => path.to_string(),
+        }
+    }
+
+    // Build the effective links from merged_links (and the proxy_auth_token),
+    // with the ${NAME} references resolved.
+    fn rebuild_links(&mut self) {
+        // Sorted for the diagnostics to be in a stable order.
+        let mut aliases: Vec<&String> = self.merged_links.keys().collect();
+        aliases.sort();
+
+        let mut links = HashMap::new();
+        let mut resolve_diagnostics = Vec::new();
+        for alias in aliases {
+            let mut link = self.merged_links[alias].to_link(alias);
+            self.interpolate_link(&mut link, &mut resolve_diagnostics);
+            links.insert(alias.clone(), link);
+        }
+        self.resolve_proxy_listen(&mut resolve_diagnostics);
+        self.links = links;
+        self.resolve_diagnostics = resolve_diagnostics;
+    }
+
+    // Resolve proxy_auth_token and check that the TLS config is complete.
+    fn resolve_proxy_listen(&mut self, diagnostics: &mut Vec<ConfigDiagnostic>) {
+        let mut add_error = |key: &str, message: String| {
+            let source = self.sources.get(key);
+            diagnostics.push(ConfigDiagnostic {
+                level: ConfigDiagnosticLevel::Error,
+                path: source.map(|source| source.path.clone()).unwrap_or_default(),
+                line: source.and_then(|source| source.line),
+                message,
+            });
+        };
+
+        self.proxy_listen.auth_token = match &self.proxy_listen.auth_token_template {
+            None => None,
+            Some(template) => match self.secrets.interpolate(template) {
+                Ok((token, _)) if !token.is_empty() => Some(token),
+                Ok(_) => {
+                    add_error(
+                        "proxy_auth_token",
+                        "proxy_auth_token is empty (all requests rejected)".to_string(),
+                    );
+                    Some(String::new())
+                }
+                Err(message) => {
+                    // Fail closed: do not silently accept unauthenticated requests.
+                    add_error(
+                        "proxy_auth_token",
+                        format!("proxy_auth_token: {} (all requests rejected)", message),
+                    );
+                    Some(String::new())
+                }
+            },
+        };
+
+        if self.proxy_listen.tls_cert.is_some() != self.proxy_listen.tls_key.is_some() {
+            let (key, missing) = if self.proxy_listen.tls_cert.is_some() {
+                ("proxy_tls_cert", "proxy_tls_key")
+            } else {
+                ("proxy_tls_key", "proxy_tls_cert")
+            };
+            add_error(key, format!("{} is missing (TLS disabled)", missing));
+        }
+    }
+
+    // A member that can't be resolved is dropped (a link without rpc is not used).
+    fn interpolate_link(&self, link: &mut Link, diagnostics: &mut Vec<ConfigDiagnostic>) {
+        let alias = link.alias.clone();
+        let mut add_error = |member: &str, message: String| {
+            let source = self.sources.get(&format!("links.{}.{}", alias, member));
+            diagnostics.push(ConfigDiagnostic {
+                level: ConfigDiagnosticLevel::Error,
+                path: source.map(|source| source.path.clone()).unwrap_or_default(),
+                line: source.and_then(|source| source.line),
+                message: format!("link '{}' {}: {} (ignored)", alias, member, message),
+            });
+        };
+
+        let mut secrets: Vec<String> = Vec::new();
+        for (member, value) in [
+            ("rpc", &mut link.rpc),
+            ("metrics", &mut link.metrics),
+            ("ws", &mut link.ws),
+        ] {
+            let template = match value.as_ref() {
+                Some(template) => template,
+                None => continue,
+            };
+            match self.secrets.interpolate(template) {
+                Ok((resolved, substituted)) => {
+                    if reqwest::Url::parse(&resolved).is_err() {
+                        add_error(member, "not a valid URL once resolved".to_string());
+                        *value = None;
+                    } else {
+                        *value = Some(resolved);
+                        secrets.extend(substituted);
+                    }
+                }
+                Err(message) => {
+                    add_error(member, message);
+                    *value = None;
+                }
+            }
+        }
+
+        let mut headers = BTreeMap::new();
+        for (name, template) in &link.headers {
+            let member = format!("headers.{}", name);
+            match self.secrets.interpolate(template) {
+                Ok((resolved, substituted)) => {
+                    if reqwest::header::HeaderValue::from_str(&resolved).is_err() {
+                        add_error(
+                            member.as_str(),
+                            "not a valid header value once resolved".to_string(),
+                        );
+                    } else {
+                        headers.insert(name.clone(), resolved);
+                        secrets.extend(substituted);
+                    }
+                }
+                Err(message) => add_error(member.as_str(), message),
+            }
+        }
+        link.headers = headers;
+        link.secrets = secrets;
+    }
+
+    fn set_source(&mut self, key: &str, path: &str, line: Option<usize>) {
+        self.sources
+            .insert(key.to_string(), ConfigSource::new(path, line));
+    }
+
+    // Human readable dump of the effective (merged) config, with the file
+    // that did set each value.
+    pub fn effective_config_dump(&self) -> String {
+        let mut out = String::new();
+
+        let network_type = self.network_type.as_deref().unwrap_or("~").to_string();
+        let quoted = |value: &Option<String>| match value {
+            Some(value) => format!("\"{}\"", value),
+            None => "~".to_string(),
+        };
+        // A token without any ${NAME} reference is a literal secret.
+        let auth_token = match &self.proxy_listen.auth_token_template {
+            Some(template) if !template.contains("${") => "****".to_string(),
+            template => quoted(template),
+        };
+        let variables: [(&str, String); 11] = [
+            ("network_type", network_type),
+            ("proxy_enabled", self.proxy_enabled.to_string()),
+            ("proxy_port_number", self.proxy_port_number.to_string()),
+            (
+                "proxy_bind_address",
+                format!("\"{}\"", self.proxy_listen.bind_address),
+            ),
+            ("proxy_tls_cert", quoted(&self.proxy_listen.tls_cert)),
+            ("proxy_tls_key", quoted(&self.proxy_listen.tls_key)),
+            ("proxy_auth_token", auth_token),
+            ("links_overrides", self.links_overrides.to_string()),
+            ("sui_faucet_enabled", self.sui_faucet_enabled.to_string()),
+            (
+                "sui_faucet_host_ip",
+                format!("\"{}\"", self.sui_faucet_host_ip),
+            ),
+            ("sui_faucet_port", self.sui_faucet_port.to_string()),
+        ];
+        for (key, value) in variables {
+            self.dump_line(&mut out, "", key, &value, key, "default");
+        }
+
+        // Links are displayed with the ${NAME} references not resolved (the
+        // header values without any reference are masked).
+        out.push_str("links:\n");
+        let mut aliases: Vec<&String> = self.links.keys().collect();
+        aliases.sort();
+        for alias in aliases {
+            let link = &self.links[alias];
+            let merged = &self.merged_links[alias];
+            let alias_key = format!("links.{}", alias);
+            self.dump_line(
+                &mut out,
+                "  - ",
+                "alias",
+                &format!("\"{}\"", alias),
+                &alias_key,
+                "default",
+            );
+            let enabled = merged.enabled.unwrap_or(true);
+            let mut members: Vec<(&str, String, &str)> = vec![
+                ("enabled", enabled.to_string(), "default"),
+                ("selectable", link.selectable.to_string(), "from enabled"),
+                ("monitored", link.monitored.to_string(), "from enabled"),
+            ];
+            for (member, value) in [
+                ("rpc", &merged.rpc),
+                ("metrics", &merged.metrics),
+                ("ws", &merged.ws),
+            ] {
+                if let Some(value) = value {
+                    members.push((member, format!("\"{}\"", value), "default"));
+                }
+            }
+            members.push(("priority", link.priority.to_string(), "default"));
+            for (member, value, no_source) in members {
+                let key = format!("{}.{}", alias_key, member);
+                self.dump_line(&mut out, "    ", member, &value, &key, no_source);
+            }
+            if !merged.headers.is_empty() {
+                out.push_str("    headers:\n");
+            }
+            for (name, value) in &merged.headers {
+                let value = if value.contains("${") {
+                    format!("\"{}\"", value)
+                } else {
+                    "****".to_string()
+                };
+                let key = format!("{}.headers.{}", alias_key, name);
+                self.dump_line(&mut out, "      ", name, &value, &key, "default");
+            }
+        }
+        out
+    }
+
+    fn dump_line(
+        &self,
+        out: &mut String,
+        prefix: &str,
+        name: &str,
+        value: &str,
+        source_key: &str,
+        no_source: &str,
+    ) {
+        let source = self
+            .sources
+            .get(source_key)
+            .map_or(no_source, |source| source.path.as_str());
+        out.push_str(&format!("{}{}: {}  # {}\n", prefix, name, value, source));
+    }
 }
 
 impl Default for WorkdirProxyConfig {
@@ -220,9 +793,41 @@ pub struct Workdir {
     suibase_state_file: PathBuf,
     suibase_yaml_user: PathBuf,
     suibase_yaml_default: PathBuf,
+    secrets_yaml_user: PathBuf,
+    is_custom: bool,
 }
 
 impl Workdir {
+    fn new(name: &str, workdirs_path: &Path, suibase_home: &Path, is_custom: bool) -> Self {
+        // Paths
+        let path = workdirs_path.join(name);
+
+        let state_path = path.join(".state");
+
+        // Files
+        let state = state_path.join("user_request");
+
+        let user_yaml = path.join("suibase.yaml");
+        let user_secrets = path.join("secrets.yaml");
+
+        let mut default_yaml = suibase_home.join("scripts");
+        default_yaml.push("defaults");
+        default_yaml.push(name);
+        default_yaml.push("suibase.yaml");
+
+        Self {
+            idx: None,
+            name: name.to_string(),
+            path,
+            state_path,
+            suibase_state_file: state,
+            suibase_yaml_user: user_yaml,
+            suibase_yaml_default: default_yaml,
+            secrets_yaml_user: user_secrets,
+            is_custom,
+        }
+    }
+
     pub fn idx(&self) -> Option<ManagedVecUSize> {
         self.idx
     }
@@ -261,9 +866,22 @@ impl Workdir {
         &self.suibase_yaml_user
     }
 
+    // Only for built-in workdirs (custom workdirs have no default suibase.yaml).
     pub fn suibase_yaml_default(&self) -> &Path {
         &self.suibase_yaml_default
     }
+
+    pub fn secrets_yaml_user(&self) -> &Path {
+        &self.secrets_yaml_user
+    }
+
+    // True for a workdir discovered in ~/suibase/workdirs (not one of WORKDIRS_KEYS).
+    //
+    // A custom workdir can be created/removed at runtime. Its entry is kept once
+    // discovered (to keep its WorkdirIdx stable), so check path().exists().
+    pub fn is_custom(&self) -> bool {
+        self.is_custom
+    }
 }
 
 #[derive(Debug)]
@@ -272,6 +890,9 @@ pub struct GlobalsWorkdirsST {
     suibase_home: String,
     path: PathBuf,
     suibase_yaml_common: PathBuf,
+    secrets_yaml_common: PathBuf,
+    api_token_file: PathBuf,
+    api_socket_file: PathBuf,
 }
 
 impl GlobalsWorkdirsST {
@@ -284,48 +905,94 @@ impl GlobalsWorkdirsST {
             PathBuf::from("/tmp")
         };
 
-        let suibase_home = home_dir.join("suibase");
+        Self::with_suibase_home(home_dir.join("suibase"))
+    }
 
+    // Same as new(), but for a suibase installation not in the home directory.
+    pub fn with_suibase_home(suibase_home: PathBuf) -> Self {
         // Generate all the suibase paths for state and config files of each WORKDIRS_KEYS.
         let mut workdirs = ManagedVec::new();
 
         let workdirs_path = suibase_home.join("workdirs");
 
         for workdir in WORKDIRS_KEYS.iter() {
-            // Paths
-            let path = workdirs_path.join(workdir);
-
-            let state_path = path.join(".state");
-
-            // Files
-            let state = state_path.join("user_request");
-
-            let user_yaml = path.join("suibase.yaml");
-
-            let mut default_yaml = suibase_home.join("scripts");
-            default_yaml.push("defaults");
-            default_yaml.push(workdir);
-            default_yaml.push("suibase.yaml");
-
-            workdirs.push(Workdir {
-                idx: None,
-                name: workdir.to_string(),
-                path,
-                state_path,
-                suibase_state_file: state,
-                suibase_yaml_user: user_yaml,
-                suibase_yaml_default: default_yaml,
-            });
+            workdirs.push(Workdir::new(workdir, &workdirs_path, &suibase_home, false));
         }
 
         let suibase_yaml_common = workdirs_path.join("common").join("suibase.yaml");
+        let secrets_yaml_common = workdirs_path.join("common").join("secrets.yaml");
+        let api_token_file = workdirs_path
+            .join("common")
+            .join(".state")
+            .join("api_token");
+        let api_socket_file = workdirs_path.join("common").join(".state").join("api.sock");
 
-        Self {
+        let mut new_globals = Self {
             suibase_home: suibase_home.to_string_lossy().to_string(),
             workdirs,
             path: workdirs_path,
             suibase_yaml_common,
+            secrets_yaml_common,
+            api_token_file,
+            api_socket_file,
+        };
+        new_globals.discover_custom_workdirs();
+        new_globals
+    }
+
+    // Returns the name of the custom workdir for a directory in ~/suibase/workdirs.
+    pub fn custom_workdir_name(&self, path: &Path) -> Option<String> {
+        if path.parent() != Some(self.path.as_path()) {
+            return None;
+        }
+        let name = path.file_name()?.to_str()?;
+        if name.starts_with('.')
+            || WORKDIRS_KEYS.contains(&name)
+            || NOT_CUSTOM_WORKDIRS.contains(&name)
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return None;
         }
+        // Must be a real directory (e.g. "active" is a symlink).
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => Some(name.to_string()),
+            _ => None,
+        }
+    }
+
+    // Add the custom workdir (if not already known). Returns its WorkdirIdx.
+    pub fn add_custom_workdir(&mut self, name: &str) -> Option<WorkdirIdx> {
+        if let Some((workdir_idx, _)) = self.workdirs.iter().find(|(_, w)| w.name() == name) {
+            return Some(workdir_idx);
+        }
+        let suibase_home = PathBuf::from(&self.suibase_home);
+        let workdir = Workdir::new(name, &self.path, &suibase_home, true);
+        let workdir_idx = self.workdirs.push(workdir);
+        if workdir_idx.is_some() {
+            log::info!("custom workdir {} added", name);
+        }
+        workdir_idx
+    }
+
+    // Scan ~/suibase/workdirs for new custom workdirs. Returns true if at least one added.
+    pub fn discover_custom_workdirs(&mut self) -> bool {
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| self.custom_workdir_name(&entry.path()))
+            .filter(|name| !self.workdirs.iter().any(|(_, w)| w.name() == name))
+            .collect();
+        names.sort();
+        let mut at_least_one_added = false;
+        for name in names {
+            at_least_one_added |= self.add_custom_workdir(&name).is_some();
+        }
+        at_least_one_added
     }
 
     pub fn suibase_home(&self) -> &str {
@@ -340,6 +1007,20 @@ impl GlobalsWorkdirsST {
         &self.suibase_yaml_common
     }
 
+    pub fn secrets_yaml_common(&self) -> &Path {
+        &self.secrets_yaml_common
+    }
+
+    // Token required by the mutating methods of the daemon API (see ApiAccessPolicy).
+    pub fn api_token_file(&self) -> &Path {
+        &self.api_token_file
+    }
+
+    // Unix domain socket of the daemon API (see api_unix_socket.rs).
+    pub fn api_socket_file(&self) -> &Path {
+        &self.api_socket_file
+    }
+
     // Given a path string, find the corresponding workdir object.
     // This also works if the string is simply the workdir name (e.g. "localnet").
     pub fn find_workdir(&self, path: &str) -> Option<(WorkdirIdx, &Workdir)> {
@@ -347,12 +1028,11 @@ impl GlobalsWorkdirsST {
         let path = path.trim_start_matches(&self.suibase_home);
         let path = path.trim_start_matches("/scripts/defaults/");
         let path = path.trim_start_matches("/workdirs/");
-        for (workdir_idx, workdir) in self.workdirs.iter() {
-            if path.starts_with(workdir.name()) {
-                return Some((workdir_idx, workdir));
-            }
-        }
-        None
+        // Compare the whole first component (e.g. "localnet2" is not "localnet").
+        let name = path.split('/').next().unwrap_or_default();
+        self.workdirs
+            .iter()
+            .find(|(_, workdir)| workdir.name() == name)
     }
 
     // Utility that returns the workdir_idx from the globals
@@ -371,6 +1051,10 @@ impl GlobalsWorkdirsST {
         let workdirs_vec = &workdirs.workdirs;
         for (workdir_idx, workdir) in workdirs_vec.iter() {
             if workdir.name() == workdir_name {
+                // A removed custom workdir is no longer valid.
+                if workdir.is_custom() && !workdir.path().exists() {
+                    return None;
+                }
                 return Some(workdir_idx);
             }
         }
@@ -393,3 +1077,249 @@ impl ManagedElement for Workdir {
         self.idx = index;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+
+    #[test]
+    fn test_link_member_merging() {
+        let temp_dir = TempDir::new("merge");
+        let dir = temp_dir.path();
+        let default_path = dir.join("default.yaml");
+        let user_path = dir.join("user.yaml");
+        std::fs::write(
+            &default_path,
+            "links:\n  - alias: \"a\"\n    rpc: \"https://a.io\"\n    priority: 10\n  - alias: \"b\"\n    rpc: \"https://b.io\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &user_path,
+            "links:\n  - alias: \"a\"\n    priority: 5\n    ws: \"wss://a.io\"\n  - alias: \"b\"\n    remove: true\n",
+        )
+        .unwrap();
+
+        let default_path = default_path.to_string_lossy().to_string();
+        let user_path = user_path.to_string_lossy().to_string();
+        let mut config = WorkdirProxyConfig::new();
+        config.load_and_merge_from_file(&default_path).unwrap();
+        config.load_and_merge_from_file(&user_path).unwrap();
+
+        assert!(config.diagnostics().is_empty());
+        assert_eq!(config.links().len(), 1);
+        let link = &config.links()["a"];
+        assert_eq!(link.rpc.as_deref(), Some("https://a.io"));
+        assert_eq!(link.ws.as_deref(), Some("wss://a.io"));
+        assert_eq!(link.priority, 5);
+
+        let dump = config.effective_config_dump();
+        assert!(dump.contains(&format!("rpc: \"https://a.io\"  # {}", default_path)));
+        assert!(dump.contains(&format!("priority: 5  # {}", user_path)));
+        assert!(!dump.contains("\"b\""));
+    }
+
+    #[test]
+    fn test_link_without_rpc() {
+        let temp_dir = TempDir::new("norpc");
+        let dir = temp_dir.path();
+        let default_path = dir.join("default.yaml");
+        let user_path = dir.join("user.yaml");
+        std::fs::write(
+            &default_path,
+            "links:\n  - alias: \"a\"\n    rpc: \"https://a.io\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &user_path,
+            "links:\n  - alias: \"a\"\n    priority: 5\n  - alias: \"typo\"\n    priority: 1\n",
+        )
+        .unwrap();
+
+        let default_path = default_path.to_string_lossy().to_string();
+        let user_path = user_path.to_string_lossy().to_string();
+        let mut config = WorkdirProxyConfig::new();
+        config.load_and_merge_from_file(&default_path).unwrap();
+        config.load_and_merge_from_file(&user_path).unwrap();
+
+        // The override of "a" is fine, "typo" overrides nothing and is dropped.
+        assert_eq!(config.links().len(), 1);
+        assert_eq!(config.links()["a"].priority, 5);
+        let diagnostics = config.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].level, ConfigDiagnosticLevel::Error);
+        assert_eq!(diagnostics[0].path, user_path);
+        assert_eq!(diagnostics[0].line, Some(4));
+        assert_eq!(
+            diagnostics[0].message,
+            "link 'typo' has no rpc and does not override an existing link"
+        );
+        assert!(!config.effective_config_dump().contains("typo"));
+    }
+
+    #[test]
+    fn test_link_secrets() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new("secrets");
+        let dir = temp_dir.path();
+        let secrets_path = dir.join("secrets.yaml");
+        let user_path = dir.join("suibase.yaml");
+        std::fs::write(&secrets_path, "A_KEY: \"key123\"\nA_TOKEN: \"token456\"\n").unwrap();
+        std::fs::set_permissions(&secrets_path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        std::fs::write(
+            &user_path,
+            "links:\n  - alias: \"a\"\n    rpc: \"https://a.io/v1/${A_KEY}\"\n    headers:\n      Authorization: \"Bearer ${A_TOKEN}\"\n  - alias: \"b\"\n    rpc: \"https://b.io/${B_UNDEFINED_KEY}\"\n",
+        )
+        .unwrap();
+
+        let mut config = WorkdirProxyConfig::new();
+        config
+            .load_secrets_file(&secrets_path.to_string_lossy())
+            .unwrap();
+        config
+            .load_and_merge_from_file(&user_path.to_string_lossy())
+            .unwrap();
+
+        let link = &config.links()["a"];
+        assert_eq!(link.rpc.as_deref(), Some("https://a.io/v1/key123"));
+        assert_eq!(link.headers["authorization"], "Bearer token456");
+        assert_eq!(config.links()["b"].rpc, None);
+        let diagnostics = config.diagnostics();
+        assert_eq!(diagnostics.len(), 1); // B_UNDEFINED_KEY
+        assert_eq!(diagnostics[0].path, user_path.to_string_lossy());
+        assert_eq!(diagnostics[0].line, Some(6));
+
+        let debug_out = format!("{:?} {}", config, config.effective_config_dump());
+        assert!(!debug_out.contains("key123"));
+        assert!(!debug_out.contains("token456"));
+    }
+
+    #[test]
+    fn test_proxy_listen_config() {
+        let temp_dir = TempDir::new("listen");
+        let dir = temp_dir.path();
+        let user_path = dir.join("suibase.yaml");
+        let load = |contents: &str| {
+            std::fs::write(&user_path, contents).unwrap();
+            let mut config = WorkdirProxyConfig::new();
+            config
+                .load_and_merge_from_file(&user_path.to_string_lossy())
+                .unwrap();
+            config
+        };
+
+        let config = load("proxy_port_number: 44340\n");
+        let listen = config.proxy_listen_config();
+        assert_eq!(listen, &ProxyListenConfig::default());
+        assert_eq!(listen.local_url(44340), "http://127.0.0.1:44340");
+        assert_eq!(listen.auth_header(), None);
+
+        let config = load(
+            "proxy_bind_address: \"0.0.0.0\"\nproxy_tls_cert: \"/c.pem\"\nproxy_tls_key: \"/k.pem\"\nproxy_auth_token: \"literal789\"\n",
+        );
+        let listen = config.proxy_listen_config();
+        assert_eq!(listen.local_url(44340), "https://127.0.0.1:44340");
+        assert_eq!(listen.auth_header().as_deref(), Some("Bearer literal789"));
+        assert!(config.diagnostics().is_empty());
+        let debug_out = format!("{:?} {}", config, config.effective_config_dump());
+        assert!(!debug_out.contains("literal789"));
+
+        // Fail closed on an unresolved token. TLS requires both files.
+        let config = load(
+            "proxy_tls_cert: \"/c.pem\"\nproxy_auth_token: \"${SUIBASE_UNDEFINED_TOKEN_TEST}\"\n",
+        );
+        let listen = config.proxy_listen_config();
+        assert_eq!(listen.auth_token.as_deref(), Some(""));
+        assert!(!listen.is_tls());
+        let lines: Vec<Option<usize>> = config
+            .diagnostics()
+            .iter()
+            .map(|diagnostic| diagnostic.line)
+            .collect();
+        assert_eq!(lines, vec![Some(2), Some(1)]); // proxy_auth_token, proxy_tls_cert
+    }
+
+    #[test]
+    fn test_custom_workdirs() {
+        let temp_dir = TempDir::new("workdirs");
+        let suibase_home = temp_dir.path();
+        let workdirs_path = suibase_home.join("workdirs");
+        for name in [
+            "localnet", "common", ".hidden", "my-net_2", "bad name", "zeta",
+        ] {
+            std::fs::create_dir_all(workdirs_path.join(name)).unwrap();
+        }
+        std::fs::write(workdirs_path.join("a_file"), "").unwrap();
+        std::os::unix::fs::symlink(workdirs_path.join("localnet"), workdirs_path.join("active"))
+            .unwrap();
+        std::os::unix::fs::symlink(workdirs_path.join("zeta"), workdirs_path.join("alias"))
+            .unwrap();
+
+        let mut workdirs = GlobalsWorkdirsST::with_suibase_home(suibase_home.to_path_buf());
+        let name = |path: &str| workdirs.custom_workdir_name(&workdirs_path.join(path));
+        assert_eq!(name("my-net_2").as_deref(), Some("my-net_2"));
+        assert_eq!(name("zeta").as_deref(), Some("zeta"));
+        for not_custom in [
+            "localnet", "common", ".hidden", "bad name", "a_file", "active", "alias", "missing",
+        ] {
+            assert_eq!(name(not_custom), None, "{}", not_custom);
+        }
+        // Only directly in ~/suibase/workdirs.
+        assert_eq!(
+            workdirs.custom_workdir_name(&workdirs_path.join("zeta").join("sub")),
+            None
+        );
+
+        // Discovered on creation (sorted, after the built-in workdirs).
+        let custom: Vec<&str> = workdirs
+            .workdirs
+            .iter()
+            .filter(|(_, w)| w.is_custom())
+            .map(|(_, w)| w.name())
+            .collect();
+        assert_eq!(custom, vec!["my-net_2", "zeta"]);
+        assert!(!workdirs.discover_custom_workdirs());
+
+        std::fs::create_dir_all(workdirs_path.join("another")).unwrap();
+        assert!(workdirs.discover_custom_workdirs());
+        let (idx, workdir) = workdirs.find_workdir("another").unwrap();
+        assert!(workdir.is_custom());
+        // The WorkdirIdx is stable.
+        assert_eq!(workdirs.add_custom_workdir("another"), Some(idx));
+    }
+
+    #[test]
+    fn test_find_workdir() {
+        let suibase_home = PathBuf::from("/home/user/suibase");
+        let mut workdirs = GlobalsWorkdirsST::with_suibase_home(suibase_home);
+        workdirs.add_custom_workdir("localnet2").unwrap();
+
+        let find = |path: &str| {
+            workdirs
+                .find_workdir(path)
+                .map(|(_, w)| w.name().to_string())
+        };
+        assert_eq!(find("localnet").as_deref(), Some("localnet"));
+        assert_eq!(
+            find("/home/user/suibase/workdirs/localnet/suibase.yaml").as_deref(),
+            Some("localnet")
+        );
+        assert_eq!(
+            find("/home/user/suibase/scripts/defaults/testnet/suibase.yaml").as_deref(),
+            Some("testnet")
+        );
+        // Exact component match ("localnet2" is not "localnet").
+        assert_eq!(
+            find("/home/user/suibase/workdirs/localnet2/suibase.yaml").as_deref(),
+            Some("localnet2")
+        );
+        assert_eq!(find("localnet2").as_deref(), Some("localnet2"));
+        assert_eq!(find("local"), None);
+        assert_eq!(
+            find("/home/user/suibase/workdirs/localnetx/suibase.yaml"),
+            None
+        );
+        assert_eq!(find(""), None);
+    }
+}
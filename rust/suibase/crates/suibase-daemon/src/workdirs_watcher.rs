@@ -158,6 +158,66 @@ impl WorkdirsWatcher {
         at_least_one_modif
     }
 
+    // Files for which a creation, modification or removal is notified to the AdminController.
+    //
+    // Editors often write a temporary file and then rename it over the target, so
+    // a change may be reported as a removal/creation instead of a modification.
+    fn is_watched_file(path: &std::path::Path) -> bool {
+        path.ends_with("user_request")
+            || path.ends_with("suibase.yaml")
+            || path.ends_with("secrets.yaml")
+    }
+
+    // Resynchronize all the watches with the filesystem and request the AdminController
+    // to reload every workdir. Used when notify-rs events might have been missed.
+    async fn rescan(&mut self, poll_watcher: &mut PollWatcher) {
+        log::warn!("rescan of all workdirs (events might have been missed)");
+
+        // Custom workdirs created while events were missed.
+        self.workdirs.write().await.discover_custom_workdirs();
+
+        let mut workdir_paths: Vec<String> = Vec::new();
+        {
+            let workdirs_guard = self.workdirs.read().await;
+            let workdirs = &*workdirs_guard;
+
+            if workdirs.path().exists() {
+                let _ = poll_watcher.unwatch(workdirs.path());
+                let _ = poll_watcher.watch(workdirs.path(), RecursiveMode::NonRecursive);
+            }
+
+            for (workdir_idx, workdir) in workdirs.workdirs.iter() {
+                // Forget previous watches (the directories may have been re-created).
+                let tracking = self.tracking.get_mut(workdir_idx);
+                if tracking.is_workdir_watched {
+                    let _ = poll_watcher.unwatch(workdir.path());
+                    tracking.is_workdir_watched = false;
+                }
+                if tracking.is_state_watched {
+                    let _ = poll_watcher.unwatch(workdir.state_path());
+                    tracking.is_state_watched = false;
+                }
+                Self::update_workdir_watch(
+                    &mut self.tracking,
+                    poll_watcher,
+                    workdir,
+                    &workdir.path().to_string_lossy(),
+                );
+                Self::update_workdir_watch(
+                    &mut self.tracking,
+                    poll_watcher,
+                    workdir,
+                    &workdir.state_path().to_string_lossy(),
+                );
+                workdir_paths.push(workdir.path().to_string_lossy().to_string());
+            }
+        } // Release workdirs read lock
+
+        for path in workdir_paths {
+            self.send_notif_config_file_change(path).await;
+        }
+    }
+
     async fn watch_loop(
         &mut self,
         subsys: &SubsystemHandle,
@@ -168,8 +228,8 @@ impl WorkdirsWatcher {
             // Wait for a message.
             if let Some(msg) = local_rx.recv().await {
                 if msg.need_rescan() {
-                    // TODO Implement rescan of all workdirs (assume events were missed).
-                    log::error!("watch_loop() need_rescan (not implemented!)");
+                    self.rescan(&mut poll_watcher).await;
+                    continue;
                 }
 
                 // Process the event from notify-rs
@@ -178,8 +238,8 @@ impl WorkdirsWatcher {
                 match msg.kind {
                     notify::event::EventKind::Modify(_) => {
                         for path in msg.paths {
-                            // Ignore everything except for user_request and suibase.yaml files.
-                            if !path.ends_with("user_request") && !path.ends_with("suibase.yaml") {
+                            // Ignore everything except for the watched files (see is_watched_file).
+                            if !Self::is_watched_file(&path) {
                                 continue;
                             }
                             self.send_notif_config_file_change(path.to_string_lossy().to_string())
@@ -191,55 +251,92 @@ impl WorkdirsWatcher {
                     // Meta-events about notifier itself (can be ignored).
                     // notify::event::EventKind::Other =>
 
-                    // File creation, but not "writing" (can be ignored)
-                    notify::event::EventKind::Create(create_kind) => {
-                        // If creating one of the "suibase" standard workdir, then
-                        // start watching it.
-                        if create_kind == notify::event::CreateKind::Folder {
-                            let workdirs_guard = self.workdirs.read().await;
-                            let workdirs = &*workdirs_guard;
-                            log::info!("CreateKind {:?}", msg);
-                            for path in msg.paths {
+                    // Note: The PollWatcher reports CreateKind::Any, so the filesystem is
+                    //       checked to differentiate files from folders.
+                    notify::event::EventKind::Create(_) => {
+                        for path in msg.paths {
+                            if Self::is_watched_file(&path) {
+                                self.send_notif_config_file_change(
+                                    path.to_string_lossy().to_string(),
+                                )
+                                .await;
+                                continue;
+                            }
+
+                            if !path.is_dir() {
+                                continue;
+                            }
+
+                            // A new directory in ~/suibase/workdirs is a custom workdir
+                            // (unless a reserved name).
+                            {
+                                let mut workdirs_guard = self.workdirs.write().await;
+                                let workdirs = &mut *workdirs_guard;
+                                if let Some(name) = workdirs.custom_workdir_name(&path) {
+                                    workdirs.add_custom_workdir(&name);
+                                }
+                            } // Release workdirs write lock
+
+                            // If creating one of the "suibase" workdir (or its
+                            // .state), then start watching it.
+                            let is_newly_watched = {
+                                let workdirs_guard = self.workdirs.read().await;
+                                let workdirs = &*workdirs_guard;
+                                log::info!("Create {:?}", path);
                                 let path = &path.to_string_lossy();
-                                if let Some((_, workdir)) = workdirs.find_workdir(path) {
-                                    if Self::update_workdir_watch(
+                                match workdirs.find_workdir(path) {
+                                    Some((_, workdir)) => Self::update_workdir_watch(
                                         &mut self.tracking,
                                         &mut poll_watcher,
                                         workdir,
                                         path,
-                                    ) {
-                                        // TODO Need to track creation of a few key file from here
-                                        //      to make sure they are notified... for now always
-                                        //      notified once after a delay with assumption the file
-                                        //      were created after 1 second...
-                                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                                        self.send_notif_config_file_change(path.to_string()).await
-                                    }
+                                    ),
+                                    None => false,
                                 }
+                            };
+                            if is_newly_watched {
+                                // TODO Need to track creation of a few key file from here
+                                //      to make sure they are notified... for now always
+                                //      notified once after a delay with assumption the file
+                                //      were created after 1 second...
+                                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                                self.send_notif_config_file_change(
+                                    path.to_string_lossy().to_string(),
+                                )
+                                .await
                             }
                         }
                     }
 
-                    notify::event::EventKind::Remove(remove_kind) => {
-                        if remove_kind == notify::event::RemoveKind::Folder {
-                            let workdirs_guard = self.workdirs.read().await;
-                            let workdirs = &*workdirs_guard;
-                            for path in msg.paths {
-                                if let Some((_, workdir)) =
-                                    workdirs.find_workdir(&path.to_string_lossy())
-                                {
-                                    if Self::remove_workdir_watch(
+                    notify::event::EventKind::Remove(_) => {
+                        for path in msg.paths {
+                            if Self::is_watched_file(&path) {
+                                self.send_notif_config_file_change(
+                                    path.to_string_lossy().to_string(),
+                                )
+                                .await;
+                                continue;
+                            }
+
+                            // Stop watching a removed workdir (or its .state).
+                            let is_unwatched = {
+                                let workdirs_guard = self.workdirs.read().await;
+                                let workdirs = &*workdirs_guard;
+                                match workdirs.find_workdir(&path.to_string_lossy()) {
+                                    Some((_, workdir)) => Self::remove_workdir_watch(
                                         &mut self.tracking,
                                         &mut poll_watcher,
                                         workdir,
                                         &path.to_string_lossy(),
-                                    ) {
-                                        self.send_notif_config_file_change(
-                                            path.to_string_lossy().to_string(),
-                                        )
-                                        .await
-                                    }
+                                    ),
+                                    None => false,
                                 }
+                            };
+                            if is_unwatched {
+                                self.send_notif_config_file_change(
+                                    path.to_string_lossy().to_string(),
+                                )
+                                .await
                             }
                         }
                     }
@@ -286,7 +383,16 @@ impl WorkdirsWatcher {
                     });
                 }
                 Err(e) => {
+                    // Events might have been lost, so request a full rescan.
                     log::warn!("{:?}", e);
+                    let rescan_event = notify::Event::new(notify::EventKind::Other)
+                        .set_flag(notify::event::Flag::Rescan);
+                    let local_tx_to_spawned_fn = local_tx.clone();
+                    rt.spawn(async move {
+                        if let Err(e) = local_tx_to_spawned_fn.send(rescan_event).await {
+                            log::error!("local_tx.send {}", e);
+                        }
+                    });
                 }
             },
             poll_watcher_config.with_poll_interval(std::time::Duration::from_secs(15)),
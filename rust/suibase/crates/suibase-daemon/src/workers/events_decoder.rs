@@ -0,0 +1,373 @@
+// Helper of the events_writer_worker (not a thread on its own).
+//
+// Render the "parsedJson" of a Sui event with the field names and types
+// defined by the package ABI.
+//
+// The ABI is retrieved with sui_getNormalizedMoveModulesByPackage (through
+// the proxy server of the workdir) and cached per package id.
+//
+// When the ABI is not available (e.g. network down), the event is rendered
+// as its raw type followed by its parsedJson, and the ABI retrieval is
+// retried later.
+//
+// The WorkdirRpcClient (also used to get the timestamp of the transactions
+// matching a watch) is cheap to clone for use from other tasks.
+use std::collections::HashMap;
+
+use axum::http::header;
+
+use crate::proxy_server::ProxyServer;
+use crate::{basic_types::WorkdirIdx, shared_types::Globals};
+
+// Minimum delay before retrying to get the ABI of a package after a failure.
+const ABI_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+struct MoveField {
+    name: String,
+    type_str: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MovePackageAbi {
+    // Key is "module::StructName".
+    structs: HashMap<String, Vec<MoveField>>,
+}
+
+impl MovePackageAbi {
+    // Build from the JSON result of sui_getNormalizedMoveModulesByPackage.
+    fn from_normalized_modules(modules: &serde_json::Value) -> Option<Self> {
+        let modules = modules.as_object()?;
+        let mut abi = MovePackageAbi::default();
+        for (module_name, module) in modules {
+            let structs = match module["structs"].as_object() {
+                Some(structs) => structs,
+                None => continue,
+            };
+            for (struct_name, struct_def) in structs {
+                let mut fields = Vec::new();
+                if let Some(fields_def) = struct_def["fields"].as_array() {
+                    for field in fields_def {
+                        fields.push(MoveField {
+                            name: field["name"].as_str().unwrap_or_default().to_string(),
+                            type_str: Self::type_to_string(&field["type"]),
+                        });
+                    }
+                }
+                abi.structs
+                    .insert(format!("{}::{}", module_name, struct_name), fields);
+            }
+        }
+        Some(abi)
+    }
+
+    // Convert a SuiMoveNormalizedType into something similar to the Move syntax.
+    fn type_to_string(t: &serde_json::Value) -> String {
+        if let Some(primitive) = t.as_str() {
+            return primitive.to_lowercase();
+        }
+        if let Some(s) = t.get("Struct") {
+            let mut ret = format!(
+                "{}::{}",
+                s["module"].as_str().unwrap_or_default(),
+                s["name"].as_str().unwrap_or_default()
+            );
+            if let Some(type_args) = s["typeArguments"].as_array() {
+                if !type_args.is_empty() {
+                    let type_args: Vec<String> =
+                        type_args.iter().map(Self::type_to_string).collect();
+                    ret.push_str(&format!("<{}>", type_args.join(", ")));
+                }
+            }
+            return ret;
+        }
+        if let Some(inner) = t.get("Vector") {
+            return format!("vector<{}>", Self::type_to_string(inner));
+        }
+        if let Some(idx) = t.get("TypeParameter") {
+            return format!("T{}", idx);
+        }
+        if let Some(inner) = t.get("Reference") {
+            return format!("&{}", Self::type_to_string(inner));
+        }
+        if let Some(inner) = t.get("MutableReference") {
+            return format!("&mut {}", Self::type_to_string(inner));
+        }
+        t.to_string()
+    }
+}
+
+// JSON-RPC requests to the network of a workdir (through its proxy server).
+#[derive(Clone)]
+pub struct WorkdirRpcClient {
+    globals: Globals,
+    workdir_idx: WorkdirIdx,
+    client: reqwest::Client,
+}
+
+impl WorkdirRpcClient {
+    pub fn new(globals: Globals, workdir_idx: WorkdirIdx) -> Self {
+        Self {
+            globals,
+            workdir_idx,
+            client: ProxyServer::local_client(),
+        }
+    }
+
+    // URL and "Authorization" header of the proxy server of the workdir.
+    async fn get_proxy_url(&self) -> Option<(String, Option<String>)> {
+        let globals_read_guard = self.globals.proxy.read().await;
+        let globals = &*globals_read_guard;
+        let input_port = globals.find_input_port_by_workdir_idx(self.workdir_idx)?;
+        Some((input_port.local_url(), input_port.local_auth_header()))
+    }
+
+    // JSON-RPC request to the network of the workdir (through its proxy server).
+    //
+    // Returns the "result" field. Failures are logged, except when the proxy
+    // server is not available.
+    async fn proxy_request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Option<serde_json::Value> {
+        let (uri, auth_header) = self.get_proxy_url().await?;
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "id": 1,
+            "params": params,
+        });
+
+        let mut req_builder = self.client.post(uri);
+        if let Some(auth_header) = auth_header {
+            req_builder = req_builder.header(header::AUTHORIZATION, auth_header);
+        }
+        let resp = req_builder
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await;
+
+        let json = match resp {
+            Ok(resp) => resp.json::<serde_json::Value>().await,
+            Err(e) => {
+                log::warn!("{} {} failed: {}", method, params, e);
+                return None;
+            }
+        };
+
+        match json {
+            Ok(mut json) => match json.get_mut("result") {
+                Some(result) => Some(result.take()),
+                None => {
+                    log::warn!("{} {} invalid response {}", method, params, json);
+                    None
+                }
+            },
+            Err(e) => {
+                log::warn!("{} {} invalid JSON: {}", method, params, e);
+                None
+            }
+        }
+    }
+
+    // Returns the "timestampMs" of a transaction.
+    //
+    // None when not available (e.g. the transaction is not yet in a checkpoint).
+    pub async fn transaction_timestamp_ms(&self, tx_digest: &str) -> Option<String> {
+        let result = self
+            .proxy_request("sui_getTransactionBlock", serde_json::json!([tx_digest]))
+            .await?;
+        result["timestampMs"].as_str().map(|ts| ts.to_string())
+    }
+}
+
+pub struct EventsDecoder {
+    rpc_client: WorkdirRpcClient,
+    // Key is the package id.
+    abi_cache: HashMap<String, MovePackageAbi>,
+    // Last failed attempt to get the ABI of a package.
+    abi_failures: HashMap<String, tokio::time::Instant>,
+}
+
+impl EventsDecoder {
+    pub fn new(globals: Globals, workdir_idx: WorkdirIdx) -> Self {
+        Self {
+            rpc_client: WorkdirRpcClient::new(globals, workdir_idx),
+            abi_cache: HashMap::new(),
+            abi_failures: HashMap::new(),
+        }
+    }
+
+    // Returns a human readable message for the event.
+    //
+    // Format is "module::StructName { field: type = value, ... }"
+    pub async fn render_event(&mut self, event: &serde_json::Value) -> String {
+        let event_type = event["type"].as_str().unwrap_or_default();
+        let parsed_json = &event["parsedJson"];
+        let raw_msg = format!("{} {}", event_type, parsed_json);
+
+        // Type is "<package_id>::<module>::<StructName>" possibly followed
+        // by type arguments "<...>".
+        let (type_name, type_args) = match event_type.find('<') {
+            Some(pos) => event_type.split_at(pos),
+            None => (event_type, ""),
+        };
+        let mut parts = type_name.splitn(3, "::");
+        let (package_id, module, struct_name) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(p), Some(m), Some(s)) => (p, m, s),
+            _ => return raw_msg,
+        };
+
+        let struct_key = format!("{}::{}", module, struct_name);
+        let fields = match self.get_abi(package_id).await {
+            Some(abi) => match abi.structs.get(&struct_key) {
+                Some(fields) => fields,
+                None => return raw_msg,
+            },
+            None => return raw_msg,
+        };
+
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|field| {
+                let value = &parsed_json[&field.name];
+                let value = match value.as_str() {
+                    Some(s) => s.to_string(),
+                    None => value.to_string(),
+                };
+                format!("{}: {} = {}", field.name, field.type_str, value)
+            })
+            .collect();
+
+        format!("{}{} {{ {} }}", struct_key, type_args, fields.join(", "))
+    }
+
+    async fn get_abi(&mut self, package_id: &str) -> Option<&MovePackageAbi> {
+        if !self.abi_cache.contains_key(package_id) {
+            if let Some(last_failure) = self.abi_failures.get(package_id) {
+                if last_failure.elapsed() < ABI_RETRY_DELAY {
+                    return None;
+                }
+            }
+            match self.fetch_abi(package_id).await {
+                Some(abi) => {
+                    self.abi_failures.remove(package_id);
+                    self.abi_cache.insert(package_id.to_string(), abi);
+                }
+                None => {
+                    self.abi_failures
+                        .insert(package_id.to_string(), tokio::time::Instant::now());
+                    return None;
+                }
+            }
+        }
+        self.abi_cache.get(package_id)
+    }
+
+    pub fn rpc_client(&self) -> WorkdirRpcClient {
+        self.rpc_client.clone()
+    }
+
+    async fn fetch_abi(&self, package_id: &str) -> Option<MovePackageAbi> {
+        let result = self
+            .rpc_client
+            .proxy_request(
+                "sui_getNormalizedMoveModulesByPackage",
+                serde_json::json!([package_id]),
+            )
+            .await?;
+        let abi = MovePackageAbi::from_normalized_modules(&result);
+        if abi.is_none() {
+            log::warn!(
+                "get ABI of package {} invalid response {}",
+                package_id,
+                result
+            );
+        }
+        abi
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_type_to_string() {
+        assert_eq!(MovePackageAbi::type_to_string(&json!("U64")), "u64");
+        assert_eq!(
+            MovePackageAbi::type_to_string(&json!({ "Vector": "U8" })),
+            "vector<u8>"
+        );
+        assert_eq!(
+            MovePackageAbi::type_to_string(&json!({ "TypeParameter": 0 })),
+            "T0"
+        );
+        assert_eq!(
+            MovePackageAbi::type_to_string(
+                &json!({ "MutableReference": { "Reference": "Address" } })
+            ),
+            "&mut &address"
+        );
+        let coin = json!({ "Struct": {
+            "address": "0x2",
+            "module": "coin",
+            "name": "Coin",
+            "typeArguments": [{ "Struct": {
+                "address": "0x2", "module": "sui", "name": "SUI", "typeArguments": []
+            }}]
+        }});
+        assert_eq!(
+            MovePackageAbi::type_to_string(&coin),
+            "coin::Coin<sui::SUI>"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_event() {
+        let modules = json!({
+            "demo": { "structs": { "Counted": { "fields": [
+                { "name": "count", "type": "U64" },
+                { "name": "owner", "type": "Address" },
+            ]}}}
+        });
+        let abi = MovePackageAbi::from_normalized_modules(&modules).unwrap();
+
+        let mut decoder = EventsDecoder::new(Globals::new(), 0);
+        decoder.abi_cache.insert("0xabc".to_string(), abi);
+
+        let event = json!({
+            "type": "0xabc::demo::Counted",
+            "parsedJson": { "count": "5", "owner": "0x1" },
+        });
+        assert_eq!(
+            decoder.render_event(&event).await,
+            "demo::Counted { count: u64 = 5, owner: address = 0x1 }"
+        );
+
+        // Type arguments are kept after the struct name.
+        let event = json!({
+            "type": "0xabc::demo::Counted<0x2::sui::SUI>",
+            "parsedJson": { "count": 1, "owner": "0x1" },
+        });
+        assert_eq!(
+            decoder.render_event(&event).await,
+            "demo::Counted<0x2::sui::SUI> { count: u64 = 1, owner: address = 0x1 }"
+        );
+
+        // Struct not in the ABI, or ABI not available (no proxy server): raw rendering.
+        let event = json!({ "type": "0xabc::demo::Other", "parsedJson": { "a": 1 } });
+        assert_eq!(
+            decoder.render_event(&event).await,
+            r#"0xabc::demo::Other {"a":1}"#
+        );
+        let event = json!({ "type": "0xdef::demo::Counted", "parsedJson": {} });
+        assert_eq!(
+            decoder.render_event(&event).await,
+            "0xdef::demo::Counted {}"
+        );
+    }
+}
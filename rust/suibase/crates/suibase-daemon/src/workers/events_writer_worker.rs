@@ -4,21 +4,30 @@
 //
 // The events_worker is responsible to subscribe/unsubscribe events, filter them
 // and forward the validated data to its events_writer_worker parent.
-use std::{collections::HashSet, process::Command, sync::Arc};
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
 
 use crate::{
     admin_controller::{self, AdminControllerMsg, AdminControllerRx},
     basic_types::{AutoThread, Runnable, WorkdirIdx},
-    shared_types::Globals,
-    workers::{WebSocketWorker, WebSocketWorkerParams},
+    shared_types::{EventsChannel, Globals, SuiEventData, EVENTS_MAX_PER_CHANNEL},
+    workers::{EventsDecoder, WebSocketWorker, WebSocketWorkerParams, WorkdirRpcClient},
 };
 
 use anyhow::Result;
 use axum::async_trait;
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use tokio_graceful_shutdown::{FutureExt, SubsystemBuilder, SubsystemHandle};
 
+// Retries to get the timestamp of a watched transaction (see process_sui_watch).
+const TX_TIMESTAMP_ATTEMPTS: u32 = 3;
+const TX_TIMESTAMP_RETRY_DELAY: tokio::time::Duration = tokio::time::Duration::from_millis(500);
+// Maximum timestamp requests in progress at the same time (the others wait their turn).
+const TX_TIMESTAMP_MAX_CONCURRENT: usize = 4;
+
 #[derive(Clone)]
 pub struct EventsWriterWorkerParams {
     _globals: Globals,
@@ -62,18 +71,37 @@ struct EventsWriterThread {
     // Set of unique packaged id (string).
     subscribed_ids: HashSet<String>,
 
+    // Unique id ("txDigest:eventSeq" or "watch:txDigest") of the most recent events
+    // stored. Used to dedup the events (the oldest are forgotten
+    // in same order they are dropped from the in-memory storage).
+    stored_ids: HashSet<String>,
+    stored_ids_order: VecDeque<String>,
+
+    // Render the events using the ABI of their package.
+    decoder: Option<EventsDecoder>,
+
     // Last known valid sequence number processed.
     last_seq_number: u64,
+
+    // Bound the concurrent tasks getting the timestamp of a watched transaction.
+    tx_timestamp_permits: Arc<Semaphore>,
 }
 
 #[async_trait]
 impl Runnable<EventsWriterWorkerParams> for EventsWriterThread {
     fn new(name: String, params: EventsWriterWorkerParams) -> Self {
+        let decoder = params
+            .workdir_idx
+            .map(|workdir_idx| EventsDecoder::new(params._globals.clone(), workdir_idx));
         Self {
             name,
             params,
             subscribed_ids: HashSet::new(),
+            stored_ids: HashSet::new(),
+            stored_ids_order: VecDeque::new(),
+            decoder,
             last_seq_number: 0,
+            tx_timestamp_permits: Arc::new(Semaphore::new(TX_TIMESTAMP_MAX_CONCURRENT)),
         }
     }
 
@@ -82,16 +110,22 @@ impl Runnable<EventsWriterWorkerParams> for EventsWriterThread {
 
         // Start a child websocket_worker thread.
         let (_worker_tx, worker_rx) = tokio::sync::mpsc::channel(1000);
+        let (ws_events_tx, ws_events_rx) = tokio::sync::mpsc::channel(10000);
         let ws_worker_params = WebSocketWorkerParams::new(
             self.params._globals.clone(),
             worker_rx,
+            ws_events_tx,
             self.params.workdir_idx,
         );
         let ws_worker = WebSocketWorker::new(ws_worker_params);
         subsys.start(SubsystemBuilder::new("ws-worker", |a| ws_worker.run(a)));
         // TODO Send a periodic audit message to the websocket_worker.
 
-        match self.event_loop(&subsys).cancel_on_shutdown(&subsys).await {
+        match self
+            .event_loop(&subsys, ws_events_rx)
+            .cancel_on_shutdown(&subsys)
+            .await
+        {
             Ok(()) => {
                 log::info!("shutting down - normal exit (2)");
                 Ok(())
@@ -105,80 +139,190 @@ impl Runnable<EventsWriterWorkerParams> for EventsWriterThread {
 }
 
 impl EventsWriterThread {
-    async fn do_exec(&mut self, msg: AdminControllerMsg) {
-        // No error return here. Once the execution is completed, the output
-        // of the response is returned to requester with a one shot message.
-        //
-        // If the response starts with "Error:", then an error was detected.
-        //
-        // Some effects are also possible on globals, particularly
-        // for sharing large results.
-        //
-        log::info!(
-            "do_exec() msg {:?} for workdir_idx={:?}",
-            msg,
-            self.params.workdir_idx
-        );
+    // Returns true if the event_id was already stored, otherwise remember it.
+    fn is_duplicate(&mut self, event_id: String) -> bool {
+        if self.stored_ids.contains(&event_id) {
+            return true;
+        }
+        self.stored_ids.insert(event_id.clone());
+        self.stored_ids_order.push_back(event_id);
+        while self.stored_ids_order.len() > EVENTS_MAX_PER_CHANNEL {
+            if let Some(oldest) = self.stored_ids_order.pop_front() {
+                self.stored_ids.remove(&oldest);
+            }
+        }
+        false
+    }
 
-        let resp = if msg.event_id != admin_controller::EVENT_SHELL_EXEC {
+    async fn process_sui_event(&mut self, msg: AdminControllerMsg) {
+        // The data_string is the JSON "result" of a suix_subscribeEvent notification.
+        if msg.event_id != admin_controller::EVENT_NOTIF_SUI_EVENT {
             log::error!("Unexpected event_id {:?}", msg.event_id);
-            format!("Error: Unexpected event_id {:?}", msg.event_id)
-        } else if let Some(cmd) = &msg.data_string {
-            // Execute the command as if it was a bash script.
-            let output = Command::new("bash").arg("-c").arg(cmd).output();
-
-            match output {
-                Ok(output) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                    let resp = format!("{}{}", stdout, stderr);
-                    if output.status.success() && stderr.is_empty() {
-                        resp
-                    } else {
-                        format!("Error: {}", resp)
-                    }
-                }
-                Err(e) => {
-                    let error_msg = format!(
-                        "Error: do_exec({:?}, {:?}) error 1: {}",
-                        msg.workdir_idx, cmd, e
-                    );
-                    log::error!("{}", error_msg);
-                    error_msg
-                }
+            return;
+        }
+
+        let workdir_idx = match self.params.workdir_idx {
+            Some(workdir_idx) => workdir_idx,
+            None => {
+                log::error!("EVENT_NOTIF_SUI_EVENT missing workdir_idx");
+                return;
             }
-        } else {
-            let error_msg = format!(
-                "Error: do_exec({:?}, None) error 2: No command to execute",
-                msg.workdir_idx
-            );
-            log::error!("{}", error_msg);
-            error_msg
         };
 
-        if let Some(resp_channel) = msg.resp_channel {
-            if let Err(e) = resp_channel.send(resp) {
-                let error_msg = format!(
-                    "Error: do_exec({:?}, {:?}) error 3: {}",
-                    msg.workdir_idx, msg.data_string, e
-                );
-                log::error!("{}", error_msg);
+        let event = match msg
+            .data_string
+            .as_ref()
+            .map(|s| serde_json::from_str::<serde_json::Value>(s))
+        {
+            Some(Ok(event)) => event,
+            _ => {
+                log::error!("EVENT_NOTIF_SUI_EVENT invalid data {:?}", msg.data_string);
+                return;
             }
+        };
+
+        // Dedup using the unique event id.
+        let tx_digest = event["id"]["txDigest"].as_str().unwrap_or_default();
+        let event_seq = event["id"]["eventSeq"].as_str().unwrap_or_default();
+        if tx_digest.is_empty() {
+            log::error!("EVENT_NOTIF_SUI_EVENT missing event id {}", event);
+            return;
+        }
+        if self.is_duplicate(format!("{}:{}", tx_digest, event_seq)) {
+            return;
+        }
+
+        let message = match self.decoder.as_mut() {
+            Some(decoder) => decoder.render_event(&event).await,
+            None => format!("{} {}", event["type"], event["parsedJson"]),
+        };
+        let timestamp = event["timestampMs"].as_str().unwrap_or_default();
+        let event_data = SuiEventData::new(message, timestamp.to_string());
+
+        {
+            let mut globals_write_guard = self.params._globals.events_data.write().await;
+            let globals = &mut *globals_write_guard;
+            globals
+                .workdirs
+                .get_mut(workdir_idx)
+                .add_event(EventsChannel::User, event_data);
         }
     }
 
-    async fn event_loop(&mut self, subsys: &SubsystemHandle) {
-        while !subsys.is_shutdown_requested() {
-            // Wait for a suibase internal message (not a websocket message!).
-            let mut event_rx = self.params.event_rx.lock().await;
-            if let Some(msg) = event_rx.recv().await {
-                // Process the message.
-                drop(event_rx);
-                self.do_exec(msg).await;
-            } else {
-                // Channel closed or shutdown requested.
+    fn process_sui_watch(&mut self, msg: AdminControllerMsg) {
+        // The data_string is {"watch": <WatchTarget key>, "effects": <transaction effects>}
+        let workdir_idx = match self.params.workdir_idx {
+            Some(workdir_idx) => workdir_idx,
+            None => {
+                log::error!("EVENT_NOTIF_SUI_WATCH missing workdir_idx");
                 return;
             }
+        };
+
+        let data = match msg
+            .data_string
+            .as_ref()
+            .map(|s| serde_json::from_str::<serde_json::Value>(s))
+        {
+            Some(Ok(data)) => data,
+            _ => {
+                log::error!("EVENT_NOTIF_SUI_WATCH invalid data {:?}", msg.data_string);
+                return;
+            }
+        };
+
+        let watch = data["watch"].as_str().unwrap_or_default();
+        let effects = &data["effects"];
+        let tx_digest = effects["transactionDigest"].as_str().unwrap_or_default();
+        if watch.is_empty() || tx_digest.is_empty() {
+            log::error!("EVENT_NOTIF_SUI_WATCH missing watch or digest {}", data);
+            return;
+        }
+
+        // Dedup (same transaction can be notified more than once for a watch).
+        if self.is_duplicate(format!("{}:{}", watch, tx_digest)) {
+            return;
+        }
+
+        let status = effects["status"]["status"].as_str().unwrap_or("unknown");
+        let message = format!("{} tx {} ({})", watch, tx_digest, status);
+
+        // Stamp with the time of the transaction (like the user events) for a
+        // consistent after_ts/last_ts filtering. The effects do not have it, and
+        // it is assigned only once the transaction is in a checkpoint.
+        //
+        // The event is stored by another task once its timestamp is known, so the
+        // retries do not hold the processing of the websocket messages.
+        let rpc_client = self.decoder.as_ref().map(|decoder| decoder.rpc_client());
+        let permits = self.tx_timestamp_permits.clone();
+        let globals = self.params._globals.clone();
+        let tx_digest = tx_digest.to_string();
+        tokio::spawn(async move {
+            let timestamp = match (rpc_client, permits.acquire_owned().await) {
+                (Some(rpc_client), Ok(_permit)) => {
+                    Self::get_tx_timestamp(&rpc_client, &tx_digest).await
+                }
+                _ => None,
+            };
+            let event_data = match timestamp {
+                Some(timestamp) => SuiEventData::new(message, timestamp),
+                None => {
+                    log::warn!("no timestamp for tx {} (using current time)", tx_digest);
+                    SuiEventData::new_now(message)
+                }
+            };
+
+            let mut globals_write_guard = globals.events_data.write().await;
+            let globals = &mut *globals_write_guard;
+            globals
+                .workdirs
+                .get_mut(workdir_idx)
+                .add_event(EventsChannel::Watch, event_data);
+        });
+    }
+
+    async fn get_tx_timestamp(rpc_client: &WorkdirRpcClient, tx_digest: &str) -> Option<String> {
+        for attempt in 1..=TX_TIMESTAMP_ATTEMPTS {
+            let timestamp = rpc_client.transaction_timestamp_ms(tx_digest).await;
+            if timestamp.is_some() || attempt == TX_TIMESTAMP_ATTEMPTS {
+                return timestamp;
+            }
+            tokio::time::sleep(TX_TIMESTAMP_RETRY_DELAY).await;
+        }
+        None
+    }
+
+    async fn event_loop(&mut self, subsys: &SubsystemHandle, mut ws_events_rx: AdminControllerRx) {
+        // Take ownership of the event_rx channel as long this thread is running.
+        let event_rx = self.params.event_rx.clone();
+        let mut event_rx = event_rx.lock().await;
+
+        while !subsys.is_shutdown_requested() {
+            tokio::select! {
+                // Wait for a suibase internal message (not a websocket message!).
+                msg = event_rx.recv() => {
+                    if let Some(msg) = msg {
+                        // No message expected yet (placeholder for config changes).
+                        log::error!("Unexpected event_id {:?}", msg.event_id);
+                    } else {
+                        // Channel closed or shutdown requested.
+                        return;
+                    }
+                }
+                // Events forwarded by the websocket worker.
+                msg = ws_events_rx.recv() => {
+                    if let Some(msg) = msg {
+                        if msg.event_id == admin_controller::EVENT_NOTIF_SUI_WATCH {
+                            self.process_sui_watch(msg);
+                        } else {
+                            self.process_sui_event(msg).await;
+                        }
+                    } else {
+                        // Channel closed or shutdown requested.
+                        return;
+                    }
+                }
+            }
         }
     }
 }
@@ -6,18 +6,26 @@
 //
 // Interaction with workers is with messaging (channels).
 //
-// Workers can also serve the purpose of serializing operations. Example, there is one
+// Workers can also serve the purpose of bounding operations. Example, there is one
 // instance of shell_worker per workdir running. This allows to :
-//   - No more than one shell command executed at the time per workdir.
+//   - No more than SHELL_WORKER_MAX_CONCURRENT_JOBS shell commands executed at the time per workdir.
 //   - Shell command on different workdir can be executed concurrently.
 //
 // flatten everything under "workers" module.
+pub(crate) use self::events_decoder::*;
 pub(crate) use self::events_writer_worker::*;
 pub(crate) use self::request_worker::*;
+pub(crate) use self::shell_job::*;
 pub(crate) use self::shell_worker::*;
+pub(crate) use self::status_builder::*;
+pub(crate) use self::status_worker::*;
 pub(crate) use self::websocket_worker::*;
 
+mod events_decoder;
 mod events_writer_worker;
 mod request_worker;
+mod shell_job;
 mod shell_worker;
+mod status_builder;
+mod status_worker;
 mod websocket_worker;
@@ -1,4 +1,6 @@
 use crate::network_monitor::NetmonMsg;
+use crate::proxy_server::ProxyServer;
+use crate::shared_types::GlobalsProxyMT;
 
 use anyhow::Result;
 use tokio_graceful_shutdown::{FutureExt, SubsystemHandle};
@@ -13,24 +15,37 @@ const SERVER_CHECK_REQUEST_BODY: &str =
 
 pub struct RequestWorker {
     netmon_rx: NetMonRx,
+    globals: GlobalsProxyMT,
     client: reqwest::Client,
 }
 
 impl RequestWorker {
-    pub fn new(netmon_rx: NetMonRx) -> Self {
+    pub fn new(netmon_rx: NetMonRx, globals: GlobalsProxyMT) -> Self {
         Self {
             netmon_rx,
-            client: reqwest::Client::new(),
+            globals,
+            client: ProxyServer::local_client(),
         }
     }
 
     async fn do_request(&mut self, msg: NetmonMsg) {
         let server_idx = msg.server_idx().to_string();
 
-        let uri = format!("http://0.0.0.0:{}", msg.para16()[0]);
-        let _ = self
-            .client
-            .request(Method::POST, uri)
+        // The URL depends on the bind address and TLS of the port (see ProxyListenConfig).
+        let (uri, auth_header) = {
+            let globals_read_guard = self.globals.read().await;
+            let globals = &*globals_read_guard;
+            match globals.input_ports.get(msg.port_idx()) {
+                Some(input_port) => (input_port.local_url(), input_port.local_auth_header()),
+                None => return,
+            }
+        };
+
+        let mut req_builder = self.client.request(Method::POST, uri);
+        if let Some(auth_header) = auth_header {
+            req_builder = req_builder.header(header::AUTHORIZATION, auth_header);
+        }
+        let _ = req_builder
             .header(header::CONTENT_TYPE, "application/json")
             .header(header::USER_AGENT, "curl/7.68.0")
             .header(header::ACCEPT, "*/*")
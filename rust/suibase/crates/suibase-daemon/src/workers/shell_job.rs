@@ -0,0 +1,330 @@
+// Execute one shell command (bash -c) on behalf of the ShellWorker.
+//
+// The command runs as an async tokio process with:
+//   - stdout and stderr captured separately, line by line.
+//   - A timeout, after which the process is killed.
+//   - A cancellation channel, which also kills the process.
+//
+// The command runs in its own process group, so that a kill also reaches
+// the processes it started (e.g. cargo during "update").
+//
+// The output lines are streamed into a job of globals.jobs (so getJob can
+// report progress).
+use std::process::Stdio;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+use tokio::sync::oneshot;
+
+use crate::shared_types::Globals;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellJobEnd {
+    // Exit code is None when terminated by a signal.
+    Exited(Option<i32>),
+    TimedOut,
+    Cancelled,
+    SpawnFailed,
+}
+
+pub struct ShellJob {
+    globals: Globals,
+    job_id: u64,
+}
+
+// Kill (SIGKILL) the process group of a command on timeout, cancellation, or
+// when the job is dropped before the command exits.
+struct ProcessGroupKiller {
+    pgid: Option<u32>,
+}
+
+impl ProcessGroupKiller {
+    fn kill(&mut self) {
+        if let Some(pgid) = self.pgid.take() {
+            let _ = std::process::Command::new("kill")
+                .arg("-KILL")
+                .arg("--")
+                .arg(format!("-{}", pgid))
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+
+    // The command exited on its own. What it intentionally left running in
+    // the background (e.g. "start") is not killed.
+    fn disarm(&mut self) {
+        self.pgid = None;
+    }
+}
+
+impl Drop for ProcessGroupKiller {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+impl ShellJob {
+    pub fn new(globals: Globals, job_id: u64) -> Self {
+        Self { globals, job_id }
+    }
+
+    // The output beyond JOB_OUTPUT_MAX_LINES is replaced by a truncation marker.
+    async fn add_line(&mut self, is_stderr: bool, line: String) {
+        let mut globals_write_guard = self.globals.jobs.write().await;
+        let globals = &mut *globals_write_guard;
+        if let Some(job) = globals.get_mut(self.job_id) {
+            job.add_output_line(is_stderr, line);
+        }
+    }
+
+    pub async fn run(
+        &mut self,
+        cmd: &str,
+        timeout: tokio::time::Duration,
+        mut cancel_rx: oneshot::Receiver<()>,
+    ) -> ShellJobEnd {
+        let child = tokio::process::Command::new("bash")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn();
+
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                self.add_line(true, format!("Error: {}", e)).await;
+                return ShellJobEnd::SpawnFailed;
+            }
+        };
+        // The process group id is the pid of bash (see process_group).
+        let mut killer = ProcessGroupKiller { pgid: child.id() };
+
+        let mut stdout = child.stdout.take().map(|s| BufReader::new(s).lines());
+        let mut stderr = child.stderr.take().map(|s| BufReader::new(s).lines());
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+        // A dropped sender means no cancellation will ever be requested.
+        let mut cancel_open = true;
+
+        loop {
+            tokio::select! {
+                line = next_line(&mut stdout), if stdout.is_some() => {
+                    match line {
+                        Some(line) => self.add_line(false, line).await,
+                        None => stdout = None,
+                    }
+                }
+                line = next_line(&mut stderr), if stderr.is_some() => {
+                    match line {
+                        Some(line) => self.add_line(true, line).await,
+                        None => stderr = None,
+                    }
+                }
+                status = child.wait(), if stdout.is_none() && stderr.is_none() => {
+                    killer.disarm();
+                    return match status {
+                        Ok(status) => ShellJobEnd::Exited(status.code()),
+                        Err(e) => {
+                            self.add_line(true, format!("Error: {}", e)).await;
+                            ShellJobEnd::Exited(None)
+                        }
+                    };
+                }
+                _ = &mut deadline => {
+                    killer.kill();
+                    let _ = child.wait().await;
+                    self.add_line(true, format!("Error: timeout after {} secs", timeout.as_secs())).await;
+                    return ShellJobEnd::TimedOut;
+                }
+                cancel = &mut cancel_rx, if cancel_open => {
+                    if cancel.is_ok() {
+                        killer.kill();
+                        let _ = child.wait().await;
+                        return ShellJobEnd::Cancelled;
+                    }
+                    cancel_open = false;
+                }
+            }
+        }
+    }
+}
+
+// Returns None on end of stream (or read error).
+async fn next_line<R: AsyncRead + Unpin>(
+    lines: &mut Option<Lines<BufReader<R>>>,
+) -> Option<String> {
+    match lines {
+        Some(lines) => lines.next_line().await.ok().flatten(),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+    use crate::shared_types::{Job, JOB_OUTPUT_MAX_LINES, JOB_OUTPUT_TRUNCATED};
+    use tokio::time::{Duration, Instant};
+
+    async fn add_job(globals: &Globals) -> u64 {
+        let mut globals_write_guard = globals.jobs.write().await;
+        let globals = &mut *globals_write_guard;
+        globals.add_job(0, "localnet".to_string(), "test".to_string(), 60)
+    }
+
+    async fn get_job(globals: &Globals, job_id: u64) -> Job {
+        let globals_read_guard = globals.jobs.read().await;
+        let globals = &*globals_read_guard;
+        globals.get(job_id).unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_run_output() {
+        let globals = Globals::new();
+        let job_id = add_job(&globals).await;
+        let mut shell_job = ShellJob::new(globals.clone(), job_id);
+        let (_cancel_tx, cancel_rx) = oneshot::channel();
+        let end = shell_job
+            .run(
+                "echo out1; echo err1 >&2; echo out2; exit 3",
+                Duration::from_secs(10),
+                cancel_rx,
+            )
+            .await;
+        assert_eq!(end, ShellJobEnd::Exited(Some(3)));
+
+        let job = get_job(&globals, job_id).await;
+        let lines = |is_stderr: bool| -> Vec<String> {
+            job.output
+                .iter()
+                .filter(|output| output.is_stderr == is_stderr)
+                .map(|output| output.line.clone())
+                .collect()
+        };
+        assert_eq!(lines(false), vec!["out1", "out2"]);
+        assert_eq!(lines(true), vec!["err1"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_output_truncated() {
+        let globals = Globals::new();
+        let job_id = add_job(&globals).await;
+        let mut shell_job = ShellJob::new(globals.clone(), job_id);
+        let (_cancel_tx, cancel_rx) = oneshot::channel();
+        let cmd = format!("seq 1 {}", JOB_OUTPUT_MAX_LINES + 10);
+        let end = shell_job
+            .run(&cmd, Duration::from_secs(30), cancel_rx)
+            .await;
+        assert_eq!(end, ShellJobEnd::Exited(Some(0)));
+
+        let job = get_job(&globals, job_id).await;
+        assert_eq!(job.output.len(), JOB_OUTPUT_MAX_LINES + 1);
+        assert_eq!(
+            job.output[JOB_OUTPUT_MAX_LINES - 1].line,
+            JOB_OUTPUT_MAX_LINES.to_string()
+        );
+        assert_eq!(job.output[JOB_OUTPUT_MAX_LINES].line, JOB_OUTPUT_TRUNCATED);
+    }
+
+    // The command writes the pid of a background "sleep" in pid_file.
+    fn orphan_cmd(pid_file: &std::path::Path) -> String {
+        format!("sleep 30 & echo $! > {}; wait", pid_file.display())
+    }
+
+    // Real time wait (not tokio time) for the pid written by the command.
+    async fn read_pid_file(pid_file: std::path::PathBuf) -> u32 {
+        tokio::task::spawn_blocking(move || {
+            for _ in 0..100 {
+                if let Ok(pid) = std::fs::read_to_string(&pid_file) {
+                    if let Ok(pid) = pid.trim().parse() {
+                        return pid;
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            panic!("no pid in {:?}", pid_file);
+        })
+        .await
+        .unwrap()
+    }
+
+    // True when the process exits (or is a zombie) within a few (real) seconds.
+    async fn is_process_gone(pid: u32) -> bool {
+        tokio::task::spawn_blocking(move || {
+            for _ in 0..100 {
+                match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+                    Err(_) => return true,
+                    // The state follows the command name (in parenthesis).
+                    Ok(stat) => {
+                        let state = stat.rsplit(") ").next().unwrap_or_default();
+                        if state.starts_with('Z') {
+                            return true;
+                        }
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            false
+        })
+        .await
+        .unwrap()
+    }
+
+    // The timing tests use the virtual time of tokio. Waiting for the pid of
+    // the background process (spawn_blocking) holds the time until it is started.
+    #[tokio::test(start_paused = true)]
+    async fn test_run_timeout() {
+        let globals = Globals::new();
+        let job_id = add_job(&globals).await;
+        let temp_dir = TempDir::new("shell-job");
+        let pid_file = temp_dir.path().join("pid");
+        let cmd = orphan_cmd(&pid_file);
+        let run = {
+            let globals = globals.clone();
+            tokio::spawn(async move {
+                let mut shell_job = ShellJob::new(globals, job_id);
+                // A dropped sender is not a cancellation.
+                let (_, cancel_rx) = oneshot::channel();
+                let start = Instant::now();
+                let end = shell_job.run(&cmd, Duration::from_secs(1), cancel_rx).await;
+                (end, start.elapsed())
+            })
+        };
+        let pid = read_pid_file(pid_file.clone()).await;
+
+        let (end, elapsed) = run.await.unwrap();
+        assert_eq!(end, ShellJobEnd::TimedOut);
+        assert_eq!(elapsed, Duration::from_secs(1));
+        assert!(is_process_gone(pid).await, "orphan process {}", pid);
+
+        let job = get_job(&globals, job_id).await;
+        let last = job.output.last().unwrap();
+        assert!(last.is_stderr);
+        assert!(last.line.contains("timeout"), "{}", last.line);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_cancel() {
+        let globals = Globals::new();
+        let job_id = add_job(&globals).await;
+        let temp_dir = TempDir::new("shell-job");
+        let pid_file = temp_dir.path().join("pid");
+        let cmd = orphan_cmd(&pid_file);
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let run = tokio::spawn(async move {
+            let mut shell_job = ShellJob::new(globals, job_id);
+            shell_job
+                .run(&cmd, Duration::from_secs(60), cancel_rx)
+                .await
+        });
+        let pid = read_pid_file(pid_file.clone()).await;
+
+        let _ = cancel_tx.send(());
+        assert_eq!(run.await.unwrap(), ShellJobEnd::Cancelled);
+        assert!(is_process_gone(pid).await, "orphan process {}", pid);
+    }
+}
@@ -1,18 +1,48 @@
-use std::process::Command;
+// Execute shell commands for one workdir.
+//
+// Requests (from the AdminController):
+//   - EVENT_WORKDIR_JOB: a job of globals.jobs (e.g. "localnet start"), its
+//     state and output are updated in globals while it progresses.
+//   - EVENT_WORKDIR_JOB_CANCEL: kill the process of a job (or skip it if queued).
+//
+// Each command runs in its own tokio task, so a long command does not block
+// the event loop (EVENT_WORKDIR_JOB_CANCEL is processed while jobs run).
+//
+// At most SHELL_WORKER_MAX_CONCURRENT_JOBS commands execute at the same time
+// for a workdir, the others wait (queued) for a free slot.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::{oneshot, Semaphore};
+use tokio::task::JoinHandle;
 
 use crate::{
-    admin_controller::{self, AdminControllerMsg, AdminControllerRx},
+    admin_controller::{
+        AdminControllerMsg, AdminControllerRx, EVENT_WORKDIR_JOB, EVENT_WORKDIR_JOB_CANCEL,
+    },
     basic_types::WorkdirIdx,
-    shared_types::Globals,
+    shared_types::{Globals, GlobalsEventsDataST, JobState},
 };
 
+use super::{ShellJob, ShellJobEnd};
+
 use anyhow::Result;
 use tokio_graceful_shutdown::{FutureExt, SubsystemHandle};
 
+pub const SHELL_WORKER_MAX_CONCURRENT_JOBS: usize = 2;
+
+struct RunningJob {
+    handle: JoinHandle<()>,
+    cancel_tx: Option<oneshot::Sender<()>>,
+}
+
 pub struct ShellWorker {
-    _globals: Globals,
+    globals: Globals,
     event_rx: AdminControllerRx,
     workdir_idx: Option<WorkdirIdx>,
+    job_slots: Arc<Semaphore>,
+    // Key is the job_id.
+    running_jobs: HashMap<u64, RunningJob>,
 }
 
 impl ShellWorker {
@@ -22,80 +52,168 @@ impl ShellWorker {
         workdir_idx: Option<WorkdirIdx>,
     ) -> Self {
         Self {
-            _globals: globals,
+            globals,
             event_rx,
             workdir_idx,
+            job_slots: Arc::new(Semaphore::new(SHELL_WORKER_MAX_CONCURRENT_JOBS)),
+            running_jobs: HashMap::new(),
         }
     }
 
-    async fn do_exec(&mut self, msg: AdminControllerMsg) {
-        // No error return here. Once the execution is completed, the output
-        // of the response is returned to requester with a one shot message.
-        //
-        // If the response starts with "Error:", then an error was detected.
-        //
-        // Some effects are also possible on globals, particularly
-        // for sharing large results.
-        //
-        log::info!(
-            "do_exec() msg {:?} for workdir_idx={:?}",
-            msg,
-            self.workdir_idx
+    fn parse_job_id(msg: &AdminControllerMsg) -> Option<u64> {
+        let job_id = msg.data_string.as_ref().and_then(|s| s.parse::<u64>().ok());
+        if job_id.is_none() {
+            log::error!(
+                "event_id {} invalid job_id {:?}",
+                msg.event_id,
+                msg.data_string
+            );
+        }
+        job_id
+    }
+
+    fn do_job(&mut self, msg: AdminControllerMsg) {
+        // Execute a workdir command (e.g. "localnet start") in the background.
+        let job_id = match Self::parse_job_id(&msg) {
+            Some(job_id) => job_id,
+            None => return,
+        };
+
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        let globals = self.globals.clone();
+        let job_slots = self.job_slots.clone();
+        let handle = tokio::spawn(async move {
+            Self::job_task(globals, job_slots, job_id, cancel_rx).await;
+        });
+        self.running_jobs.insert(
+            job_id,
+            RunningJob {
+                handle,
+                cancel_tx: Some(cancel_tx),
+            },
         );
+    }
 
-        let resp = if msg.event_id != admin_controller::EVENT_SHELL_EXEC {
-            log::error!("Unexpected event_id {:?}", msg.event_id);
-            format!("Error: Unexpected event_id {:?}", msg.event_id)
-        } else if let Some(cmd) = &msg.data_string {
-            // Execute the command as if it was a bash script.
-            let output = Command::new("bash").arg("-c").arg(cmd).output();
-
-            match output {
-                Ok(output) => {
-                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-                    let resp = format!("{}{}", stdout, stderr);
-                    if output.status.success() && stderr.is_empty() {
-                        resp
+    fn do_job_cancel(&mut self, msg: AdminControllerMsg) {
+        let job_id = match Self::parse_job_id(&msg) {
+            Some(job_id) => job_id,
+            None => return,
+        };
+
+        // The job task updates the job state once the process is killed.
+        match self
+            .running_jobs
+            .get_mut(&job_id)
+            .and_then(|running_job| running_job.cancel_tx.take())
+        {
+            Some(cancel_tx) => {
+                let _ = cancel_tx.send(());
+            }
+            None => log::info!("job {} not running (cancel ignored)", job_id),
+        }
+    }
+
+    async fn job_task(
+        globals: Globals,
+        job_slots: Arc<Semaphore>,
+        job_id: u64,
+        mut cancel_rx: oneshot::Receiver<()>,
+    ) {
+        // Wait for a free slot (the job can be cancelled while queued).
+        let permit = tokio::select! {
+            permit = job_slots.acquire_owned() => permit.ok(),
+            _ = &mut cancel_rx => None,
+        };
+
+        let (workdir_idx, cmd, timeout_secs) = {
+            let mut globals_write_guard = globals.jobs.write().await;
+            let globals = &mut *globals_write_guard;
+            match globals.get_mut(job_id) {
+                Some(job) => {
+                    if permit.is_none() {
+                        job.state = JobState::Cancelled;
                     } else {
-                        format!("Error: {}", resp)
+                        job.state = JobState::Running;
                     }
+                    (job.workdir_idx, job.command_line(), job.timeout_secs)
                 }
-                Err(e) => {
-                    let error_msg = format!(
-                        "Error: do_exec({:?}, {:?}) error 1: {}",
-                        msg.workdir_idx, cmd, e
-                    );
-                    log::error!("{}", error_msg);
-                    error_msg
+                None => {
+                    log::error!("job_task() job_id {} not found", job_id);
+                    return;
                 }
             }
-        } else {
-            let error_msg = format!(
-                "Error: do_exec({:?}, None) error 2: No command to execute",
-                msg.workdir_idx
-            );
-            log::error!("{}", error_msg);
-            error_msg
         };
 
-        if let Some(resp_channel) = msg.resp_channel {
-            if let Err(e) = resp_channel.send(resp) {
-                let error_msg = format!(
-                    "Error: do_exec({:?}, {:?}) error 3: {}",
-                    msg.workdir_idx, msg.data_string, e
-                );
-                log::error!("{}", error_msg);
+        if permit.is_none() {
+            GlobalsEventsDataST::add_console_event(
+                &globals,
+                workdir_idx,
+                format!("{} cancelled (job {})", cmd, job_id),
+            )
+            .await;
+            return;
+        }
+
+        GlobalsEventsDataST::add_console_event(
+            &globals,
+            workdir_idx,
+            format!("{} started (job {})", cmd, job_id),
+        )
+        .await;
+
+        let mut shell_job = ShellJob::new(globals.clone(), job_id);
+        let timeout = tokio::time::Duration::from_secs(timeout_secs);
+        let end = shell_job.run(&cmd, timeout, cancel_rx).await;
+        drop(permit);
+
+        let (state, exit_code) = match end {
+            ShellJobEnd::Exited(Some(0)) => (JobState::Succeeded, Some(0)),
+            ShellJobEnd::Exited(exit_code) => (JobState::Failed, exit_code),
+            ShellJobEnd::TimedOut => (JobState::TimedOut, None),
+            ShellJobEnd::Cancelled => (JobState::Cancelled, None),
+            ShellJobEnd::SpawnFailed => (JobState::Failed, None),
+        };
+
+        {
+            let mut globals_write_guard = globals.jobs.write().await;
+            let globals = &mut *globals_write_guard;
+            if let Some(job) = globals.get_mut(job_id) {
+                job.state = state;
+                job.exit_code = exit_code;
             }
         }
+
+        GlobalsEventsDataST::add_console_event(
+            &globals,
+            workdir_idx,
+            format!("{} {} (job {})", cmd, state, job_id),
+        )
+        .await;
+    }
+
+    fn prune_completed(&mut self) {
+        self.running_jobs
+            .retain(|_, running_job| !running_job.handle.is_finished());
+    }
+
+    fn abort_all(&mut self) {
+        // Processes are killed when their task is dropped (kill_on_drop).
+        for (_, running_job) in self.running_jobs.drain() {
+            running_job.handle.abort();
+        }
     }
 
     async fn event_loop(&mut self, subsys: &SubsystemHandle) {
         while !subsys.is_shutdown_requested() {
             // Wait for a message.
             if let Some(msg) = self.event_rx.recv().await {
+                self.prune_completed();
                 // Process the message.
-                self.do_exec(msg).await;
+                match msg.event_id {
+                    EVENT_WORKDIR_JOB => self.do_job(msg),
+                    EVENT_WORKDIR_JOB_CANCEL => self.do_job_cancel(msg),
+                    _ => log::error!("Unexpected event_id {:?}", msg.event_id),
+                }
             } else {
                 // Channel closed or shutdown requested.
                 return;
@@ -106,7 +224,9 @@ impl ShellWorker {
     pub async fn run(mut self, subsys: SubsystemHandle) -> Result<()> {
         log::info!("started");
 
-        match self.event_loop(&subsys).cancel_on_shutdown(&subsys).await {
+        let result = self.event_loop(&subsys).cancel_on_shutdown(&subsys).await;
+        self.abort_all();
+        match result {
             Ok(()) => {
                 log::info!("shutting down - normal exit (2)");
                 Ok(())
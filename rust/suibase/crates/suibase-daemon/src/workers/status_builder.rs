@@ -0,0 +1,583 @@
+// Helper of the StatusWorker (not a thread on its own).
+//
+// Compute the StatusResponse of a workdir natively (no "<workdir> status"
+// shell call):
+//   - sui and sui-faucet process liveness from the PID files written by the
+//     scripts in the workdir '.state' (with a process scan fallback).
+//   - proxy server and multi-link RPC state from the InputPort.
+//   - faucet reachability with an HTTP request.
+//   - network version with an RPC request through the proxy server.
+//   - client version from the workdir sui binary (cached until modified).
+//   - asui selection from the '~/suibase/workdirs/active' symlink.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use axum::http::header;
+
+use crate::api::{StatusResponse, StatusService};
+use crate::basic_types::WorkdirIdx;
+use crate::proxy_server::ProxyServer;
+use crate::shared_types::Globals;
+
+// PID files maintained by the scripts in the workdir '.state' directory.
+const SUI_PROCESS_PID_FILE: &str = "sui_process_pid";
+const SUI_FAUCET_PROCESS_PID_FILE: &str = "sui_faucet_process_pid";
+
+const FAUCET_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+const NETWORK_VERSION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+// The network version rarely changes (and rpc.discover is a large response).
+const NETWORK_VERSION_MAX_AGE: tokio::time::Duration = tokio::time::Duration::from_secs(60);
+
+// Everything needed from the globals (copied while holding the read locks).
+#[derive(Debug, Default)]
+struct StatusInputs {
+    workdir_name: String,
+    workdir_path: PathBuf,
+    state_path: PathBuf,
+    workdirs_path: PathBuf,
+    user_request: Option<String>,
+
+    is_local_network: bool,
+    sui_faucet_enabled: bool,
+    sui_faucet_host_ip: String,
+    sui_faucet_port: u16,
+
+    proxy_enabled: bool,
+    proxy_server_running: bool,
+    proxy_port_number: u16,
+    proxy_url: String,                  // See InputPort::local_url
+    proxy_auth_header: Option<String>,  // Set when proxy_auth_token is configured.
+    proxy_server_error: Option<String>, // e.g. port already in use.
+    multi_link_status: String,
+    multi_link_info: String,
+}
+
+pub struct StatusBuilder {
+    globals: Globals,
+    client: reqwest::Client,
+    proxy_client: reqwest::Client, // For the requests to the proxy server.
+    // "sui --version" output per binary path, along with the modification
+    // time of the binary when it was queried.
+    client_versions: HashMap<PathBuf, (std::time::SystemTime, String)>,
+    // Last network version retrieved per proxy port.
+    network_versions: HashMap<u16, (tokio::time::Instant, String)>,
+}
+
+impl StatusBuilder {
+    pub fn new(globals: Globals) -> Self {
+        Self {
+            globals,
+            client: reqwest::Client::new(),
+            proxy_client: ProxyServer::local_client(),
+            client_versions: HashMap::new(),
+            network_versions: HashMap::new(),
+        }
+    }
+
+    // Fill all the data fields of resp (header, display and debug are left untouched).
+    //
+    // Returns debug information about how the status was computed.
+    pub async fn build(&mut self, workdir_idx: WorkdirIdx, resp: &mut StatusResponse) -> String {
+        let inputs = self.get_inputs(workdir_idx).await;
+
+        let is_local = inputs.is_local_network;
+        let is_stopped = inputs.user_request.as_deref() == Some("stop");
+
+        resp.status = None;
+        resp.status_info = None;
+        resp.services = None;
+        resp.client_version = self.get_client_version(&inputs.workdir_path).await;
+        resp.asui_selection = Self::get_asui_selection(&inputs.workdirs_path);
+        resp.network_version = None;
+
+        if inputs.user_request.is_none() {
+            // The workdir was never initialized by the scripts.
+            resp.status = Some("DOWN".to_string());
+            resp.status_info = Some(format!("{} not initialized", inputs.workdir_name));
+            return format!("{:?}", inputs);
+        }
+
+        let mut services: Vec<StatusService> = Vec::new();
+        let mut is_degraded = false;
+
+        if is_local {
+            let sui_pid = Self::find_process_pid(
+                &inputs.state_path,
+                SUI_PROCESS_PID_FILE,
+                &inputs.workdir_path.join("sui-repo/target/debug/sui"),
+                Some("start"),
+            )
+            .await;
+            let faucet_pid = if inputs.sui_faucet_enabled || is_stopped {
+                Self::find_process_pid(
+                    &inputs.state_path,
+                    SUI_FAUCET_PROCESS_PID_FILE,
+                    &inputs.workdir_path.join("sui-repo/target/debug/sui-faucet"),
+                    None,
+                )
+                .await
+            } else {
+                None
+            };
+
+            if is_stopped {
+                // Report only the processes "abnormally" still running.
+                for (label, pid) in [
+                    ("localnet process", sui_pid),
+                    ("faucet process", faucet_pid),
+                ] {
+                    if let Some(pid) = pid {
+                        let mut service = StatusService::new(label.to_string());
+                        service.status = Some("STILL RUNNING".to_string());
+                        service.pid = Some(pid);
+                        services.push(service);
+                    }
+                }
+            } else {
+                let mut service = StatusService::new("localnet process".to_string());
+                service.status = Some(Self::process_status_word(true, sui_pid));
+                service.pid = sui_pid;
+                services.push(service);
+
+                let mut service = StatusService::new("faucet process".to_string());
+                service.status = Some(Self::process_status_word(
+                    inputs.sui_faucet_enabled,
+                    faucet_pid,
+                ));
+                service.pid = faucet_pid;
+                if inputs.sui_faucet_enabled {
+                    let faucet_url = format!(
+                        "http://{}:{}",
+                        inputs.sui_faucet_host_ip, inputs.sui_faucet_port
+                    );
+                    if faucet_pid.is_none() {
+                        is_degraded = true;
+                    } else if !self.is_faucet_reachable(&faucet_url).await {
+                        service.status = Some("DEGRADED".to_string());
+                        service.status_info = Some("not responding".to_string());
+                        is_degraded = true;
+                    }
+                    service.help_info = Some(faucet_url);
+                }
+                services.push(service);
+
+                if sui_pid.is_none() {
+                    resp.status = Some("DOWN".to_string());
+                    resp.status_info = Some("localnet process not running".to_string());
+                }
+            }
+        }
+
+        if !is_stopped {
+            let mut service = StatusService::new("proxy server".to_string());
+            let proxy_pid = if inputs.proxy_server_running {
+                Some(std::process::id() as u64)
+            } else {
+                None
+            };
+            service.status = Some(Self::process_status_word(inputs.proxy_enabled, proxy_pid));
+            service.pid = proxy_pid;
+            if inputs.proxy_enabled {
+                service.help_info = Some(inputs.proxy_url.clone());
+                if proxy_pid.is_none() {
+                    is_degraded = true;
+                }
+                if let Some(error) = &inputs.proxy_server_error {
+                    service.status_info = Some(error.clone());
+                    is_degraded = true;
+                }
+            }
+            services.push(service);
+        }
+
+        if inputs.proxy_enabled {
+            let mut service = StatusService::new("multi-link RPC".to_string());
+            service.status = Some(inputs.multi_link_status.clone());
+            if !inputs.multi_link_info.is_empty() {
+                service.status_info = Some(inputs.multi_link_info.clone());
+            }
+            services.push(service);
+
+            if inputs.proxy_server_running && !is_stopped {
+                resp.network_version = self.get_network_version(&inputs).await;
+            }
+        }
+
+        if resp.status.is_none() {
+            resp.status = Some(if is_stopped {
+                "STOPPED".to_string()
+            } else if is_local && is_degraded {
+                "DEGRADED".to_string()
+            } else {
+                "OK".to_string()
+            });
+        }
+
+        resp.services = Some(services);
+
+        format!("{:?}", inputs)
+    }
+
+    // Human-friendly version of the status (similar to "<workdir> status").
+    pub fn display(workdir: &str, resp: &StatusResponse) -> String {
+        let mut out = String::new();
+        let status = resp.status.as_deref().unwrap_or("DOWN");
+        if workdir != "localnet" && status == "STOPPED" {
+            out.push_str(&format!("{} services {}\n", workdir, status));
+        } else {
+            out.push_str(&format!("{} {}\n", workdir, status));
+        }
+        if let Some(status_info) = &resp.status_info {
+            out.push_str(&format!("  ( {} )\n", status_info));
+        }
+
+        if let Some(services) = &resp.services {
+            if !services.is_empty() {
+                out.push_str("---\n");
+            }
+            for service in services {
+                out.push_str(&format!(
+                    "{:<17}: {}",
+                    service.label,
+                    service.status.as_deref().unwrap_or("")
+                ));
+                if let Some(pid) = service.pid {
+                    out.push_str(&format!(" ( pid {} )", pid));
+                }
+                if let Some(status_info) = &service.status_info {
+                    out.push_str(&format!(" ( {} )", status_info));
+                }
+                if let Some(help_info) = &service.help_info {
+                    out.push_str(&format!(" {}", help_info));
+                }
+                out.push('\n');
+            }
+        }
+
+        out.push_str("---\n");
+        if let Some(client_version) = &resp.client_version {
+            out.push_str(&format!("client version: {}\n", client_version));
+        }
+        if let Some(network_version) = &resp.network_version {
+            out.push_str(&format!("network version: {}\n", network_version));
+        }
+        out.push_str(&format!(
+            "asui selection: [ {} ]\n",
+            resp.asui_selection.as_deref().unwrap_or("<none>")
+        ));
+        out
+    }
+
+    async fn get_inputs(&self, workdir_idx: WorkdirIdx) -> StatusInputs {
+        let mut inputs = StatusInputs::default();
+        {
+            let globals_read_guard = self.globals.workdirs.read().await;
+            let globals = &*globals_read_guard;
+            inputs.workdirs_path = globals.path().to_path_buf();
+            if let Some(workdir) = globals.workdirs.get(workdir_idx) {
+                inputs.workdir_name = workdir.name().to_string();
+                inputs.workdir_path = workdir.path().to_path_buf();
+                inputs.state_path = workdir.state_path().to_path_buf();
+                // Read the user_request directly (instead of waiting for
+                // the watcher to notify the AdminController).
+                inputs.user_request = std::fs::read_to_string(workdir.suibase_state_file())
+                    .ok()
+                    .map(|s| s.trim().to_string());
+            }
+        }
+        {
+            let globals_read_guard = self.globals.config.read().await;
+            let globals = &*globals_read_guard;
+            if let Some(wd_config) = globals.workdirs.get_if_some(workdir_idx) {
+                inputs.is_local_network = wd_config.is_local_network;
+                inputs.sui_faucet_enabled = wd_config.sui_faucet_enabled;
+                inputs.sui_faucet_host_ip = wd_config.sui_faucet_host_ip.clone();
+                inputs.sui_faucet_port = wd_config.sui_faucet_port;
+            }
+        }
+        {
+            let globals_read_guard = self.globals.proxy.read().await;
+            let globals = &*globals_read_guard;
+            if let Some(input_port) = globals.find_input_port_by_workdir_idx(workdir_idx) {
+                inputs.proxy_enabled = input_port.is_proxy_enabled();
+                inputs.proxy_server_running = input_port.is_proxy_server_running();
+                inputs.proxy_port_number = input_port.port_number();
+                inputs.proxy_url = input_port.local_url();
+                inputs.proxy_auth_header = input_port.local_auth_header();
+                inputs.proxy_server_error = input_port.proxy_server_error().cloned();
+                (inputs.multi_link_status, inputs.multi_link_info) =
+                    input_port.get_multi_link_status();
+            }
+        }
+        inputs
+    }
+
+    fn process_status_word(is_supported: bool, pid: Option<u64>) -> String {
+        if !is_supported {
+            "DISABLED".to_string()
+        } else if pid.is_some() {
+            "OK".to_string()
+        } else {
+            "NOT RUNNING".to_string()
+        }
+    }
+
+    // Returns the PID of a running process.
+    //
+    // The PID file is trusted only if that process is still alive. Otherwise,
+    // fallback on scanning the processes for a matching command line (the
+    // scripts may not have yet written the PID file).
+    async fn find_process_pid(
+        state_path: &Path,
+        pid_file: &str,
+        binary_path: &Path,
+        args: Option<&str>,
+    ) -> Option<u64> {
+        let binary_path = binary_path.to_string_lossy();
+        let pattern = match args {
+            Some(args) => format!("{} {}", binary_path, args),
+            None => binary_path.to_string(),
+        };
+
+        if let Ok(contents) = tokio::fs::read_to_string(state_path.join(pid_file)).await {
+            if let Ok(pid) = contents.trim().parse::<u64>() {
+                if let Some(cmdline) = Self::process_cmdline(pid).await {
+                    if cmdline.contains(&pattern) {
+                        return Some(pid);
+                    }
+                }
+            }
+        }
+
+        Self::scan_processes(&pattern).await
+    }
+
+    // Command line of a running process, None if not running.
+    async fn process_cmdline(pid: u64) -> Option<String> {
+        if cfg!(target_os = "linux") {
+            let cmdline = tokio::fs::read(format!("/proc/{}/cmdline", pid))
+                .await
+                .ok()?;
+            Some(Self::cmdline_to_string(&cmdline))
+        } else {
+            let output = tokio::process::Command::new("ps")
+                .args(["-p", &pid.to_string(), "-o", "command="])
+                .output()
+                .await
+                .ok()?;
+            let cmdline = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if cmdline.is_empty() {
+                None
+            } else {
+                Some(cmdline)
+            }
+        }
+    }
+
+    async fn scan_processes(pattern: &str) -> Option<u64> {
+        if cfg!(target_os = "linux") {
+            let mut entries = tokio::fs::read_dir("/proc").await.ok()?;
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let pid = match entry.file_name().to_string_lossy().parse::<u64>() {
+                    Ok(pid) => pid,
+                    Err(_) => continue,
+                };
+                if let Ok(cmdline) = tokio::fs::read(entry.path().join("cmdline")).await {
+                    if Self::cmdline_to_string(&cmdline).contains(pattern) {
+                        return Some(pid);
+                    }
+                }
+            }
+            None
+        } else {
+            let output = tokio::process::Command::new("ps")
+                .args(["x", "-o", "pid=,command="])
+                .output()
+                .await
+                .ok()?;
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .find(|line| line.contains(pattern))
+                .and_then(|line| line.split_whitespace().next())
+                .and_then(|pid| pid.parse::<u64>().ok())
+        }
+    }
+
+    // /proc cmdline arguments are separated by null characters.
+    fn cmdline_to_string(cmdline: &[u8]) -> String {
+        String::from_utf8_lossy(cmdline)
+            .split('\0')
+            .filter(|arg| !arg.is_empty())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    async fn is_faucet_reachable(&self, faucet_url: &str) -> bool {
+        // The faucet answers its health check on "/".
+        let resp = self
+            .client
+            .get(faucet_url)
+            .timeout(FAUCET_CHECK_TIMEOUT)
+            .send()
+            .await;
+        matches!(resp, Ok(resp) if resp.status().is_success())
+    }
+
+    async fn get_network_version(&mut self, inputs: &StatusInputs) -> Option<String> {
+        let proxy_port_number = inputs.proxy_port_number;
+        if let Some((last_update, version)) = self.network_versions.get(&proxy_port_number) {
+            if last_update.elapsed() < NETWORK_VERSION_MAX_AGE {
+                return Some(version.clone());
+            }
+        }
+
+        let mut req_builder = self.proxy_client.post(&inputs.proxy_url);
+        if let Some(auth_header) = &inputs.proxy_auth_header {
+            req_builder = req_builder.header(header::AUTHORIZATION, auth_header);
+        }
+        let resp = req_builder
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(r#"{"jsonrpc":"2.0","method":"rpc.discover","id":1,"params":[]}"#)
+            .timeout(NETWORK_VERSION_TIMEOUT)
+            .send()
+            .await
+            .ok()?;
+        let json = resp.json::<serde_json::Value>().await.ok()?;
+        let version = json["result"]["info"]["version"].as_str()?.to_string();
+        self.network_versions.insert(
+            proxy_port_number,
+            (tokio::time::Instant::now(), version.clone()),
+        );
+        Some(version)
+    }
+
+    async fn get_client_version(&mut self, workdir_path: &Path) -> Option<String> {
+        let binary_path = workdir_path.join("sui-repo/target/debug/sui");
+        let modified = tokio::fs::metadata(&binary_path)
+            .await
+            .ok()?
+            .modified()
+            .ok()?;
+        if let Some((cached_modified, version)) = self.client_versions.get(&binary_path) {
+            if *cached_modified == modified {
+                return Some(version.clone());
+            }
+        }
+
+        let output = tokio::process::Command::new(&binary_path)
+            .arg("--version")
+            .output()
+            .await
+            .ok()?;
+        let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || version.is_empty() {
+            return None;
+        }
+        self.client_versions
+            .insert(binary_path, (modified, version.clone()));
+        Some(version)
+    }
+
+    fn get_asui_selection(workdirs_path: &Path) -> Option<String> {
+        let target = std::fs::read_link(workdirs_path.join("active")).ok()?;
+        target
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(label: &str, status: &str, pid: Option<u64>) -> StatusService {
+        let mut service = StatusService::new(label.to_string());
+        service.status = Some(status.to_string());
+        service.pid = pid;
+        service
+    }
+
+    #[test]
+    fn test_display() {
+        let mut resp = StatusResponse::new();
+        resp.status = Some("DEGRADED".to_string());
+        let mut faucet = service("faucet process", "DEGRADED", Some(1235));
+        faucet.status_info = Some("not responding".to_string());
+        faucet.help_info = Some("http://0.0.0.0:9123".to_string());
+        let mut proxy = service("proxy server", "OK", Some(99));
+        proxy.help_info = Some("http://localhost:44340".to_string());
+        resp.services = Some(vec![
+            service("localnet process", "OK", Some(1234)),
+            faucet,
+            proxy,
+            service("multi-link RPC", "OK", None),
+        ]);
+        resp.client_version = Some("sui 1.14.0".to_string());
+        resp.network_version = Some("1.14.0".to_string());
+        resp.asui_selection = Some("localnet".to_string());
+        assert_eq!(
+            StatusBuilder::display("localnet", &resp),
+            "localnet DEGRADED\n\
+             ---\n\
+             localnet process : OK ( pid 1234 )\n\
+             faucet process   : DEGRADED ( pid 1235 ) ( not responding ) http://0.0.0.0:9123\n\
+             proxy server     : OK ( pid 99 ) http://localhost:44340\n\
+             multi-link RPC   : OK\n\
+             ---\n\
+             client version: sui 1.14.0\n\
+             network version: 1.14.0\n\
+             asui selection: [ localnet ]\n"
+        );
+
+        // Only localnet has processes of its own ("services" for the others).
+        let mut resp = StatusResponse::new();
+        resp.status = Some("STOPPED".to_string());
+        resp.services = Some(Vec::new());
+        assert_eq!(
+            StatusBuilder::display("testnet", &resp),
+            "testnet services STOPPED\n---\nasui selection: [ <none> ]\n"
+        );
+        assert_eq!(
+            StatusBuilder::display("localnet", &resp),
+            "localnet STOPPED\n---\nasui selection: [ <none> ]\n"
+        );
+
+        let mut resp = StatusResponse::new();
+        resp.status_info = Some("devnet not initialized".to_string());
+        assert_eq!(
+            StatusBuilder::display("devnet", &resp),
+            "devnet DOWN\n  ( devnet not initialized )\n---\nasui selection: [ <none> ]\n"
+        );
+    }
+
+    #[test]
+    fn test_process_status_word() {
+        assert_eq!(StatusBuilder::process_status_word(true, Some(1)), "OK");
+        assert_eq!(
+            StatusBuilder::process_status_word(true, None),
+            "NOT RUNNING"
+        );
+        assert_eq!(StatusBuilder::process_status_word(false, None), "DISABLED");
+        assert_eq!(
+            StatusBuilder::process_status_word(false, Some(1)),
+            "DISABLED"
+        );
+    }
+
+    #[test]
+    fn test_cmdline_to_string() {
+        assert_eq!(
+            StatusBuilder::cmdline_to_string(
+                b"/w/sui-repo/target/debug/sui\0start\0--network.config\0/w/config\0"
+            ),
+            "/w/sui-repo/target/debug/sui start --network.config /w/config"
+        );
+        assert_eq!(
+            StatusBuilder::cmdline_to_string(b"sui-faucet"),
+            "sui-faucet"
+        );
+        assert_eq!(StatusBuilder::cmdline_to_string(b"a\0\0b\0"), "a b");
+        assert_eq!(StatusBuilder::cmdline_to_string(b""), "");
+    }
+}
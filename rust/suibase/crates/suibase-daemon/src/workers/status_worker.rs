@@ -0,0 +1,237 @@
+// Keep the status of one workdir (globals.status) up to date.
+//
+// One instance per workdir, started by the AdminController. A refresh is
+// requested periodically by the ClockTrigger (through the AdminController)
+// and on demand by the GeneralApi.
+//
+// Refresh for a workdir are serialized by this worker event loop. Because each
+// workdir has its own worker, a slow refresh (e.g. faucet not responding)
+// never delays the status of another workdir.
+//
+// The Versioned UUID of the status is bumped only when the data changes, so
+// polling clients are told "no change" without any refresh on their behalf.
+use crate::{
+    admin_controller::{AdminControllerMsg, AdminControllerRx, EVENT_STATUS_REFRESH},
+    api::{StatusResponse, Versioned},
+    basic_types::WorkdirIdx,
+    shared_types::Globals,
+};
+
+use super::StatusBuilder;
+
+use anyhow::Result;
+use tokio_graceful_shutdown::{FutureExt, SubsystemHandle};
+
+// Refresh requests within that delay of the previous refresh are answered
+// with the result of the previous one (debounce bursts of API requests).
+const STATUS_REFRESH_DEBOUNCE: tokio::time::Duration = tokio::time::Duration::from_millis(50);
+
+pub struct StatusWorker {
+    globals: Globals,
+    event_rx: AdminControllerRx,
+    workdir_idx: WorkdirIdx,
+    builder: StatusBuilder,
+    last_refresh: Option<tokio::time::Instant>,
+    last_debug_out: String,
+}
+
+impl StatusWorker {
+    pub fn new(globals: Globals, event_rx: AdminControllerRx, workdir_idx: WorkdirIdx) -> Self {
+        Self {
+            globals: globals.clone(),
+            event_rx,
+            workdir_idx,
+            builder: StatusBuilder::new(globals),
+            last_refresh: None,
+            last_debug_out: String::new(),
+        }
+    }
+
+    async fn do_refresh(&mut self, msg: AdminControllerMsg) {
+        if msg.event_id != EVENT_STATUS_REFRESH {
+            log::error!("Unexpected event_id {:?}", msg.event_id);
+            return;
+        }
+
+        let is_debounced = self
+            .last_refresh
+            .is_some_and(|last_refresh| last_refresh.elapsed() < STATUS_REFRESH_DEBOUNCE);
+
+        if !is_debounced {
+            self.refresh().await;
+        }
+
+        // The response is the debug information of the refresh.
+        if let Some(resp_channel) = msg.resp_channel {
+            let _ = resp_channel.send(self.last_debug_out.clone());
+        }
+    }
+
+    async fn refresh(&mut self) {
+        let workdir_name = {
+            let globals_read_guard = self.globals.workdirs.read().await;
+            let globals = &*globals_read_guard;
+            match globals.workdirs.get(self.workdir_idx) {
+                Some(workdir) => workdir.name().to_string(),
+                None => {
+                    log::error!("workdir_idx {} not found", self.workdir_idx);
+                    return;
+                }
+            }
+        };
+
+        let mut resp = StatusResponse::new();
+        resp.header.method = "getStatus".to_string();
+        resp.header.key = Some(workdir_name);
+        self.last_debug_out = self.builder.build(self.workdir_idx, &mut resp).await;
+
+        {
+            let mut globals_write_guard = self.globals.status.write().await;
+            let globals = &mut *globals_write_guard;
+            let globals = globals.workdirs.get_mut(self.workdir_idx);
+
+            // Update globals only if different (to not bump the Uuid).
+            if let Some(globals_ui) = &mut globals.ui {
+                if resp != *globals_ui.get_data() {
+                    globals_ui.set(&resp);
+                }
+            } else {
+                globals.ui = Some(Versioned::new(resp));
+            }
+            globals.last_ui_update = tokio::time::Instant::now();
+        }
+
+        self.last_refresh = Some(tokio::time::Instant::now());
+    }
+
+    async fn event_loop(&mut self, subsys: &SubsystemHandle) {
+        while !subsys.is_shutdown_requested() {
+            // Wait for a message.
+            if let Some(msg) = self.event_rx.recv().await {
+                // Process the message.
+                self.do_refresh(msg).await;
+            } else {
+                // Channel closed or shutdown requested.
+                return;
+            }
+        }
+    }
+
+    pub async fn run(mut self, subsys: SubsystemHandle) -> Result<()> {
+        log::info!("started for workdir_idx={}", self.workdir_idx);
+
+        match self.event_loop(&subsys).cancel_on_shutdown(&subsys).await {
+            Ok(()) => {
+                log::info!("shutting down - normal exit (2)");
+                Ok(())
+            }
+            Err(_cancelled_by_shutdown) => {
+                log::info!("shutting down - normal exit (1)");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_types::TempDir;
+    use crate::shared_types::{GlobalsWorkdirsST, UuidST};
+    use tokio::time::Duration;
+
+    // Worker of "testnet" with the workdirs in a temporary suibase home.
+    //
+    // The status of a remote network depends only on its user_request (the
+    // proxy server is not configured), so no request is done by the builder.
+    async fn test_worker(temp_dir: &TempDir) -> (StatusWorker, std::path::PathBuf) {
+        let globals = Globals::new();
+        *globals.workdirs.write().await =
+            GlobalsWorkdirsST::with_suibase_home(temp_dir.path().to_path_buf());
+        let workdir_idx =
+            GlobalsWorkdirsST::find_workdir_idx_by_name(&globals, &"testnet".to_string())
+                .await
+                .unwrap();
+        let state_file = temp_dir.path().join("workdirs/testnet/.state/user_request");
+        std::fs::create_dir_all(state_file.parent().unwrap()).unwrap();
+        let (_event_tx, event_rx) = tokio::sync::mpsc::channel(1);
+        (
+            StatusWorker::new(globals, event_rx, workdir_idx),
+            state_file,
+        )
+    }
+
+    async fn get_status(worker: &StatusWorker) -> (Option<String>, UuidST) {
+        let globals_read_guard = worker.globals.status.read().await;
+        let globals = &*globals_read_guard;
+        let ui = globals
+            .workdirs
+            .get_if_some(worker.workdir_idx)
+            .and_then(|status| status.ui.as_ref())
+            .unwrap();
+        (ui.get_data().status.clone(), ui.get_uuid().clone())
+    }
+
+    async fn request_refresh(worker: &mut StatusWorker) -> String {
+        let (resp_tx, resp_rx) = tokio::sync::oneshot::channel();
+        let mut msg = AdminControllerMsg::new();
+        msg.event_id = EVENT_STATUS_REFRESH;
+        msg.workdir_idx = Some(worker.workdir_idx);
+        msg.resp_channel = Some(resp_tx);
+        worker.do_refresh(msg).await;
+        resp_rx.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_refresh_uuid() {
+        let temp_dir = TempDir::new("status");
+        let (mut worker, state_file) = test_worker(&temp_dir).await;
+
+        worker.refresh().await;
+        let (status, uuid) = get_status(&worker).await;
+        assert_eq!(status.as_deref(), Some("DOWN"));
+
+        // Same status, same version.
+        worker.refresh().await;
+        assert_eq!(get_status(&worker).await, (status, uuid.clone()));
+
+        std::fs::write(&state_file, "start\n").unwrap();
+        worker.refresh().await;
+        let (status, new_uuid) = get_status(&worker).await;
+        assert_eq!(status.as_deref(), Some("OK"));
+        assert_ne!(new_uuid, uuid);
+
+        worker.refresh().await;
+        assert_eq!(get_status(&worker).await, (status, new_uuid.clone()));
+
+        std::fs::write(&state_file, "stop\n").unwrap();
+        worker.refresh().await;
+        let (status, uuid) = get_status(&worker).await;
+        assert_eq!(status.as_deref(), Some("STOPPED"));
+        assert_ne!(uuid, new_uuid);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_refresh_debounce() {
+        let temp_dir = TempDir::new("status");
+        let (mut worker, state_file) = test_worker(&temp_dir).await;
+
+        let debug_out = request_refresh(&mut worker).await;
+        assert!(debug_out.contains("testnet"), "{}", debug_out);
+        let (status, uuid) = get_status(&worker).await;
+        assert_eq!(status.as_deref(), Some("DOWN"));
+
+        // Requests within the debounce delay get the previous result.
+        std::fs::write(&state_file, "start\n").unwrap();
+        assert_eq!(request_refresh(&mut worker).await, debug_out);
+        tokio::time::advance(STATUS_REFRESH_DEBOUNCE - Duration::from_millis(1)).await;
+        assert_eq!(request_refresh(&mut worker).await, debug_out);
+        assert_eq!(get_status(&worker).await, (status, uuid.clone()));
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert_ne!(request_refresh(&mut worker).await, debug_out);
+        let (status, new_uuid) = get_status(&worker).await;
+        assert_eq!(status.as_deref(), Some("OK"));
+        assert_ne!(new_uuid, uuid);
+    }
+}
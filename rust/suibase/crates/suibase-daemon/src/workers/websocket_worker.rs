@@ -8,12 +8,18 @@
 //
 // The thread is auto-restart in case of panic.
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
-    admin_controller::AdminControllerRx,
+    admin_controller::{
+        AdminControllerMsg, AdminControllerRx, AdminControllerTx, EVENT_NOTIF_SUI_EVENT,
+        EVENT_NOTIF_SUI_WATCH,
+    },
     basic_types::{AutoThread, Runnable, WorkdirIdx},
-    shared_types::Globals,
+    shared_types::{Globals, GlobalsEventsDataST},
 };
 
 use anyhow::Result;
@@ -29,8 +35,9 @@ use tokio_tungstenite::{connect_async, tungstenite::Message, WebSocketStream};
 
 #[derive(Clone)]
 pub struct WebSocketWorkerParams {
-    _globals: Globals,
+    globals: Globals,
     event_rx: Arc<Mutex<AdminControllerRx>>,
+    writer_tx: AdminControllerTx,
     workdir_idx: Option<WorkdirIdx>,
 }
 
@@ -38,11 +45,13 @@ impl WebSocketWorkerParams {
     pub fn new(
         globals: Globals,
         event_rx: AdminControllerRx,
+        writer_tx: AdminControllerTx,
         workdir_idx: Option<WorkdirIdx>,
     ) -> Self {
         Self {
-            _globals: globals,
+            globals,
             event_rx: Arc::new(Mutex::new(event_rx)),
+            writer_tx,
             workdir_idx,
         }
     }
@@ -67,15 +76,23 @@ impl WebSocketWorker {
 struct WebSocketWorkerThread {
     name: String,
     params: WebSocketWorkerParams,
-    // Set of unique packaged id (string).
-    subscribed_ids: HashSet<String>,
+    // Active subscriptions. The key is "package:<id>" or a WatchTarget key
+    // (e.g. "address:<id>"). The value is the subscription id assigned by
+    // the server (None until the subscription request is confirmed).
+    subscriptions: HashMap<String, Option<u64>>,
+
+    // Subscription requests waiting for a response (request id -> key).
+    pending_requests: HashMap<u64, String>,
+
+    // Reverse lookup for the notifications (subscription id -> key).
+    subscription_keys: HashMap<u64, String>,
 
     // Active websocket connection.
     write: Option<SplitSink<WebSocketStream<TcpStream>, Message>>,
     read: Option<SplitStream<WebSocketStream<TcpStream>>>,
 
-    // Last known valid sequence number processed.
-    last_seq_number: u64,
+    // Last JSON-RPC id used for a request on the websocket.
+    last_request_id: u64,
 }
 
 #[async_trait]
@@ -84,10 +101,12 @@ impl Runnable<WebSocketWorkerParams> for WebSocketWorkerThread {
         Self {
             name,
             params,
-            subscribed_ids: HashSet::new(),
+            subscriptions: HashMap::new(),
+            pending_requests: HashMap::new(),
+            subscription_keys: HashMap::new(),
             write: None,
             read: None,
-            last_seq_number: 0,
+            last_request_id: 0,
         }
     }
 
@@ -108,13 +127,234 @@ impl Runnable<WebSocketWorkerParams> for WebSocketWorkerThread {
 }
 
 impl WebSocketWorkerThread {
-    fn subscribe_request_format(&mut self, id: u64, package: &str) -> String {
+    fn subscribe_request_format(id: u64, key: &str) -> Option<String> {
+        let (method, filter) = match key.split_once(':')? {
+            ("package", package_id) => ("suix_subscribeEvent", ("Package", package_id)),
+            ("address", address) => ("suix_subscribeTransaction", ("FromAddress", address)),
+            ("object", object_id) => ("suix_subscribeTransaction", ("ChangedObject", object_id)),
+            _ => return None,
+        };
+        Some(format!(
+            r#"{{"jsonrpc":"2.0","method":"{}","id":{},"params":[{{"{}":"{}"}}]}}"#,
+            method, id, filter.0, filter.1
+        ))
+    }
+
+    fn unsubscribe_request_format(id: u64, key: &str, subscription_id: u64) -> String {
+        let method = if key.starts_with("package:") {
+            "suix_unsubscribeEvent"
+        } else {
+            "suix_unsubscribeTransaction"
+        };
         format!(
-            r#"{{"jsonrpc":"2.0","method":"suix_subscribeEvent","id":{},"params":[{{"Package":"{}"}}]}}"#,
-            id, package
+            r#"{{"jsonrpc":"2.0","method":"{}","id":{},"params":[{}]}}"#,
+            method, id, subscription_id
         )
     }
 
+    async fn send_request(&mut self, request: String) -> bool {
+        if let Some(ref mut write) = self.write {
+            if let Err(e) = write.send(Message::Text(request)).await {
+                log::error!("write.send error: {:?}", e);
+                return false;
+            }
+            return true;
+        }
+        false
+    }
+
+    async fn console_event(&self, msg: String) {
+        if let Some(workdir_idx) = self.params.workdir_idx {
+            GlobalsEventsDataST::add_console_event(&self.params.globals, workdir_idx, msg).await;
+        }
+    }
+
+    // Get the subscription keys of everything that should be monitored for this workdir:
+    //   - The latest (and original) publication of every Move.toml ("package:<id>").
+    //   - The addresses/objects watched by the user (WatchTarget keys).
+    async fn get_desired_subscriptions(&self) -> HashSet<String> {
+        let mut keys = HashSet::new();
+        if let Some(workdir_idx) = self.params.workdir_idx {
+            {
+                let globals_read_guard = self.params.globals.packages_config.read().await;
+                let globals = &*globals_read_guard;
+                if let Some(globals) = globals.workdirs.get_if_some(workdir_idx) {
+                    if let Some(ui) = &globals.ui {
+                        if let Some(move_configs) = &ui.get_data().move_configs {
+                            for move_config in move_configs.values() {
+                                if let Some(package) = &move_config.latest_package {
+                                    keys.insert(format!("package:{}", package.package_id));
+                                }
+                                // Calls to an upgraded package may still be made with
+                                // its original id.
+                                if let Some(original_package_id) = &move_config.original_package_id
+                                {
+                                    keys.insert(format!("package:{}", original_package_id));
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            {
+                let globals_read_guard = self.params.globals.events_data.read().await;
+                let globals = &*globals_read_guard;
+                if let Some(globals) = globals.workdirs.get_if_some(workdir_idx) {
+                    for target in globals.watches.iter() {
+                        keys.insert(target.key());
+                    }
+                }
+            }
+        }
+        keys
+    }
+
+    // Subscribe to anything newly published/watched, and unsubscribe
+    // from what is no longer watched.
+    async fn update_subscriptions(&mut self) {
+        let desired = self.get_desired_subscriptions().await;
+
+        for key in desired.iter() {
+            if self.subscriptions.contains_key(key) {
+                continue;
+            }
+            self.last_request_id += 1;
+            let request_id = self.last_request_id;
+            let request = match Self::subscribe_request_format(request_id, key) {
+                Some(request) => request,
+                None => {
+                    log::error!("invalid subscription key {}", key);
+                    continue;
+                }
+            };
+            if self.send_request(request).await {
+                // Will retry on next update on failure.
+                log::info!("subscribing to {}", key);
+                self.subscriptions.insert(key.clone(), None);
+                self.pending_requests.insert(request_id, key.clone());
+            }
+        }
+
+        let removed: Vec<(String, Option<u64>)> = self
+            .subscriptions
+            .iter()
+            .filter(|(key, _)| !desired.contains(*key))
+            .map(|(key, subscription_id)| (key.clone(), *subscription_id))
+            .collect();
+        for (key, subscription_id) in removed {
+            // A subscription still pending is unsubscribed once confirmed.
+            if let Some(subscription_id) = subscription_id {
+                self.unsubscribe(&key, subscription_id).await;
+                self.subscriptions.remove(&key);
+            }
+        }
+    }
+
+    async fn unsubscribe(&mut self, key: &str, subscription_id: u64) {
+        self.last_request_id += 1;
+        let request = Self::unsubscribe_request_format(self.last_request_id, key, subscription_id);
+        if self.send_request(request).await {
+            log::info!("unsubscribing from {}", key);
+        }
+        self.subscription_keys.remove(&subscription_id);
+    }
+
+    // Response to one of our subscription request.
+    async fn process_ws_response(&mut self, request_id: u64, json: &serde_json::Value) {
+        let key = match self.pending_requests.remove(&request_id) {
+            Some(key) => key,
+            None => return, // Not a subscription request (e.g. unsubscribe).
+        };
+
+        let subscription_id = match json["result"].as_u64() {
+            Some(subscription_id) => subscription_id,
+            None => {
+                log::error!("subscription to {} failed: {}", key, json);
+                // Will retry on next update.
+                self.subscriptions.remove(&key);
+                return;
+            }
+        };
+
+        match self.subscriptions.get_mut(&key) {
+            Some(value) => {
+                *value = Some(subscription_id);
+                self.subscription_keys.insert(subscription_id, key);
+            }
+            None => {
+                // No longer desired while the request was pending.
+                self.unsubscribe(&key, subscription_id).await;
+            }
+        }
+    }
+
+    async fn process_ws_msg(&mut self, msg: Message) {
+        // Forward the Sui notifications to the parent. The responses to the
+        // subscription requests are tracked here. Everything else is only logged.
+        let text = match msg {
+            Message::Text(text) => text,
+            Message::Ping(data) => {
+                if let Some(ref mut write) = self.write {
+                    let _ = write.send(Message::Pong(data)).await;
+                }
+                return;
+            }
+            _ => {
+                log::debug!("Received a websocket message: {:?}", msg);
+                return;
+            }
+        };
+
+        let json = match serde_json::from_str::<serde_json::Value>(&text) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("Invalid JSON in websocket message: {} ({})", text, e);
+                return;
+            }
+        };
+
+        if let Some(request_id) = json["id"].as_u64() {
+            self.process_ws_response(request_id, &json).await;
+            return;
+        }
+
+        let result = &json["params"]["result"];
+        if !result.is_object() {
+            log::info!("Received a websocket message: {}", text);
+            return;
+        }
+
+        let mut msg = AdminControllerMsg::new();
+        msg.workdir_idx = self.params.workdir_idx;
+        match json["method"].as_str() {
+            Some("suix_subscribeEvent") => {
+                msg.event_id = EVENT_NOTIF_SUI_EVENT;
+                msg.data_string = Some(result.to_string());
+            }
+            Some("suix_subscribeTransaction") => {
+                // Identify which watch matched the transaction.
+                let subscription_id = json["params"]["subscription"].as_u64().unwrap_or_default();
+                let key = match self.subscription_keys.get(&subscription_id) {
+                    Some(key) => key.clone(),
+                    None => {
+                        log::warn!("Notification for unknown subscription: {}", text);
+                        return;
+                    }
+                };
+                msg.event_id = EVENT_NOTIF_SUI_WATCH;
+                msg.data_string =
+                    Some(serde_json::json!({ "watch": key, "effects": result }).to_string());
+            }
+            _ => {
+                log::info!("Received a websocket message: {}", text);
+                return;
+            }
+        }
+        if let Err(e) = self.params.writer_tx.send(msg).await {
+            log::error!("writer_tx.send error: {}", e);
+        }
+    }
+
     async fn event_loop(&mut self, subsys: &SubsystemHandle) {
         // TODO - auto-reconnect logic.
 
@@ -127,6 +367,8 @@ impl WebSocketWorkerThread {
                 let (write, read) = ws_stream.split();
                 self.write = Some(write);
                 self.read = Some(read);
+                self.console_event(format!("websocket connected to {}", socket_url))
+                    .await;
             }
             Err(e) => {
                 log::error!("connect_async error: {:?}", e);
@@ -136,21 +378,14 @@ impl WebSocketWorkerThread {
             }
         }
 
-        let msg = Message::Text(self.subscribe_request_format(
-            1,
-            "9402306344411345bd37243cdbb8743fa7cfcf7c33933feae74f7b2621b57d60",
-        ));
+        self.update_subscriptions().await;
 
-        if let Some(ref mut write) = self.write {
-            if let Err(e) = write.send(msg).await {
-                log::error!("write.send error: {:?}", e);
-            } else {
-                log::info!("write.send success");
-            }
-        }
+        // Periodically check for newly published packages and watches changes.
+        let mut audit_interval = tokio::time::interval(tokio::time::Duration::from_secs(10));
 
         // Take ownership of the event_rx channel as long this thread is running.
-        let mut event_rx = self.params.event_rx.lock().await;
+        let event_rx = self.params.event_rx.clone();
+        let mut event_rx = event_rx.lock().await;
 
         while !subsys.is_shutdown_requested() {
             let ws_stream_future = futures::FutureExt::fuse(self.read.as_mut().unwrap().next());
@@ -158,13 +393,22 @@ impl WebSocketWorkerThread {
 
             tokio::select! {
                 msg = ws_stream_future => {
-                    if let Some(msg) = msg {
-                        let msg = msg.unwrap();
-                        log::info!("Received a websocket message: {:?}", msg);
-                    } else {
-                        // Shutdown requested.
-                        log::info!("Received a None websocket message");
-                        return;
+                    match msg {
+                        Some(Ok(msg)) => {
+                            self.process_ws_msg(msg).await;
+                        }
+                        Some(Err(e)) => {
+                            // Exit to reconnect (this thread is auto-restarted).
+                            log::error!("websocket error: {:?}", e);
+                            self.console_event(format!("websocket disconnected ({})", e))
+                                .await;
+                            return;
+                        }
+                        None => {
+                            // Shutdown requested.
+                            log::info!("Received a None websocket message");
+                            return;
+                        }
                     }
                 }
                 msg = event_rx_future => {
@@ -178,6 +422,9 @@ impl WebSocketWorkerThread {
                         return;
                     }
                 }
+                _ = audit_interval.tick() => {
+                    self.update_subscriptions().await;
+                }
             }
         }
     }
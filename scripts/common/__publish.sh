@@ -125,8 +125,13 @@ publish_all() {
   echo "Also written in [~/suibase/workdirs/$WORKDIR_NAME/published-data/$MOVE_TOML_PACKAGE_NAME/most-recent/package-id.json]"
   echo "Publication Successful"
 
+  # The transaction digest is the first "digest" of the publish output
+  # (the other ones are object digests).
+  update_JSON_VALUE "digest" "$(cat "$INSTALL_DIR/publish-output.json")"
+  local _TX_DIGEST="$JSON_VALUE"
+
   # Push new information to suibase-daemon.
-  do_suibase_daemon_post_publish "$MOVE_TOML_DIR" "$MOVE_TOML_PACKAGE_NAME" "$PACKAGE_UUID" "$PACKAGE_TIMESTAMP" "$_ID_PACKAGE"
+  do_suibase_daemon_post_publish "$MOVE_TOML_DIR" "$MOVE_TOML_PACKAGE_NAME" "$PACKAGE_UUID" "$PACKAGE_TIMESTAMP" "$_ID_PACKAGE" "$_TX_DIGEST"
 }
 export -f publish_all
 
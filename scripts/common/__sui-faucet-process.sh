@@ -146,8 +146,13 @@ update_SUI_FAUCET_PROCESS_PID_var() {
   _PID=$(get_process_pid "$SUI_BIN_DIR/sui-faucet")
   if [ "$_PID" = "NULL" ]; then
     unset SUI_FAUCET_PROCESS_PID
+    del_key_value "$WORKDIR" "sui_faucet_process_pid"
   else
     export SUI_FAUCET_PROCESS_PID="$_PID"
+    # Allow the suibase-daemon to check liveness without a 'ps' scan.
+    if [ "$(get_key_value "$WORKDIR" "sui_faucet_process_pid")" != "$_PID" ]; then
+      set_key_value "$WORKDIR" "sui_faucet_process_pid" "$_PID"
+    fi
   fi
 }
 export -f update_SUI_FAUCET_PROCESS_PID_var
@@ -376,6 +376,36 @@ start_suibase_daemon_as_needed() {
 }
 export -f start_suibase_daemon_as_needed
 
+# Header sent with every call to the suibase-daemon API (required by the
+# mutating methods, e.g. postPublish).
+#
+# The token file is created by the daemon (readable only by the user).
+export SUIBASE_DAEMON_AUTH_HEADER=""
+update_SUIBASE_DAEMON_AUTH_HEADER() {
+  local _TOKEN=""
+  local _TOKEN_FILE="$SUIBASE_DIR/workdirs/common/.state/api_token"
+  if [ -f "$_TOKEN_FILE" ]; then
+    _TOKEN=$(cat "$_TOKEN_FILE")
+  fi
+  SUIBASE_DAEMON_AUTH_HEADER="Authorization: Bearer $_TOKEN"
+}
+export -f update_SUIBASE_DAEMON_AUTH_HEADER
+
+# Call the suibase-daemon API with curl (the params are additional curl options).
+#
+# Uses the daemon Unix domain socket when it exists (does not depend on the
+# TCP port and access is restricted by the file permissions), otherwise
+# fallback to TCP.
+suibase_daemon_api_curl() {
+  local _SOCKET="$SUIBASE_DIR/workdirs/common/.state/api.sock"
+  if [ -S "$_SOCKET" ]; then
+    curl --unix-socket "$_SOCKET" "$@" "http://localhost/"
+  else
+    curl "$@" "http://${CFG_proxy_host_ip:?}:${CFG_suibase_api_port_number:?}"
+  fi
+}
+export -f suibase_daemon_api_curl
+
 # The response is written in global JSON_RESP
 get_suibase_daemon_status() {
   local _DISP=$1 # one of "data", "debug" or "display"
@@ -384,7 +414,8 @@ get_suibase_daemon_status() {
 
   local _JSON_PARAMS="{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"getLinks\",\"params\":{\"workdir\":\"$WORKDIR_NAME\",\"$_DISP\":true}}"
 
-  JSON_RESP=$(curl -x "" -s --location -X POST "http://${CFG_proxy_host_ip:?}:${CFG_suibase_api_port_number:?}" -H "$_HEADERS" -d "$_JSON_PARAMS")
+  update_SUIBASE_DAEMON_AUTH_HEADER
+  JSON_RESP=$(suibase_daemon_api_curl -x "" -s --location -X POST -H "$_HEADERS" -H "$SUIBASE_DAEMON_AUTH_HEADER" -d "$_JSON_PARAMS")
 }
 export -f get_suibase_daemon_status
 
@@ -511,7 +542,8 @@ notify_suibase_daemon_fs_change() {
 
   local _JSON_PARAMS="{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"fsChange\",\"params\":{\"path\":\"$WORKDIR_NAME\"}}"
 
-  curl --max-time 5 -x "" -s --location -X POST "http://${CFG_proxy_host_ip:?}:${CFG_suibase_api_port_number:?}" -H "$_HEADERS" -d "$_JSON_PARAMS" >/dev/null 2>&1 &
+  update_SUIBASE_DAEMON_AUTH_HEADER
+  suibase_daemon_api_curl --max-time 5 -x "" -s --location -X POST -H "$_HEADERS" -H "$SUIBASE_DAEMON_AUTH_HEADER" -d "$_JSON_PARAMS" >/dev/null 2>&1 &
 }
 export -f notify_suibase_daemon_fs_change
 
@@ -526,6 +558,7 @@ do_suibase_daemon_post_publish() {
   local _UUID=$3
   local _TIMESTAMP=$4
   local _ID=$5
+  local _TX_DIGEST=$6 # Optional
 
   if ! is_suibase_daemon_running; then
     # TODO attempt to restart daemon here before failing.
@@ -550,9 +583,15 @@ do_suibase_daemon_post_publish() {
 
   local _HEADERS="Content-Type: application/json"
 
-  local _JSON_PARAMS="{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"postPublish\",\"params\":{\"workdir\":\"$WORKDIR_NAME\", \"move_toml_path\": \"$_TOML_PATH\", \"package_name\": \"$_NAME\", \"package_uuid\": \"$_UUID\", \"package_timestamp\": \"$_TIMESTAMP\", \"package_id\": \"$_ID\"}}"
+  local _TX_DIGEST_PARAM=""
+  if [ -n "$_TX_DIGEST" ]; then
+    _TX_DIGEST_PARAM=", \"tx_digest\": \"$_TX_DIGEST\""
+  fi
+
+  local _JSON_PARAMS="{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"postPublish\",\"params\":{\"workdir\":\"$WORKDIR_NAME\", \"move_toml_path\": \"$_TOML_PATH\", \"package_name\": \"$_NAME\", \"package_uuid\": \"$_UUID\", \"package_timestamp\": \"$_TIMESTAMP\", \"package_id\": \"$_ID\"$_TX_DIGEST_PARAM}}"
 
-  _RESULT=$(curl --max-time 5 -x "" -s --location -X POST "http://${CFG_proxy_host_ip:?}:${CFG_suibase_api_port_number:?}" -H "$_HEADERS" -d "$_JSON_PARAMS")
+  update_SUIBASE_DAEMON_AUTH_HEADER
+  _RESULT=$(suibase_daemon_api_curl --max-time 5 -x "" -s --location -X POST -H "$_HEADERS" -H "$SUIBASE_DAEMON_AUTH_HEADER" -d "$_JSON_PARAMS")
   update_JSON_VALUE "result" "$_RESULT"
   if [ "$JSON_VALUE" != "true" ]; then
     echo "post-publish error: [$_RESULT] [$JSON_VALUE]"
@@ -593,7 +632,8 @@ do_suibase_daemon_pre_publish() {
 
   local _JSON_PARAMS="{\"id\":1,\"jsonrpc\":\"2.0\",\"method\":\"prePublish\",\"params\":{\"workdir\":\"$WORKDIR_NAME\", \"move_toml_path\": \"$_TOML_PATH\", \"package_name\": \"$_NAME\"}}"
 
-  _RESULT=$(curl --max-time 5 -x "" -s --location -X POST "http://${CFG_proxy_host_ip:?}:${CFG_suibase_api_port_number:?}" -H "$_HEADERS" -d "$_JSON_PARAMS")
+  update_SUIBASE_DAEMON_AUTH_HEADER
+  _RESULT=$(suibase_daemon_api_curl --max-time 5 -x "" -s --location -X POST -H "$_HEADERS" -H "$SUIBASE_DAEMON_AUTH_HEADER" -d "$_JSON_PARAMS")
   update_JSON_VALUE "result" "$_RESULT"
   if [ "$JSON_VALUE" != "true" ]; then
     error_exit "do_suibase_daemon_pre_publish failed: [$_RESULT] [$JSON_VALUE]"
@@ -12,6 +12,19 @@
 import * as vscode from "vscode";
 import * as cp from "child_process";
 import * as WebSocket from "ws";
+import * as fs from "fs";
+import * as os from "os";
+import * as path from "path";
+
+// Token required by the suibase-daemon for WebSocket connections (created by the daemon).
+const readApiToken = (): string => {
+  const tokenFile = path.join(os.homedir(), "suibase", "workdirs", "common", ".state", "api_token");
+  try {
+    return fs.readFileSync(tokenFile, "utf8").trim();
+  } catch (err) {
+    return "";
+  }
+};
 
 const execShell = (cmd: string) =>
   new Promise<string>((resolve, reject) => {
@@ -33,7 +46,9 @@ export class SuibaseExec {
 
   private constructor() {
     // Should be called only by SuibaseExec.activate()
-    this.ws = new WebSocket("ws://localhost:44399");
+    this.ws = new WebSocket("ws://localhost:44399", {
+      headers: { Authorization: `Bearer ${readApiToken()}` },
+    });
 
     this.ws.on("open", () => {
       console.log("WebSocket connection opened");